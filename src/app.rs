@@ -27,6 +27,8 @@ use crate::view::keyboard::{Layout};
 use crate::view::dictionary::Dictionary as DictionaryApp;
 use crate::view::calculator::Calculator;
 use crate::view::sketch::Sketch;
+use crate::view::annotations::Annotations;
+use crate::view::bookmarks::Bookmarks;
 use crate::input::{DeviceEvent, PowerSource, ButtonCode, ButtonStatus, VAL_RELEASE, VAL_PRESS, InputFilterCommand};
 use crate::input::{raw_events, device_events, usb_events, display_rotate_event, button_scheme_event};
 use crate::gesture::{GestureEvent, gesture_events};
@@ -385,7 +387,10 @@ pub fn run() -> Result<(), Error> {
         }
     }
     let (raw_sender, raw_receiver) = raw_events(paths, filter_input_cmd_receiver);
-    let touch_screen = gesture_events(device_events(raw_receiver, context.display, context.settings.button_scheme));
+    let palm_rejection = Some(context.settings.remarkable.palm_rejection_delay as f64 / 1000.0)
+                             .filter(|_| context.settings.remarkable.palm_rejection);
+    let touch_screen = gesture_events(device_events(raw_receiver, context.display,
+                                                    context.settings.button_scheme, palm_rejection));
     let usb_port = usb_events();
 
     let (tx, rx) = mpsc::channel();
@@ -926,6 +931,7 @@ pub fn run() -> Result<(), Error> {
                     },
                     AppCmd::Calculator => Box::new(Calculator::new(context.fb.rect(), &tx, &mut context)?),
                     AppCmd::Dictionary { ref query, ref language } => Box::new(DictionaryApp::new(context.fb.rect(), query, language, &tx, &mut context)),
+                    AppCmd::Annotations => Box::new(Annotations::new(context.fb.rect(), None, &tx, &mut context)),
                 };
                 transfer_notifications(view.as_mut(), next_view.as_mut(), &mut context);
                 history.push(HistoryItem {
@@ -935,6 +941,28 @@ pub fn run() -> Result<(), Error> {
                 });
                 view = next_view;
             },
+            Event::OpenAnnotations(info) => {
+                view.children_mut().retain(|child| !child.is::<Menu>());
+                let mut next_view = Box::new(Annotations::new(context.fb.rect(), Some(*info), &tx, &mut context)) as Box<dyn View>;
+                transfer_notifications(view.as_mut(), next_view.as_mut(), &mut context);
+                history.push(HistoryItem {
+                    view,
+                    rotation: context.display.rotation,
+                    monochrome: context.fb.monochrome(),
+                });
+                view = next_view;
+            },
+            Event::OpenBookmarks(info) => {
+                view.children_mut().retain(|child| !child.is::<Menu>());
+                let mut next_view = Box::new(Bookmarks::new(context.fb.rect(), *info, &tx, &mut context)) as Box<dyn View>;
+                transfer_notifications(view.as_mut(), next_view.as_mut(), &mut context);
+                history.push(HistoryItem {
+                    view,
+                    rotation: context.display.rotation,
+                    monochrome: context.fb.monochrome(),
+                });
+                view = next_view;
+            },
             Event::Back => {
                 if let Some(item) = history.pop() {
                     view = item.view;
@@ -1116,6 +1144,9 @@ pub fn run() -> Result<(), Error> {
                     history[0].view.handle_event(&evt, &tx, &mut VecDeque::new(), &mut context);
                 };
             },
+            Event::UpdateFingerprint(path, fp) => {
+                context.library.update_fingerprint(path, fp);
+            },
             Event::Notify(msg) => {
                 let notif = Notification::new(ViewId::MessageNotif,
                                               msg, &tx, &mut context);
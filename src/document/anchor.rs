@@ -0,0 +1,216 @@
+use std::ops::Range;
+use levenshtein::levenshtein;
+use super::{Document, Location, TextLocation};
+
+// Maximum number of characters of the text stored before and after an annotation.
+pub const QUOTE_CONTEXT_LENGTH: usize = 32;
+// Maximum number of characters of the pieces of a quote searched to find approximate matches.
+const PROBE_LENGTH: usize = 24;
+// Quotes shorter than this are only searched for exactly.
+const MIN_FUZZY_LENGTH: usize = 8;
+// Maximum number of edits, relative to the length of a quote, of an approximate match.
+const MAX_EDIT_RATIO: f32 = 0.2;
+// Maximum number of approximate candidates considered for a quote.
+const MAX_CANDIDATES: usize = 256;
+
+// The text of a sequence of words, with the location of each word.
+#[derive(Debug, Clone, Default)]
+pub struct WordsText {
+    pub text: String,
+    // The byte offset, in `text`, and the location of each word.
+    words: Vec<(usize, TextLocation)>,
+}
+
+impl WordsText {
+    // Words are joined with spaces, unless the previous one ends with a hyphen.
+    pub fn push(&mut self, word: &str, location: TextLocation) {
+        if self.text.ends_with('\u{00AD}') {
+            self.text.pop();
+        } else if !self.text.ends_with('-') && !self.text.is_empty() {
+            self.text.push(' ');
+        }
+        self.words.push((self.text.len(), location));
+        self.text += word;
+    }
+
+    // Returns the selection of the words overlapping the given byte range.
+    pub fn selection(&self, range: Range<usize>) -> Option<[TextLocation; 2]> {
+        let first = match self.words.binary_search_by_key(&range.start, |w| w.0) {
+            Ok(index) => index,
+            Err(index) => index.checked_sub(1)?,
+        };
+        let last = match self.words.binary_search_by_key(&range.end.saturating_sub(1).max(range.start), |w| w.0) {
+            Ok(index) => index,
+            Err(index) => index.checked_sub(1)?,
+        };
+        Some([self.words[first].1, self.words[last].1])
+    }
+
+    // Returns the byte offset of the first word at or after the given location.
+    pub fn position(&self, location: TextLocation) -> usize {
+        let index = self.words.iter().position(|w| w.1 >= location)
+                        .unwrap_or_else(|| self.words.len());
+        self.words.get(index).map_or(self.text.len(), |w| w.0)
+    }
+}
+
+// Returns the text of the words within the given selection.
+pub fn selection_text(doc: &mut dyn Document, selection: [TextLocation; 2]) -> Option<String> {
+    let [start, end] = selection;
+    let mut words_text = WordsText::default();
+    let mut loc = Location::Exact(start.location());
+
+    while let Some(location) = doc.resolve_location(loc) {
+        let (words, _) = doc.words(Location::Exact(location))?;
+        for word in &words {
+            if word.location >= start && word.location <= end {
+                words_text.push(&word.text, word.location);
+            }
+        }
+        if words.last().map_or(true, |w| w.location >= end) {
+            break;
+        }
+        loc = Location::Next(location);
+    }
+
+    Some(words_text.text)
+}
+
+// Two quotes match if they only differ by their spacing.
+pub fn same_quote(a: &str, b: &str) -> bool {
+    a.split_whitespace().eq(b.split_whitespace())
+}
+
+fn common_prefix_len(a: &str, b: &str) -> usize {
+    a.chars().zip(b.chars()).take_while(|(x, y)| x == y).count()
+}
+
+fn common_suffix_len(a: &str, b: &str) -> usize {
+    a.chars().rev().zip(b.chars().rev()).take_while(|(x, y)| x == y).count()
+}
+
+// Returns the byte offset of the character that is `n` characters after `start`.
+fn advance(text: &str, start: usize, n: usize) -> usize {
+    text[start..].char_indices().nth(n)
+                 .map_or(text.len(), |(i, _)| start + i)
+}
+
+fn floor_char_boundary(text: &str, mut index: usize) -> usize {
+    while !text.is_char_boundary(index) {
+        index -= 1;
+    }
+    index
+}
+
+// Finds the byte range of the given quote within the text. Among several matches, the one
+// with the closest context is preferred, then the one closest to `hint`. When the quote
+// doesn't appear verbatim, matches with a few edits are considered.
+pub fn locate_quote(text: &str, exact: &str, prefix: &str, suffix: &str, hint: usize) -> Option<Range<usize>> {
+    if exact.is_empty() {
+        return None;
+    }
+
+    let context_score = |range: &Range<usize>| {
+        common_suffix_len(&text[..range.start], prefix) +
+        common_prefix_len(&text[range.end..], suffix)
+    };
+    let distance = |range: &Range<usize>| (range.start as i64 - hint as i64).abs();
+
+    let exact_matches = text.match_indices(exact)
+                            .map(|(i, s)| i..i+s.len())
+                            .collect::<Vec<Range<usize>>>();
+
+    if !exact_matches.is_empty() {
+        return exact_matches.into_iter().max_by(|a, b| {
+            context_score(a).cmp(&context_score(b))
+                            .then_with(|| distance(b).cmp(&distance(a)))
+        });
+    }
+
+    let count = exact.chars().count();
+    if count < MIN_FUZZY_LENGTH {
+        return None;
+    }
+
+    // Look for the head, the middle and the tail of the quote, and deduce where it starts.
+    let probe_len = PROBE_LENGTH.min(count / 3).max(MIN_FUZZY_LENGTH / 2);
+    let mut candidates = Vec::new();
+    for &start_char in &[0, (count - probe_len) / 2, count - probe_len] {
+        let probe_start = advance(exact, 0, start_char);
+        let probe_end = advance(exact, probe_start, probe_len);
+        let probe = &exact[probe_start..probe_end];
+        for (i, _) in text.match_indices(probe) {
+            if candidates.len() >= MAX_CANDIDATES {
+                break;
+            }
+            if let Some(start) = i.checked_sub(probe_start) {
+                let start = floor_char_boundary(text, start);
+                if !candidates.contains(&start) {
+                    candidates.push(start);
+                }
+            }
+        }
+    }
+
+    let max_edits = (MAX_EDIT_RATIO * count as f32) as usize;
+
+    candidates.into_iter().filter_map(|start| {
+        let end = advance(text, start, count);
+        let edits = levenshtein(exact, &text[start..end]);
+        if edits <= max_edits {
+            Some((start..end, edits))
+        } else {
+            None
+        }
+    }).min_by(|(a, ea), (b, eb)| {
+        ea.cmp(eb)
+          .then_with(|| context_score(b).cmp(&context_score(a)))
+          .then_with(|| distance(a).cmp(&distance(b)))
+    }).map(|(range, _)| range)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXT: &str = "It was the best of times, it was the worst of times, \
+                        it was the age of wisdom, it was the age of foolishness.";
+
+    #[test]
+    fn test_exact_context() {
+        let range = locate_quote(TEXT, "it was the", "of times, ", " worst", 0).unwrap();
+        assert_eq!(&TEXT[range.start-10..range.end+6], "of times, it was the worst");
+        let range = locate_quote(TEXT, "it was the", "wisdom, ", " age", 0).unwrap();
+        assert_eq!(&TEXT[range.end..range.end+15], " age of foolish");
+    }
+
+    #[test]
+    fn test_exact_hint() {
+        let range = locate_quote(TEXT, "times", "", "", TEXT.len()).unwrap();
+        assert_eq!(range.start, TEXT.rfind("times").unwrap());
+        let range = locate_quote(TEXT, "times", "", "", 0).unwrap();
+        assert_eq!(range.start, TEXT.find("times").unwrap());
+    }
+
+    #[test]
+    fn test_fuzzy() {
+        let range = locate_quote(TEXT, "it was the age of wisdon, it was", "", "", 0).unwrap();
+        assert_eq!(&TEXT[range], "it was the age of wisdom, it was");
+        assert!(locate_quote(TEXT, "a completely different sentence", "", "", 0).is_none());
+        assert!(locate_quote(TEXT, "wisdon", "", "", 0).is_none());
+    }
+
+    #[test]
+    fn test_words_text() {
+        let mut words_text = WordsText::default();
+        for (i, word) in ["It", "was", "the", "be\u{00AD}", "st", "of"].iter().enumerate() {
+            words_text.push(word, TextLocation::Dynamic(10 * i));
+        }
+        assert_eq!(words_text.text, "It was the best of");
+        assert_eq!(words_text.selection(3..10),
+                   Some([TextLocation::Dynamic(10), TextLocation::Dynamic(20)]));
+        assert_eq!(words_text.selection(11..15),
+                   Some([TextLocation::Dynamic(30), TextLocation::Dynamic(40)]));
+        assert_eq!(words_text.position(TextLocation::Dynamic(25)), 11);
+    }
+}
@@ -0,0 +1,132 @@
+use crate::framebuffer::Pixmap;
+use crate::metadata::Margin;
+
+// Pixels darker than this are considered ink.
+const INK_THRESHOLD: u8 = 0x80;
+// Rows or columns with fewer ink pixels, relative to their length, are blank.
+const SPECKLE_RATIO: f32 = 0.003;
+// Minimum extent, relative to the page size, of a block of ink.
+const MIN_BLOCK_RATIO: f32 = 0.004;
+// Rows or columns with more ink pixels, relative to their length, belong to a
+// scanner edge when they're close enough to the side of the page.
+const EDGE_INK_RATIO: f32 = 0.4;
+// Size, relative to the page size, of the area where scanner edges are looked for.
+const EDGE_ZONE_RATIO: f32 = 0.08;
+// Space added, relative to the page size, around the detected ink.
+const PADDING_RATIO: f32 = 0.01;
+
+// Computes the margins that surround the ink of a rendered page.
+// Returns `None` if the page is blank.
+pub fn ink_margin(pixmap: &Pixmap) -> Option<Margin> {
+    let width = pixmap.width as usize;
+    let height = pixmap.height as usize;
+
+    if width == 0 || height == 0 {
+        return None;
+    }
+
+    let is_ink = |x: usize, y: usize| pixmap.data[y * width + x] < INK_THRESHOLD;
+
+    // Remove the dark bands left by the scanner on the sides of the page.
+    let rows = (0..height).map(|y| (0..width).filter(|&x| is_ink(x, y)).count())
+                          .collect::<Vec<usize>>();
+    let (y_min, y_max) = edges(&rows, width);
+    if y_min >= y_max {
+        return None;
+    }
+
+    let columns = (0..width).map(|x| (y_min..y_max).filter(|&y| is_ink(x, y)).count())
+                            .collect::<Vec<usize>>();
+    let (x_min, x_max) = edges(&columns, y_max - y_min);
+    if x_min >= x_max {
+        return None;
+    }
+
+    let rows = (y_min..y_max).map(|y| (x_min..x_max).filter(|&x| is_ink(x, y)).count())
+                             .collect::<Vec<usize>>();
+    let (top, bottom) = ink_extent(&rows, x_max - x_min, height)?;
+
+    let columns = (x_min..x_max).map(|x| (y_min+top..y_min+bottom).filter(|&y| is_ink(x, y)).count())
+                                .collect::<Vec<usize>>();
+    let (left, right) = ink_extent(&columns, bottom - top, width)?;
+
+    let top = ((y_min + top) as f32 / height as f32 - PADDING_RATIO).max(0.0);
+    let bottom = ((height - y_min - bottom) as f32 / height as f32 - PADDING_RATIO).max(0.0);
+    let left = ((x_min + left) as f32 / width as f32 - PADDING_RATIO).max(0.0);
+    let right = ((width - x_min - right) as f32 / width as f32 - PADDING_RATIO).max(0.0);
+
+    Some(Margin::new(top, right, bottom, left))
+}
+
+// Returns the range of the profile that lies between the scanner edges.
+fn edges(profile: &[usize], length: usize) -> (usize, usize) {
+    let size = profile.len();
+    let zone = (EDGE_ZONE_RATIO * size as f32).ceil() as usize;
+    let is_edge = |&count: &usize| count as f32 > EDGE_INK_RATIO * length as f32;
+    let start = profile[..zone.min(size)].iter().rposition(is_edge)
+                                         .map_or(0, |i| i + 1);
+    let end = profile[size.saturating_sub(zone)..].iter().position(is_edge)
+                                                  .map_or(size, |i| size - zone.min(size) + i);
+    (start, end)
+}
+
+// Returns the range, within the profile, of the blocks of ink that are large
+// enough not to be speckles.
+fn ink_extent(profile: &[usize], length: usize, size: usize) -> Option<(usize, usize)> {
+    let threshold = (SPECKLE_RATIO * length as f32).ceil() as usize;
+    let min_block = ((MIN_BLOCK_RATIO * size as f32).ceil() as usize).max(2);
+    let mut start = None;
+    let mut end = None;
+    let mut block_start = None;
+
+    for i in 0..=profile.len() {
+        if i < profile.len() && profile[i] > threshold {
+            if block_start.is_none() {
+                block_start = Some(i);
+            }
+        } else if let Some(bs) = block_start.take() {
+            if i - bs >= min_block {
+                start.get_or_insert(bs);
+                end = Some(i);
+            }
+        }
+    }
+
+    start.and_then(|s| end.map(|e| (s, e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fill(pixmap: &mut Pixmap, x0: u32, y0: u32, x1: u32, y1: u32) {
+        for y in y0..y1 {
+            for x in x0..x1 {
+                pixmap.data[(y * pixmap.width + x) as usize] = 0;
+            }
+        }
+    }
+
+    #[test]
+    fn test_speckles_and_edges() {
+        let mut pixmap = Pixmap::new(200, 300);
+        fill(&mut pixmap, 50, 60, 150, 240);
+        // Speckles.
+        fill(&mut pixmap, 10, 10, 11, 11);
+        fill(&mut pixmap, 180, 280, 181, 281);
+        // Scanner edge.
+        fill(&mut pixmap, 0, 0, 6, 300);
+        let margin = ink_margin(&pixmap).unwrap();
+        assert!((margin.top - 0.19).abs() < 0.005);
+        assert!((margin.bottom - 0.19).abs() < 0.005);
+        assert!((margin.left - 0.24).abs() < 0.005);
+        assert!((margin.right - 0.24).abs() < 0.005);
+    }
+
+    #[test]
+    fn test_blank_page() {
+        let mut pixmap = Pixmap::new(200, 300);
+        fill(&mut pixmap, 100, 100, 101, 101);
+        assert!(ink_margin(&pixmap).is_none());
+    }
+}
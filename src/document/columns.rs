@@ -0,0 +1,195 @@
+use crate::framebuffer::Pixmap;
+use crate::geom::Boundary;
+
+// Lines at least this wide, relative to the text width, span several columns.
+const WIDE_LINE_RATIO: f32 = 0.6;
+// Minimum width, relative to the text width, of the space between two columns.
+const MIN_GUTTER_RATIO: f32 = 0.02;
+// Pixels darker than this are considered ink.
+const INK_THRESHOLD: u8 = 0x80;
+// Maximum number of ink pixels in a blank row or column.
+const SPECKLE_SIZE: usize = 2;
+
+// Detects the text columns of a page from the bounding boxes of its lines.
+// The columns are returned in reading order: bands of lines spanning the whole
+// text width interrupt the columns, which are read from left to right.
+pub fn columns_from_lines(lines: &[Boundary]) -> Vec<Boundary> {
+    let mut lines: Vec<Boundary> = lines.iter()
+                                        .filter(|r| r.width() > 0.0 && r.height() > 0.0)
+                                        .cloned().collect();
+    if lines.is_empty() {
+        return Vec::new();
+    }
+
+    let text_width = union(&lines).width();
+    lines.sort_by(|a, b| a.min.y.partial_cmp(&b.min.y).unwrap());
+
+    let mut bands: Vec<(bool, Vec<Boundary>)> = Vec::new();
+
+    for line in lines {
+        let wide = line.width() >= WIDE_LINE_RATIO * text_width;
+        match bands.last_mut() {
+            Some((w, band)) if *w == wide => band.push(line),
+            _ => bands.push((wide, vec![line])),
+        }
+    }
+
+    let mut columns: Vec<Boundary> = Vec::new();
+    let mut previous_single = false;
+
+    for (wide, band) in bands {
+        let band_columns = if wide {
+            vec![union(&band)]
+        } else {
+            split_columns(&band, text_width)
+        };
+
+        if band_columns.len() == 1 {
+            if previous_single {
+                if let Some(last) = columns.last_mut() {
+                    last.absorb(&band_columns[0]);
+                    continue;
+                }
+            }
+            previous_single = true;
+        } else {
+            previous_single = false;
+        }
+
+        columns.extend(band_columns);
+    }
+
+    columns
+}
+
+// Detects the text columns of a page from its rendering, using the blank spaces
+// between the blocks of ink. The columns are expressed in pixels.
+pub fn columns_from_pixmap(pixmap: &Pixmap) -> Vec<Boundary> {
+    let width = pixmap.width as usize;
+    let height = pixmap.height as usize;
+    let is_ink = |x: usize, y: usize| pixmap.data[y * width + x] < INK_THRESHOLD;
+    let min_gutter = ((MIN_GUTTER_RATIO * width as f32) as usize).max(3);
+    let mut blocks = Vec::new();
+    let mut y = 0;
+
+    while y < height {
+        if (0..width).filter(|&x| is_ink(x, y)).count() <= SPECKLE_SIZE {
+            y += 1;
+            continue;
+        }
+
+        let y_start = y;
+        while y < height && (0..width).filter(|&x| is_ink(x, y)).count() > SPECKLE_SIZE {
+            y += 1;
+        }
+
+        // Split the band of rows at the blank vertical strips it contains.
+        let mut x_start = None;
+        let mut blank_run = 0;
+        for x in 0..=width {
+            let inked = x < width && (y_start..y).filter(|&v| is_ink(x, v)).count() > SPECKLE_SIZE;
+            if inked {
+                if x_start.is_none() {
+                    x_start = Some(x);
+                }
+                blank_run = 0;
+            } else if let Some(xs) = x_start {
+                blank_run += 1;
+                if blank_run >= min_gutter || x == width {
+                    let x_end = x + 1 - blank_run;
+                    if x_end > xs + SPECKLE_SIZE {
+                        blocks.push(bndr!(xs as f32, y_start as f32, x_end as f32, y as f32));
+                    }
+                    x_start = None;
+                    blank_run = 0;
+                }
+            }
+        }
+    }
+
+    columns_from_lines(&blocks)
+}
+
+fn union(rects: &[Boundary]) -> Boundary {
+    let mut result = rects[0];
+    for rect in &rects[1..] {
+        result.absorb(rect);
+    }
+    result
+}
+
+fn split_columns(lines: &[Boundary], text_width: f32) -> Vec<Boundary> {
+    let bbox = union(lines);
+    let min_x = bbox.min.x.floor();
+    let count = (bbox.max.x.ceil() - min_x) as usize + 1;
+    let mut covered = vec![false; count];
+
+    for line in lines {
+        let start = (line.min.x - min_x) as usize;
+        let end = ((line.max.x - min_x).ceil() as usize).min(count);
+        for c in &mut covered[start..end] {
+            *c = true;
+        }
+    }
+
+    let min_gutter = (MIN_GUTTER_RATIO * text_width).max(1.0) as usize;
+    let mut cuts = Vec::new();
+    let mut start = None;
+
+    for (i, c) in covered.iter().enumerate() {
+        if !c {
+            if start.is_none() {
+                start = Some(i);
+            }
+        } else if let Some(s) = start.take() {
+            if i - s >= min_gutter {
+                cuts.push(min_x + (s + i) as f32 / 2.0);
+            }
+        }
+    }
+
+    let mut columns: Vec<Option<Boundary>> = vec![None; cuts.len() + 1];
+
+    for line in lines {
+        let center = (line.min.x + line.max.x) / 2.0;
+        let index = cuts.iter().filter(|&&c| c < center).count();
+        match columns[index] {
+            Some(ref mut column) => column.absorb(line),
+            None => columns[index] = Some(*line),
+        }
+    }
+
+    columns.into_iter().flatten().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_single_column() {
+        let lines = vec![bndr!(50.0, 50.0, 550.0, 60.0),
+                         bndr!(50.0, 62.0, 550.0, 72.0),
+                         bndr!(50.0, 74.0, 200.0, 84.0),
+                         bndr!(70.0, 86.0, 550.0, 96.0)];
+        let columns = columns_from_lines(&lines);
+        assert_eq!(columns.len(), 1);
+        assert_eq!(columns[0].min.y, 50.0);
+        assert_eq!(columns[0].max.y, 96.0);
+    }
+
+    #[test]
+    fn test_two_columns_with_title() {
+        let mut lines = vec![bndr!(50.0, 20.0, 550.0, 40.0)];
+        for i in 0..10 {
+            let y = 50.0 + 12.0 * i as f32;
+            lines.push(bndr!(50.0, y, 290.0, y + 10.0));
+            lines.push(bndr!(310.0, y + 1.0, 550.0, y + 11.0));
+        }
+        let columns = columns_from_lines(&lines);
+        assert_eq!(columns.len(), 3);
+        assert_eq!(columns[0].max.y, 40.0);
+        assert_eq!(columns[1].max.x, 290.0);
+        assert_eq!(columns[2].min.x, 310.0);
+    }
+}
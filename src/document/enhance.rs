@@ -0,0 +1,214 @@
+use crate::framebuffer::Pixmap;
+
+// Pixels darker than this are considered ink.
+const INK_THRESHOLD: u8 = 0x80;
+// Pixels darker than this belong to the connected components of ink.
+const BLOT_THRESHOLD: u8 = 0xC0;
+// Maximum number of pixels of a speckle, for a pixmap of the reference width.
+const SPECKLE_AREA: f32 = 4.0;
+const REFERENCE_WIDTH: f32 = 1000.0;
+// Fraction of the pixels clipped at both ends of the histogram by the auto-levels.
+const LEVELS_CLIP_RATIO: f32 = 0.005;
+// Range and precision, in degrees, of the skew detection.
+const MAX_SKEW_ANGLE: f32 = 5.0;
+const SKEW_ANGLE_STEP: f32 = 0.1;
+// Width of the sample of the pixmap used for the skew detection.
+const SKEW_SAMPLE_WIDTH: usize = 600;
+
+// Stretches the gray levels of the pixmap to the full range.
+pub fn auto_levels(pixmap: &mut Pixmap) {
+    let mut histogram = [0usize; 256];
+    for &v in &pixmap.data {
+        histogram[v as usize] += 1;
+    }
+
+    let clip = (LEVELS_CLIP_RATIO * pixmap.data.len() as f32) as usize;
+    let mut sum = 0;
+    let low = histogram.iter().position(|&n| { sum += n; sum > clip }).unwrap_or(0);
+    sum = 0;
+    let high = 255 - histogram.iter().rev().position(|&n| { sum += n; sum > clip }).unwrap_or(0);
+
+    if high <= low + 16 {
+        return;
+    }
+
+    let range = (high - low) as f32;
+    let mut table = [0u8; 256];
+    for (i, t) in table.iter_mut().enumerate() {
+        *t = ((i.max(low).min(high) - low) as f32 * 255.0 / range).round() as u8;
+    }
+
+    for v in &mut pixmap.data {
+        *v = table[*v as usize];
+    }
+}
+
+// Removes the small isolated dots of ink.
+pub fn despeckle(pixmap: &mut Pixmap) {
+    let width = pixmap.width as usize;
+    let height = pixmap.height as usize;
+    let ratio = width as f32 / REFERENCE_WIDTH;
+    let max_area = ((SPECKLE_AREA * ratio * ratio).round() as usize).max(1);
+    let mut visited = vec![false; width * height];
+    let mut stack = Vec::new();
+    let mut component = Vec::new();
+
+    for start in 0..width * height {
+        if visited[start] || pixmap.data[start] >= BLOT_THRESHOLD {
+            continue;
+        }
+
+        visited[start] = true;
+        stack.push(start);
+        component.clear();
+        let mut has_ink = false;
+
+        while let Some(i) = stack.pop() {
+            component.push(i);
+            has_ink |= pixmap.data[i] < INK_THRESHOLD;
+            let (x, y) = ((i % width) as i64, (i / width) as i64);
+            for dy in -1..=1 {
+                for dx in -1..=1 {
+                    let (nx, ny) = (x + dx, y + dy);
+                    if nx < 0 || ny < 0 || nx >= width as i64 || ny >= height as i64 {
+                        continue;
+                    }
+                    let j = ny as usize * width + nx as usize;
+                    if !visited[j] && pixmap.data[j] < BLOT_THRESHOLD {
+                        visited[j] = true;
+                        stack.push(j);
+                    }
+                }
+            }
+        }
+
+        if has_ink && component.len() <= max_area {
+            for &i in &component {
+                pixmap.data[i] = 0xff;
+            }
+        }
+    }
+}
+
+// Returns the angle, in degrees, of the lines of text of the pixmap.
+// A positive angle means that the lines go down from left to right.
+pub fn skew_angle(pixmap: &Pixmap) -> f32 {
+    let width = pixmap.width as usize;
+    let height = pixmap.height as usize;
+    let step = (width / SKEW_SAMPLE_WIDTH).max(1);
+    let mut points = Vec::new();
+
+    for y in (0..height).step_by(step) {
+        for x in (0..width).step_by(step) {
+            if pixmap.data[y * width + x] < INK_THRESHOLD {
+                points.push(((x / step) as f32, (y / step) as f32));
+            }
+        }
+    }
+
+    if points.is_empty() {
+        return 0.0;
+    }
+
+    let sample_width = (width / step) as f32;
+    let sample_height = (height / step) as f32;
+    let offset = sample_width * MAX_SKEW_ANGLE.to_radians().tan();
+    let bins_count = (sample_height + 2.0 * offset).ceil() as usize + 1;
+    let mut bins = vec![0usize; bins_count];
+    let steps = (MAX_SKEW_ANGLE / SKEW_ANGLE_STEP).round() as i32;
+    let mut best = (0.0, 0);
+
+    // The projection on the vertical axis along the direction of the lines is
+    // the most contrasted when the angle matches the skew of the page.
+    for k in -steps..=steps {
+        let angle = k as f32 * SKEW_ANGLE_STEP;
+        let slope = angle.to_radians().tan();
+        for b in bins.iter_mut() {
+            *b = 0;
+        }
+        for &(x, y) in &points {
+            let index = (y - x * slope + offset) as usize;
+            bins[index.min(bins_count - 1)] += 1;
+        }
+        let score = bins.windows(2)
+                        .map(|w| { let d = w[1] as i64 - w[0] as i64; (d * d) as u64 })
+                        .sum::<u64>();
+        if score > best.1 || (score == best.1 && angle.abs() < f32::abs(best.0)) {
+            best = (angle, score);
+        }
+    }
+
+    best.0
+}
+
+// Rotates the pixmap around its center so that its lines of text become horizontal.
+pub fn deskew(pixmap: &mut Pixmap, angle: f32) {
+    if angle.abs() < SKEW_ANGLE_STEP / 2.0 {
+        return;
+    }
+
+    let width = pixmap.width as usize;
+    let height = pixmap.height as usize;
+    let (sin, cos) = angle.to_radians().sin_cos();
+    let (cx, cy) = (width as f32 / 2.0, height as f32 / 2.0);
+    let source = &pixmap.data;
+    let sample = |x: i64, y: i64| -> f32 {
+        if x < 0 || y < 0 || x >= width as i64 || y >= height as i64 {
+            255.0
+        } else {
+            source[y as usize * width + x as usize] as f32
+        }
+    };
+    let mut data = vec![0xff; width * height];
+
+    for yd in 0..height {
+        for xd in 0..width {
+            let (dx, dy) = (xd as f32 + 0.5 - cx, yd as f32 + 0.5 - cy);
+            let xs = cx + dx * cos - dy * sin - 0.5;
+            let ys = cy + dx * sin + dy * cos - 0.5;
+            let (x0, y0) = (xs.floor(), ys.floor());
+            let (fx, fy) = (xs - x0, ys - y0);
+            let (x0, y0) = (x0 as i64, y0 as i64);
+            let top = sample(x0, y0) * (1.0 - fx) + sample(x0 + 1, y0) * fx;
+            let bottom = sample(x0, y0 + 1) * (1.0 - fx) + sample(x0 + 1, y0 + 1) * fx;
+            data[yd * width + xd] = (top * (1.0 - fy) + bottom * fy).round() as u8;
+        }
+    }
+
+    pixmap.data = data;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_despeckle() {
+        let mut pixmap = Pixmap::new(100, 100);
+        pixmap.data[10 * 100 + 10] = 0;
+        for y in 40..50 {
+            for x in 20..80 {
+                pixmap.data[y * 100 + x] = 0;
+            }
+        }
+        despeckle(&mut pixmap);
+        assert_eq!(pixmap.data[10 * 100 + 10], 0xff);
+        assert_eq!(pixmap.data[45 * 100 + 50], 0);
+    }
+
+    #[test]
+    fn test_skew_angle() {
+        let mut pixmap = Pixmap::new(400, 400);
+        let slope = 2.0f32.to_radians().tan();
+        for line in 0..10 {
+            let y0 = 40.0 + 30.0 * line as f32;
+            for x in 20..380 {
+                let y = (y0 + x as f32 * slope) as usize;
+                for dy in 0..4 {
+                    pixmap.data[(y + dy) * 400 + x] = 0;
+                }
+            }
+        }
+        assert!((skew_angle(&pixmap) - 2.0).abs() <= 0.2);
+    }
+}
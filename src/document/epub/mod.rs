@@ -2,12 +2,14 @@ use std::io::Read;
 use std::fs::{self, File};
 use std::path::{Path, PathBuf};
 use std::borrow::Cow;
-use std::collections::BTreeSet;
-use fxhash::FxHashMap;
+use std::collections::{BTreeSet, BTreeMap};
+use std::hash::{Hash, Hasher};
+use fxhash::{FxHashMap, FxHasher64};
 use zip::ZipArchive;
+use serde::{Serialize, Deserialize};
 use anyhow::{Error, format_err};
 use crate::framebuffer::Pixmap;
-use crate::helpers::{Normalize, decode_entities};
+use crate::helpers::{Normalize, decode_entities, load_json, save_json};
 use crate::document::{Document, Location, TextLocation, TocEntry, BoundedText, chapter_from_uri};
 use crate::unit::pt_to_px;
 use crate::geom::{Rectangle, Edge, CycleDir};
@@ -15,12 +17,18 @@ use super::html::dom::Node;
 use super::html::engine::{Page, Engine, ResourceFetcher};
 use super::html::layout::{StyleData, LoopContext};
 use super::html::layout::{RootData, DrawState, DrawCommand, TextCommand, ImageCommand};
-use super::html::layout::TextAlign;
+use super::html::layout::{TextAlign, HYPHENATION_PATTERNS_DIR};
+use crate::metadata::DitherMode;
+use crate::settings::DEFAULT_FONT_FAMILY;
 use super::html::css::{CssParser, RuleKind};
 use super::html::xml::XmlParser;
 
 const VIEWER_STYLESHEET: &str = "css/epub.css";
 const USER_STYLESHEET: &str = "css/epub-user.css";
+// Number of layout settings whose page boundaries are remembered.
+const MAX_PAGINATIONS: usize = 4;
+// Bumped whenever the layout engine changes the way pages are cut.
+const PAGINATION_VERSION: u32 = 1;
 
 type UriCache = FxHashMap<String, usize>;
 
@@ -40,9 +48,22 @@ pub struct EpubDocument {
     engine: Engine,
     spine: Vec<Chunk>,
     cache: FxHashMap<usize, Vec<Page>>,
+    font_family: String,
+    // The page boundaries computed for the most recently used layout settings.
+    pagination: Vec<Pagination>,
+    pagination_modified: bool,
+    // Fingerprint of the stylesheets and hyphenation patterns the layout depends on.
+    resources_digest: u64,
     ignore_document_css: bool,
 }
 
+// The start offsets of the pages of each spine item, for the layout settings given by the key.
+#[derive(Debug, Serialize, Deserialize)]
+struct Pagination {
+    key: String,
+    boundaries: BTreeMap<usize, Vec<usize>>,
+}
+
 #[derive(Debug)]
 struct Chunk {
     path: String,
@@ -120,6 +141,10 @@ impl EpubDocument {
             engine: Engine::new(),
             spine,
             cache: FxHashMap::default(),
+            font_family: DEFAULT_FONT_FAMILY.to_string(),
+            pagination: Vec::new(),
+            pagination_modified: false,
+            resources_digest: resources_digest(),
             ignore_document_css: false,
         })
     }
@@ -228,27 +253,84 @@ impl EpubDocument {
         entries
     }
 
+    fn pagination_key(&self) -> String {
+        let (width, height) = self.engine.dims;
+        format!("v{}:{}:{}:{}:{:?}:{:?}:{}x{}:{}:{}:{:016x}",
+                PAGINATION_VERSION, self.font_family, self.engine.font_size,
+                self.engine.line_height, self.engine.text_align, self.engine.margin,
+                width, height, self.engine.dpi, self.ignore_document_css,
+                self.resources_digest)
+    }
+
+    fn cache_display_list(&mut self, index: usize, start_offset: usize) {
+        if self.cache.contains_key(&index) {
+            return;
+        }
+
+        let display_list = self.build_display_list(index, start_offset);
+        let offsets: Vec<usize> = display_list.iter()
+                                              .filter_map(|page| page.first().map(DrawCommand::offset))
+                                              .collect();
+        let key = self.pagination_key();
+
+        // Boundaries that don't match the actual layout make the whole pagination stale.
+        if let Some(pos) = self.pagination.iter().position(|p| p.key == key) {
+            if self.pagination[pos].boundaries.get(&index).map_or(false, |b| *b != offsets) {
+                self.pagination.remove(pos);
+            }
+        }
+
+        if let Some(pos) = self.pagination.iter().position(|p| p.key == key) {
+            let boundaries = &mut self.pagination[pos].boundaries;
+            if boundaries.get(&index) != Some(&offsets) {
+                boundaries.insert(index, offsets);
+                self.pagination_modified = true;
+            }
+        } else {
+            let mut boundaries = BTreeMap::new();
+            boundaries.insert(index, offsets);
+            self.pagination.insert(0, Pagination { key, boundaries });
+            self.pagination.truncate(MAX_PAGINATIONS);
+            self.pagination_modified = true;
+        }
+
+        self.cache.insert(index, display_list);
+    }
+
+    // Returns the start offsets of the pages of the given spine item. The layout
+    // is only computed if the boundaries aren't already known.
+    fn page_offsets(&mut self, index: usize, start_offset: usize) -> Option<Vec<usize>> {
+        if let Some(display_list) = self.cache.get(&index) {
+            return Some(display_list.iter()
+                                    .filter_map(|page| page.first().map(DrawCommand::offset))
+                                    .collect());
+        }
+
+        let key = self.pagination_key();
+        if let Some(offsets) = self.pagination.iter().find(|p| p.key == key)
+                                   .and_then(|p| p.boundaries.get(&index)) {
+            return Some(offsets.clone());
+        }
+
+        self.cache_display_list(index, start_offset);
+        self.page_offsets(index, start_offset)
+    }
+
     #[inline]
     fn page_index(&mut self, offset: usize, index: usize, start_offset: usize) -> Option<usize> {
-        if !self.cache.contains_key(&index) {
-            let display_list = self.build_display_list(index, start_offset);
-            self.cache.insert(index, display_list);
-        }
-        self.cache.get(&index).map(|display_list| {
-            if display_list.len() < 2 || display_list[1].first().map(|dc| offset < dc.offset()) == Some(true) {
-                return 0;
-            } else if display_list[display_list.len() - 1].first().map(|dc| offset >= dc.offset()) == Some(true) {
-                return display_list.len() - 1;
-            } else {
-                for i in 1..display_list.len()-1 {
-                    if display_list[i].first().map(|dc| offset >= dc.offset()) == Some(true) &&
-                       display_list[i+1].first().map(|dc| offset < dc.offset()) == Some(true) {
-                        return i;
-                    }
-                }
-            }
-            0
-        })
+        let offsets = self.page_offsets(index, start_offset)?;
+        if offsets.is_empty() {
+            return None;
+        }
+        Some(offsets.iter().rposition(|&o| offset >= o).unwrap_or(0))
+    }
+
+    // Returns the page containing the given offset. The display list is built
+    // first, so the page index never comes from stale boundaries.
+    fn page(&mut self, offset: usize, index: usize, start_offset: usize) -> Option<&Page> {
+        self.cache_display_list(index, start_offset);
+        let page_index = self.page_index(offset, index, start_offset)?;
+        self.cache.get(&index)?.get(page_index)
     }
 
     fn resolve_link(&mut self, uri: &str, cache: &mut UriCache) -> Option<usize> {
@@ -267,10 +349,8 @@ impl EpubDocument {
             self.cache_uris(&root, name, start_offset, cache);
             cache.get(uri).cloned()
         } else {
-            let page_index = self.page_index(start_offset, index, start_offset)?;
-            let offset = self.cache.get(&index)
-                             .and_then(|display_list| display_list[page_index].first())
-                             .map(DrawCommand::offset)?;
+            let offset = self.page_offsets(index, start_offset)?
+                              .first().cloned()?;
             cache.insert(uri.to_string(), offset);
             Some(offset)
         }
@@ -295,16 +375,14 @@ impl EpubDocument {
 
         let offset = self.resolve_location(loc)?;
         let (index, start_offset) = self.vertebra_coordinates(offset)?;
-        let page_index = self.page_index(offset, index, start_offset)?;
+        let page = self.page(offset, index, start_offset)?;
 
-        self.cache.get(&index).map(|display_list| {
-            (display_list[page_index].iter().filter_map(|dc| {
-                match dc {
-                    DrawCommand::Image(ImageCommand { rect, .. }) => Some(*rect),
-                    _ => None,
-                }
-            }).collect(), offset)
-        })
+        Some((page.iter().filter_map(|dc| {
+            match dc {
+                DrawCommand::Image(ImageCommand { rect, .. }) => Some(*rect),
+                _ => None,
+            }
+        }).collect(), offset))
     }
 
     fn build_display_list(&mut self, index: usize, start_offset: usize) -> Vec<Page> {
@@ -634,45 +712,37 @@ impl Document for EpubDocument {
             Location::Exact(offset) => {
                 let (index, start_offset) = self.vertebra_coordinates(offset)?;
                 let page_index = self.page_index(offset, index, start_offset)?;
-                self.cache.get(&index)
-                    .and_then(|display_list| display_list[page_index].first())
-                    .map(DrawCommand::offset)
+                self.page_offsets(index, start_offset)?
+                    .get(page_index).cloned()
             },
             Location::Previous(offset) => {
                 let (index, start_offset) = self.vertebra_coordinates(offset)?;
                 let page_index = self.page_index(offset, index, start_offset)?;
                 if page_index > 0 {
-                    self.cache.get(&index)
-                        .and_then(|display_list| display_list[page_index-1].first().map(DrawCommand::offset))
+                    self.page_offsets(index, start_offset)?
+                        .get(page_index-1).cloned()
                 } else {
                     if index == 0 {
                         return None;
                     }
                     let (index, start_offset) = (index - 1, start_offset - self.spine[index-1].size);
-                    if !self.cache.contains_key(&index) {
-                        let display_list = self.build_display_list(index, start_offset);
-                        self.cache.insert(index, display_list);
-                    }
-                    self.cache.get(&index)
-                        .and_then(|display_list| display_list.last().and_then(|page| page.first()).map(DrawCommand::offset))
+                    self.page_offsets(index, start_offset)?
+                        .last().cloned()
                 }
             },
             Location::Next(offset) => {
                 let (index, start_offset) = self.vertebra_coordinates(offset)?;
                 let page_index = self.page_index(offset, index, start_offset)?;
-                if page_index < self.cache.get(&index).map(Vec::len)? - 1 {
-                    self.cache.get(&index).and_then(|display_list| display_list[page_index+1].first().map(DrawCommand::offset))
+                let offsets = self.page_offsets(index, start_offset)?;
+                if page_index < offsets.len() - 1 {
+                    offsets.get(page_index+1).cloned()
                 } else {
                     if index == self.spine.len() - 1 {
                         return None;
                     }
                     let (index, start_offset) = (index + 1, start_offset + self.spine[index].size);
-                    if !self.cache.contains_key(&index) {
-                        let display_list = self.build_display_list(index, start_offset);
-                        self.cache.insert(index, display_list);
-                    }
-                    self.cache.get(&index)
-                        .and_then(|display_list| display_list.first().and_then(|page| page.first()).map(|dc| dc.offset()))
+                    self.page_offsets(index, start_offset)?
+                        .first().cloned()
                 }
             },
             Location::LocalUri(offset, ref uri) => {
@@ -705,22 +775,20 @@ impl Document for EpubDocument {
 
         let offset = self.resolve_location(loc)?;
         let (index, start_offset) = self.vertebra_coordinates(offset)?;
-        let page_index = self.page_index(offset, index, start_offset)?;
-
-        self.cache.get(&index).map(|display_list| {
-            (display_list[page_index].iter().filter_map(|dc| {
-                match dc {
-                    DrawCommand::Text(TextCommand { text, rect, offset, .. }) => {
-                        Some(BoundedText {
-                            text: text.clone(),
-                            rect: (*rect).into(),
-                            location: TextLocation::Dynamic(*offset),
-                        })
-                    },
-                    _ => None,
-                }
-            }).collect(), offset)
-        })
+        let page = self.page(offset, index, start_offset)?;
+
+        Some((page.iter().filter_map(|dc| {
+            match dc {
+                DrawCommand::Text(TextCommand { text, rect, offset, .. }) => {
+                    Some(BoundedText {
+                        text: text.clone(),
+                        rect: (*rect).into(),
+                        location: TextLocation::Dynamic(*offset),
+                    })
+                },
+                _ => None,
+            }
+        }).collect(), offset))
     }
 
     fn lines(&mut self, _loc: Location) -> Option<(Vec<BoundedText>, usize)> {
@@ -734,23 +802,21 @@ impl Document for EpubDocument {
 
         let offset = self.resolve_location(loc)?;
         let (index, start_offset) = self.vertebra_coordinates(offset)?;
-        let page_index = self.page_index(offset, index, start_offset)?;
-
-        self.cache.get(&index).map(|display_list| {
-            (display_list[page_index].iter().filter_map(|dc| {
-                match dc {
-                    DrawCommand::Text(TextCommand { uri, rect, offset, .. }) |
-                    DrawCommand::Image(ImageCommand { uri, rect, offset, .. }) if uri.is_some() => {
-                        Some(BoundedText {
-                            text: uri.clone().unwrap(),
-                            rect: (*rect).into(),
-                            location: TextLocation::Dynamic(*offset),
-                        })
-                    },
-                    _ => None,
-                }
-            }).collect(), offset)
-        })
+        let page = self.page(offset, index, start_offset)?;
+
+        Some((page.iter().filter_map(|dc| {
+            match dc {
+                DrawCommand::Text(TextCommand { uri, rect, offset, .. }) |
+                DrawCommand::Image(ImageCommand { uri, rect, offset, .. }) if uri.is_some() => {
+                    Some(BoundedText {
+                        text: uri.clone().unwrap(),
+                        rect: (*rect).into(),
+                        location: TextLocation::Dynamic(*offset),
+                    })
+                },
+                _ => None,
+            }
+        }).collect(), offset))
     }
 
     fn pixmap(&mut self, loc: Location, _scale: f32) -> Option<(Pixmap, usize)> {
@@ -760,9 +826,7 @@ impl Document for EpubDocument {
 
         let offset = self.resolve_location(loc)?;
         let (index, start_offset) = self.vertebra_coordinates(offset)?;
-
-        let page_index = self.page_index(offset, index, start_offset)?;
-        let page = self.cache.get(&index)?.get(page_index)?.clone();
+        let page = self.page(offset, index, start_offset)?.clone();
 
         let pixmap = self.engine.render_page(&page, &mut self.archive);
 
@@ -781,6 +845,7 @@ impl Document for EpubDocument {
 
     fn set_font_family(&mut self, family_name: &str, search_path: &str) {
         self.engine.set_font_family(family_name, search_path);
+        self.font_family = family_name.to_string();
         self.cache.clear();
     }
 
@@ -794,6 +859,25 @@ impl Document for EpubDocument {
         self.cache.clear();
     }
 
+    fn set_dithering(&mut self, dithering: Option<(DitherMode, u8)>) {
+        self.engine.dithering = dithering;
+    }
+
+    fn load_pagination(&mut self, path: &Path) {
+        if path.exists() {
+            if let Ok(pagination) = load_json(path).map_err(|e| eprintln!("{}", e)) {
+                self.pagination = pagination;
+            }
+        }
+    }
+
+    fn save_pagination(&mut self, path: &Path) {
+        if self.pagination_modified {
+            save_json(&self.pagination, path).map_err(|e| eprintln!("{}", e)).ok();
+            self.pagination_modified = false;
+        }
+    }
+
     fn title(&self) -> Option<String> {
         self.metadata("dc:title")
     }
@@ -819,3 +903,26 @@ impl Document for EpubDocument {
         true
     }
 }
+
+// Fingerprint of the files, besides the document itself, that the layout depends on.
+fn resources_digest() -> u64 {
+    let mut hasher = FxHasher64::default();
+
+    for path in &[VIEWER_STYLESHEET, USER_STYLESHEET] {
+        fs::read(path).ok().hash(&mut hasher);
+    }
+
+    let mut patterns = fs::read_dir(HYPHENATION_PATTERNS_DIR).map(|entries| {
+        entries.filter_map(|e| e.ok().map(|e| e.path())).collect::<Vec<PathBuf>>()
+    }).unwrap_or_default();
+    patterns.sort();
+
+    for path in patterns {
+        path.hash(&mut hasher);
+        if path.extension().map_or(false, |ext| ext == "bounds") {
+            fs::read(&path).ok().hash(&mut hasher);
+        }
+    }
+
+    hasher.finish()
+}
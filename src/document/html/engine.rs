@@ -7,7 +7,8 @@ use paragraph_breaker::{total_fit, standard_fit};
 use xi_unicode::LineBreakIterator;
 use septem::Roman;
 use crate::helpers::{Normalize, decode_entities};
-use crate::framebuffer::{Framebuffer, Pixmap};
+use crate::framebuffer::{Framebuffer, Pixmap, dither};
+use crate::metadata::DitherMode;
 use crate::font::{FontOpener, FontFamily};
 use crate::document::{Document, Location};
 use crate::document::pdf::PdfOpener;
@@ -53,6 +54,8 @@ pub struct Engine {
     pub dims: (u32, u32),
     // Device DPI.
     pub dpi: u16,
+    // Dithering, and number of gray levels, of the images.
+    pub dithering: Option<(DitherMode, u8)>,
 }
 
 impl Engine {
@@ -68,6 +71,7 @@ impl Engine {
             line_height,
             dims: (DEFAULT_WIDTH, DEFAULT_HEIGHT),
             dpi: DEFAULT_DPI,
+            dithering: None,
         }
     }
 
@@ -1675,6 +1679,7 @@ impl Engine {
     pub fn render_page(&mut self, page: &[DrawCommand], resource_fetcher: &mut dyn ResourceFetcher) -> Pixmap {
         let (width, height) = self.dims;
         let mut fb = Pixmap::new(width, height);
+        let dithering = self.dithering;
 
         for dc in page {
             match dc {
@@ -1691,7 +1696,10 @@ impl Engine {
                             opener.open_memory(path, &buf)
                         }).and_then(|mut doc| {
                             doc.pixmap(Location::Exact(0), *scale)
-                        }).map(|(pixmap, _)| {
+                        }).map(|(mut pixmap, _)| {
+                            if let Some((mode, levels)) = dithering {
+                                dither(&mut pixmap, mode, levels);
+                            }
                             fb.draw_pixmap(&pixmap, *position);
                         });
                     }
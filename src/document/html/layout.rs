@@ -11,6 +11,7 @@ use super::dom::Node;
 use crate::color::BLACK;
 
 pub const DEFAULT_HYPH_LANG: &str = "en";
+pub const HYPHENATION_PATTERNS_DIR: &str = "hyphenation-patterns";
 
 #[derive(Debug, Clone)]
 pub struct RootData {
@@ -526,7 +527,7 @@ pub static ref HYPHENATION_PATTERNS: FxHashMap<Language, Standard> = {
         if map.contains_key(lang) {
             continue;
         }
-        let base = Path::new("hyphenation-patterns")
+        let base = Path::new(HYPHENATION_PATTERNS_DIR)
                         .join(lang.code());
         let path = base.with_extension("standard.bincode");
         if let Ok(mut patterns) = Standard::from_path(*lang, path) {
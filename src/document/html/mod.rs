@@ -10,7 +10,7 @@ use std::io::Read;
 use std::fs::{self, File};
 use std::path::{Path, PathBuf};
 use fxhash::FxHashMap;
-use anyhow::Error;
+use anyhow::{Error, format_err};
 use crate::framebuffer::Pixmap;
 use crate::helpers::{Normalize, decode_entities};
 use crate::document::{Document, Location, TextLocation, TocEntry, BoundedText};
@@ -19,6 +19,7 @@ use crate::geom::{Rectangle, Edge, CycleDir};
 use self::dom::Node;
 use self::layout::{RootData, StyleData, DrawState, LoopContext};
 use self::layout::{DrawCommand, TextCommand, ImageCommand, TextAlign};
+use crate::metadata::DitherMode;
 use self::engine::{Page, Engine, ResourceFetcher};
 use self::css::{CssParser, RuleKind};
 use self::xml::XmlParser;
@@ -32,7 +33,7 @@ pub struct HtmlDocument {
     content: Node,
     engine: Engine,
     pages: Vec<Page>,
-    parent: PathBuf,
+    fetcher: Box<dyn ResourceFetcher>,
     size: usize,
     viewer_stylesheet: PathBuf,
     user_stylesheet: PathBuf,
@@ -48,6 +49,13 @@ impl ResourceFetcher for PathBuf {
     }
 }
 
+impl ResourceFetcher for FxHashMap<String, Vec<u8>> {
+    fn fetch(&mut self, name: &str) -> Result<Vec<u8>, Error> {
+        self.get(name).cloned()
+            .ok_or_else(|| format_err!("Can't find resource {}.", name))
+    }
+}
+
 unsafe impl Send for HtmlDocument {}
 unsafe impl Sync for HtmlDocument {}
 
@@ -65,7 +73,7 @@ impl HtmlDocument {
             content,
             engine: Engine::new(),
             pages: Vec::new(),
-            parent: parent.to_path_buf(),
+            fetcher: Box::new(parent.to_path_buf()),
             size,
             viewer_stylesheet: PathBuf::from(VIEWER_STYLESHEET),
             user_stylesheet: PathBuf::from(USER_STYLESHEET),
@@ -82,7 +90,7 @@ impl HtmlDocument {
             content,
             engine: Engine::new(),
             pages: Vec::new(),
-            parent: PathBuf::from(""),
+            fetcher: Box::new(PathBuf::from("")),
             size,
             viewer_stylesheet: PathBuf::from(VIEWER_STYLESHEET),
             user_stylesheet: PathBuf::from(USER_STYLESHEET),
@@ -117,6 +125,11 @@ impl HtmlDocument {
         self.pages.clear();
     }
 
+    pub fn set_resource_fetcher<F: ResourceFetcher + 'static>(&mut self, fetcher: F) {
+        self.fetcher = Box::new(fetcher);
+        self.pages.clear();
+    }
+
     #[inline]
     fn page_index(&mut self, offset: usize) -> Option<usize> {
         if self.pages.is_empty() {
@@ -189,7 +202,7 @@ impl HtmlDocument {
                         if child.tag_name() == Some("link") && child.attr("rel") == Some("stylesheet") {
                             if let Some(href) = child.attr("href") {
                                 if let Some(name) = spine_dir.join(href).normalize().to_str() {
-                                    if let Ok(buf) = self.parent.fetch(name) {
+                                    if let Ok(buf) = self.fetcher.fetch(name) {
                                         if let Ok(text) = String::from_utf8(buf) {
                                             let (mut css, _) = CssParser::new(&text).parse(RuleKind::Document);
                                             stylesheet.append(&mut css);
@@ -242,7 +255,7 @@ impl HtmlDocument {
 
         pages.push(Vec::new());
 
-        self.engine.build_display_list(&self.content, &style, &loop_context, &stylesheet, &root_data, &mut self.parent, &mut draw_state, &mut pages);
+        self.engine.build_display_list(&self.content, &style, &loop_context, &stylesheet, &root_data, self.fetcher.as_mut(), &mut draw_state, &mut pages);
 
         pages.retain(|page| !page.is_empty());
 
@@ -371,7 +384,7 @@ impl Document for HtmlDocument {
         let offset = self.resolve_location(loc)?;
         let page_index = self.page_index(offset)?;
         let page = self.pages[page_index].clone();
-        let pixmap = self.engine.render_page(&page, &mut self.parent);
+        let pixmap = self.engine.render_page(&page, self.fetcher.as_mut());
 
         Some((pixmap, offset))
     }
@@ -401,6 +414,10 @@ impl Document for HtmlDocument {
         self.pages.clear();
     }
 
+    fn set_dithering(&mut self, dithering: Option<(DitherMode, u8)>) {
+        self.engine.dithering = dithering;
+    }
+
     fn title(&self) -> Option<String> {
         self.content.find("head")
             .and_then(Node::children)
@@ -2,12 +2,18 @@ pub mod djvu;
 pub mod pdf;
 pub mod epub;
 pub mod html;
+pub mod reflow;
+pub mod columns;
+pub mod autocrop;
+pub mod enhance;
+pub mod anchor;
 
 mod djvulibre_sys;
 mod mupdf_sys;
 
 use std::path::Path;
 use std::ffi::OsStr;
+use std::collections::BTreeMap;
 use fxhash::FxHashSet;
 use lazy_static::lazy_static;
 use unicode_normalization::UnicodeNormalization;
@@ -17,7 +23,7 @@ use self::djvu::DjvuOpener;
 use self::pdf::PdfOpener;
 use self::epub::EpubDocument;
 use crate::geom::{Boundary, CycleDir};
-use crate::metadata::{TextAlign};
+use crate::metadata::{TextAlign, DitherMode};
 use crate::framebuffer::Pixmap;
 
 pub const BYTES_PER_PAGE: f64 = 2048.0;
@@ -101,10 +107,39 @@ pub trait Document: Send+Sync {
 
     fn is_reflowable(&self) -> bool;
 
+    fn is_protected(&self) -> bool {
+        false
+    }
+
+    fn authenticate(&mut self, _password: &str) -> bool {
+        true
+    }
+
     fn has_synthetic_page_numbers(&self) -> bool {
         false
     }
 
+    // Bounding boxes of the images of the given page.
+    fn images(&mut self, _loc: Location) -> Option<(Vec<Boundary>, usize)> {
+        None
+    }
+
+    // Dithering, and number of gray levels, applied to the images of reflowable documents.
+    fn set_dithering(&mut self, _dithering: Option<(DitherMode, u8)>) {
+    }
+
+    // Loads and saves the page boundaries computed by the layout of reflowable documents.
+    fn load_pagination(&mut self, _path: &Path) {
+    }
+
+    fn save_pagination(&mut self, _path: &Path) {
+    }
+
+    // Page labels defined by the document, indexed by page.
+    fn page_labels(&mut self) -> Option<BTreeMap<usize, String>> {
+        None
+    }
+
     fn resolve_location(&mut self, loc: Location) -> Option<usize> {
         if self.pages_count() == 0 {
             return None;
@@ -7,6 +7,14 @@ pub const FZ_VERSION: &str = "1.17.0";
 
 pub const FZ_META_INFO_AUTHOR: &str = "info:Author";
 pub const FZ_META_INFO_TITLE: &str = "info:Title";
+pub const FZ_META_INFO_SUBJECT: &str = "info:Subject";
+pub const FZ_META_INFO_KEYWORDS: &str = "info:Keywords";
+pub const FZ_META_INFO_CREATIONDATE: &str = "info:CreationDate";
+
+pub const PDF_ANNOT_SQUARE: libc::c_int = 4;
+pub const PDF_ANNOT_HIGHLIGHT: libc::c_int = 8;
+pub const PDF_ANNOT_UNDERLINE: libc::c_int = 9;
+pub const PDF_ANNOT_STRIKE_OUT: libc::c_int = 11;
 
 pub const FZ_TEXT_PRESERVE_LIGATURES: libc::c_int = 1;
 pub const FZ_TEXT_PRESERVE_WHITESPACE: libc::c_int = 2;
@@ -31,6 +39,7 @@ pub enum FzCookie {}
 pub enum FzStoreDropFn {}
 pub enum FzSeparations {}
 pub enum FzImage {}
+pub enum FzBuffer {}
 
 #[link(name="mupdf")]
 #[link(name="mupdf_wrapper", kind="static")]
@@ -48,7 +57,15 @@ extern {
     pub fn fz_drop_stream(ctx: *mut FzContext, stream: *mut FzStream);
     pub fn mp_count_pages(ctx: *mut FzContext, doc: *mut FzDocument) -> libc::c_int;
     pub fn fz_lookup_metadata(ctx: *mut FzContext, doc: *mut FzDocument, key: *const libc::c_char, buf: *mut libc::c_char, size: libc::c_int) -> libc::c_int;
+    pub fn mp_load_page_labels(ctx: *mut FzContext, doc: *mut FzDocument, labels: *mut MpPageLabel, size: libc::c_int) -> libc::c_int;
+    pub fn mp_add_markup_annotation(ctx: *mut FzContext, doc: *mut FzDocument, page_idx: libc::c_int, kind: libc::c_int, quads: *const FzQuad, count: libc::c_int, gray: libc::c_float, contents: *const libc::c_char, name: *const libc::c_char) -> libc::c_int;
+    pub fn mp_remove_named_annotations(ctx: *mut FzContext, doc: *mut FzDocument, page_idx: libc::c_int, prefix: *const libc::c_char) -> libc::c_int;
+    pub fn mp_save_document(ctx: *mut FzContext, doc: *mut FzDocument, path: *const libc::c_char, incremental: libc::c_int) -> libc::c_int;
+    pub fn mp_load_xmp_metadata(ctx: *mut FzContext, doc: *mut FzDocument) -> *mut FzBuffer;
+    pub fn fz_buffer_storage(ctx: *mut FzContext, buf: *mut FzBuffer, datap: *mut *mut libc::c_uchar) -> libc::size_t;
+    pub fn fz_drop_buffer(ctx: *mut FzContext, buf: *mut FzBuffer);
     pub fn fz_needs_password(ctx: *mut FzContext, doc: *mut FzDocument) -> libc::c_int;
+    pub fn fz_authenticate_password(ctx: *mut FzContext, doc: *mut FzDocument, password: *const libc::c_char) -> libc::c_int;
     pub fn fz_is_document_reflowable(ctx: *mut FzContext, doc: *mut FzDocument) -> libc::c_int;
     pub fn fz_layout_document(ctx: *mut FzContext, doc: *mut FzDocument, w: libc::c_float, h: libc::c_float, em: libc::c_float);
     pub fn mp_load_outline(ctx: *mut FzContext, doc: *mut FzDocument) -> *mut FzOutline;
@@ -94,17 +111,17 @@ impl Default for FzRect {
 #[repr(C)]
 #[derive(Debug, Copy, Clone)]
 pub struct FzPoint {
-    x: libc::c_float,
-    y: libc::c_float,
+    pub x: libc::c_float,
+    pub y: libc::c_float,
 }
 
 #[repr(C)]
 #[derive(Debug, Copy, Clone)]
 pub struct FzQuad {
-    ul: FzPoint,
-    ur: FzPoint,
-    ll: FzPoint,
-    lr: FzPoint,
+    pub ul: FzPoint,
+    pub ur: FzPoint,
+    pub ll: FzPoint,
+    pub lr: FzPoint,
 }
 
 #[derive(Copy, Clone)]
@@ -126,7 +143,7 @@ pub struct FzStorable {
 
 #[repr(C)]
 pub struct FzTextOptions {
-    flags: libc::c_int,
+    pub flags: libc::c_int,
 }
 
 #[repr(C)]
@@ -155,6 +172,21 @@ impl Default for FzMatrix {
     }
 }
 
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct MpPageLabel {
+    pub index: libc::c_int,
+    pub style: libc::c_int,
+    pub start: libc::c_int,
+    pub prefix: [libc::c_char; 64],
+}
+
+impl Default for MpPageLabel {
+    fn default() -> MpPageLabel {
+        unsafe { mem::zeroed() }
+    }
+}
+
 #[repr(C)]
 pub struct FzLink {
 	refs: libc::c_int,
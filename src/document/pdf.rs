@@ -7,9 +7,11 @@ use std::rc::Rc;
 use std::path::Path;
 use std::io::Read;
 use std::fs::File;
+use std::collections::BTreeMap;
 use std::ffi::{CString, CStr};
 use std::os::unix::ffi::OsStrExt;
-use anyhow::Error;
+use anyhow::{Error, format_err};
+use septem::Roman;
 use super::{Document, Location, TextLocation, BoundedText, TocEntry};
 use super::{chapter, chapter_relative};
 use crate::metadata::TextAlign;
@@ -17,6 +19,17 @@ use crate::geom::{Boundary, CycleDir};
 use crate::unit::pt_to_px;
 use crate::framebuffer::Pixmap;
 
+impl From<Boundary> for FzQuad {
+    fn from(rect: Boundary) -> FzQuad {
+        FzQuad {
+            ul: FzPoint { x: rect.min.x, y: rect.min.y },
+            ur: FzPoint { x: rect.max.x, y: rect.min.y },
+            ll: FzPoint { x: rect.min.x, y: rect.max.y },
+            lr: FzPoint { x: rect.max.x, y: rect.max.y },
+        }
+    }
+}
+
 impl Into<Boundary> for FzRect {
     fn into(self) -> Boundary {
         Boundary {
@@ -26,6 +39,18 @@ impl Into<Boundary> for FzRect {
     }
 }
 
+// The prefix of the names of the annotations added by `add_markup`.
+pub const MARKUP_NAME_PREFIX: &str = "plato-";
+
+// The kinds of annotations that mark text. The gray level of highlights is between 0 and 1.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum PdfMarkup {
+    Highlight(f32),
+    Underline,
+    StrikeOut,
+    Square,
+}
+
 struct PdfContext(*mut FzContext);
 
 pub struct PdfOpener(Rc<PdfContext>);
@@ -131,6 +156,52 @@ impl PdfDocument {
         }
     }
 
+    // The name of the annotation is prefixed with `MARKUP_NAME_PREFIX`.
+    pub fn add_markup(&mut self, index: usize, markup: PdfMarkup, rects: &[Boundary], note: &str, name: &str) -> Result<(), Error> {
+        let quads: Vec<FzQuad> = rects.iter().map(|r| FzQuad::from(*r)).collect();
+        let contents = CString::new(note)?;
+        let name = CString::new(format!("{}{}", MARKUP_NAME_PREFIX, name))?;
+        let (kind, gray) = match markup {
+            PdfMarkup::Highlight(gray) => (PDF_ANNOT_HIGHLIGHT, gray),
+            PdfMarkup::Underline => (PDF_ANNOT_UNDERLINE, 0.0),
+            PdfMarkup::StrikeOut => (PDF_ANNOT_STRIKE_OUT, 0.0),
+            PdfMarkup::Square => (PDF_ANNOT_SQUARE, 0.0),
+        };
+        let ret = unsafe {
+            mp_add_markup_annotation(self.ctx.0, self.doc, index as libc::c_int, kind,
+                                     quads.as_ptr(), quads.len() as libc::c_int,
+                                     gray, contents.as_ptr(), name.as_ptr())
+        };
+        if ret < 0 {
+            Err(format_err!("Can't annotate page {}.", index + 1))
+        } else {
+            Ok(())
+        }
+    }
+
+    // Removes the annotations previously added by `add_markup`.
+    pub fn remove_markups(&mut self, index: usize) -> Result<(), Error> {
+        let prefix = CString::new(MARKUP_NAME_PREFIX)?;
+        let ret = unsafe {
+            mp_remove_named_annotations(self.ctx.0, self.doc, index as libc::c_int, prefix.as_ptr())
+        };
+        if ret < 0 {
+            Err(format_err!("Can't clean page {}.", index + 1))
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn save<P: AsRef<Path>>(&mut self, path: P, incremental: bool) -> Result<(), Error> {
+        let c_path = CString::new(path.as_ref().as_os_str().as_bytes())?;
+        let ret = unsafe { mp_save_document(self.ctx.0, self.doc, c_path.as_ptr(), incremental as libc::c_int) };
+        if ret < 0 {
+            Err(format_err!("Can't save {}.", path.as_ref().display()))
+        } else {
+            Ok(())
+        }
+    }
+
     fn walk_toc(outline: *mut FzOutline, index: &mut usize) -> Vec<TocEntry> {
         unsafe {
             let mut vec = Vec::new();
@@ -153,8 +224,42 @@ impl PdfDocument {
         }
     }
 
-    pub fn is_protected(&self) -> bool {
-        unsafe { fz_needs_password(self.ctx.0, self.doc) == 1 }
+    pub fn subject(&self) -> Option<String> {
+        self.metadata(FZ_META_INFO_SUBJECT)
+    }
+
+    pub fn keywords(&self) -> Option<String> {
+        self.metadata(FZ_META_INFO_KEYWORDS)
+    }
+
+    // The creation date is formatted as `D:YYYYMMDDHHmmSSOHH'mm`.
+    pub fn year(&self) -> Option<String> {
+        self.metadata(FZ_META_INFO_CREATIONDATE).and_then(|date| {
+            let year = date.trim_start_matches("D:").chars().take(4).collect::<String>();
+            if year.len() == 4 && year.chars().all(|c| c.is_ascii_digit()) {
+                Some(year)
+            } else {
+                None
+            }
+        })
+    }
+
+    pub fn xmp_metadata(&self) -> Option<String> {
+        unsafe {
+            let buf = mp_load_xmp_metadata(self.ctx.0, self.doc);
+            if buf.is_null() {
+                return None;
+            }
+            let mut data = ptr::null_mut();
+            let len = fz_buffer_storage(self.ctx.0, buf, &mut data);
+            let text = if data.is_null() {
+                None
+            } else {
+                Some(String::from_utf8_lossy(slice::from_raw_parts(data, len as usize)).into_owned())
+            };
+            fz_drop_buffer(self.ctx.0, buf);
+            text
+        }
     }
 }
 
@@ -222,6 +327,11 @@ impl Document for PdfDocument {
         self.page(index).and_then(|page| page.links()).map(|links| (links, index))
     }
 
+    fn images(&mut self, loc: Location) -> Option<(Vec<Boundary>, usize)> {
+        let index = self.resolve_location(loc)?;
+        self.page(index).and_then(|page| page.images()).map(|images| (images, index))
+    }
+
     fn title(&self) -> Option<String> {
         self.metadata(FZ_META_INFO_TITLE)
     }
@@ -234,6 +344,54 @@ impl Document for PdfDocument {
         unsafe { fz_is_document_reflowable(self.ctx.0, self.doc) == 1 }
     }
 
+    fn page_labels(&mut self) -> Option<BTreeMap<usize, String>> {
+        let mut ranges = vec![MpPageLabel::default(); 32];
+        let mut count = unsafe { mp_load_page_labels(self.ctx.0, self.doc, ranges.as_mut_ptr(), ranges.len() as libc::c_int) } as usize;
+
+        if count > ranges.len() {
+            ranges = vec![MpPageLabel::default(); count];
+            count = unsafe { mp_load_page_labels(self.ctx.0, self.doc, ranges.as_mut_ptr(), ranges.len() as libc::c_int) } as usize;
+        }
+
+        ranges.truncate(count);
+        ranges.sort_by_key(|r| r.index);
+
+        let pages_count = self.pages_count();
+        let mut labels = BTreeMap::new();
+
+        for (i, range) in ranges.iter().enumerate() {
+            let start_index = range.index.max(0) as usize;
+            let end_index = ranges.get(i+1).map_or(pages_count, |r| (r.index.max(0) as usize).min(pages_count));
+            let prefix = unsafe { CStr::from_ptr(range.prefix.as_ptr()).to_string_lossy().into_owned() };
+            for index in start_index..end_index {
+                let number = range.start.max(1) as u32 + (index - start_index) as u32;
+                let label = format!("{}{}", prefix, page_number(number, range.style as u8));
+                if !label.is_empty() {
+                    labels.insert(index, label);
+                }
+            }
+        }
+
+        // Labels that match the page numbers don't carry any information.
+        if labels.is_empty() || labels.iter().all(|(index, label)| *label == (index + 1).to_string()) {
+            return None;
+        }
+
+        Some(labels)
+    }
+
+    fn is_protected(&self) -> bool {
+        unsafe { fz_needs_password(self.ctx.0, self.doc) == 1 }
+    }
+
+    fn authenticate(&mut self, password: &str) -> bool {
+        let password = match CString::new(password) {
+            Ok(password) => password,
+            Err(_) => return false,
+        };
+        unsafe { fz_authenticate_password(self.ctx.0, self.doc, password.as_ptr()) != 0 }
+    }
+
     fn layout(&mut self, width: u32, height: u32, font_size: f32, dpi: u16) {
         let em = pt_to_px(font_size, dpi);
         unsafe {
@@ -257,6 +415,21 @@ impl Document for PdfDocument {
     }
 }
 
+// Formats a page number according to a page label numbering style.
+fn page_number(number: u32, style: u8) -> String {
+    match style {
+        b'D' => number.to_string(),
+        b'R' => Roman::from(number).map(|r| r.to_uppercase()).unwrap_or_else(|_| number.to_string()),
+        b'r' => Roman::from(number).map(|r| r.to_lowercase()).unwrap_or_else(|_| number.to_string()),
+        b'A' | b'a' => {
+            let base = if style == b'A' { b'A' } else { b'a' };
+            let letter = (base + ((number - 1) % 26) as u8) as char;
+            letter.to_string().repeat(1 + (number as usize - 1) / 26)
+        },
+        _ => String::new(),
+    }
+}
+
 impl<'a> PdfPage<'a> {
     pub fn lines(&self) -> Option<Vec<BoundedText>> {
         unsafe {
@@ -352,6 +525,28 @@ impl<'a> PdfPage<'a> {
         }
     }
 
+    pub fn images(&self) -> Option<Vec<Boundary>> {
+        unsafe {
+            let mut images = Vec::new();
+            let opts = FzTextOptions { flags: FZ_TEXT_PRESERVE_IMAGES };
+            let tp = mp_new_stext_page_from_page(self.ctx.0, self.page, &opts);
+            if tp.is_null() {
+                return None;
+            }
+            let mut block = (*tp).first_block;
+
+            while !block.is_null() {
+                if (*block).kind == FZ_PAGE_BLOCK_IMAGE {
+                    images.push((*block).bbox.into());
+                }
+                block = (*block).next;
+            }
+
+            fz_drop_stext_page(self.ctx.0, tp);
+            Some(images)
+        }
+    }
+
     pub fn links(&self) -> Option<Vec<BoundedText>> {
         unsafe {
             let links = mp_load_links(self.ctx.0, self.page);
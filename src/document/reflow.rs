@@ -0,0 +1,660 @@
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use fxhash::{FxHashMap, FxHashSet};
+use anyhow::Error;
+use super::{Document, Location, TextLocation, BoundedText, TocEntry};
+use super::html::HtmlDocument;
+use crate::framebuffer::Pixmap;
+use crate::metadata::{TextAlign, DitherMode};
+use crate::geom::{Boundary, CycleDir};
+use crate::helpers::escape_html;
+
+// Scale at which the images are extracted from the pages.
+const IMAGE_SCALE: f32 = 2.0;
+// Images smaller than this (in points) are ignored.
+const MIN_IMAGE_SIZE: f32 = 24.0;
+// Images covering more than this fraction of a page containing text are considered backgrounds.
+const MAX_IMAGE_AREA: f32 = 0.8;
+// Fraction of the page height, at the top and the bottom, where running headers and footers live.
+const RUNNING_ZONE: f32 = 0.08;
+// Minimum number of pages a line must appear on to be considered a running header or footer.
+const RUNNING_REPEATS: usize = 3;
+// Vertical gap, relative to the line height, that separates two paragraphs.
+const PARAGRAPH_GAP: f32 = 0.7;
+// Indentation, relative to the line height, of the first line of a paragraph.
+const PARAGRAPH_INDENT: f32 = 0.5;
+// Height ratio, relative to the regular line height, of the lines of a heading.
+const HEADING_RATIO: f32 = 1.25;
+
+// A reflowable view of a fixed-layout document, built from the text it contains.
+pub struct ReflowDocument {
+    html: HtmlDocument,
+    // Offsets of the words in the generated HTML, and their locations in the original document.
+    anchors: Vec<(usize, TextLocation)>,
+    toc: Option<Vec<TocEntry>>,
+}
+
+struct Line {
+    rect: Boundary,
+    words: Vec<BoundedText>,
+}
+
+impl Line {
+    fn text(&self) -> String {
+        self.words.iter().map(|w| w.text.as_str()).collect::<Vec<&str>>().join(" ")
+    }
+
+    fn last_char(&self) -> Option<char> {
+        self.words.last().and_then(|w| w.text.chars().last())
+    }
+}
+
+enum Item {
+    Word(BoundedText),
+    Anchor(usize),
+}
+
+#[derive(Default)]
+struct Builder {
+    buf: String,
+    anchors: Vec<(usize, TextLocation)>,
+    resources: FxHashMap<String, Vec<u8>>,
+    paragraph: Vec<Item>,
+    heading: bool,
+    figures: Vec<(String, f32)>,
+}
+
+impl Builder {
+    fn push_line(&mut self, line: Line) {
+        let mut words = line.words.into_iter();
+
+        // Join the word hyphenated at the end of the previous line.
+        if let Some(Item::Word(last)) = self.paragraph.last_mut() {
+            if last.text.chars().count() > 1 && (last.text.ends_with('-') || last.text.ends_with('\u{AD}')) &&
+               line_starts_lowercase(words.as_slice()) {
+                if let Some(next) = words.next() {
+                    last.text.pop();
+                    last.text.push_str(&next.text);
+                }
+            }
+        }
+
+        self.paragraph.extend(words.map(Item::Word));
+    }
+
+    fn push_anchor(&mut self, index: usize) {
+        if self.paragraph.is_empty() {
+            self.buf.push_str(&format!("<div id=\"page-{}\"></div>\n", index));
+        } else {
+            self.paragraph.push(Item::Anchor(index));
+        }
+    }
+
+    fn flush(&mut self) {
+        if !self.paragraph.is_empty() {
+            let tag = if self.heading { "h2" } else { "p" };
+            self.buf.push_str(&format!("<{}>", tag));
+            let mut first = true;
+            for item in self.paragraph.drain(..) {
+                match item {
+                    Item::Word(word) => {
+                        if !first {
+                            self.buf.push(' ');
+                        }
+                        self.anchors.push((self.buf.len(), word.location));
+                        self.buf.push_str(&escape_html(&word.text));
+                        first = false;
+                    },
+                    Item::Anchor(index) => {
+                        self.buf.push_str(&format!("<span id=\"page-{}\"></span>", index));
+                    },
+                }
+            }
+            self.buf.push_str(&format!("</{}>\n", tag));
+        }
+
+        for (name, width) in self.figures.drain(..) {
+            self.buf.push_str(&format!("<div class=\"figure\"><img src=\"{}\" style=\"width: {:.0}%\"/></div>\n", name, width));
+        }
+    }
+}
+
+impl ReflowDocument {
+    // The document is only locked while a page is processed, so that it stays usable meanwhile.
+    // Returns `None` if `running` is cleared before the end.
+    pub fn new(doc: &Mutex<Box<dyn Document>>, running: &AtomicBool) -> Option<ReflowDocument> {
+        let pages_count = doc.lock().unwrap().pages_count();
+        let mut pages = Vec::with_capacity(pages_count);
+        let mut heights = Vec::with_capacity(pages_count);
+
+        for index in 0..pages_count {
+            if !running.load(Ordering::Relaxed) {
+                return None;
+            }
+            let mut doc = doc.lock().unwrap();
+            pages.push(page_lines(doc.as_mut(), index));
+            heights.push(doc.dims(index).map_or(1.0, |(_, h)| h));
+        }
+
+        let running_keys = running_lines(&heights, &pages);
+        let mut builder = Builder::default();
+        let mut previous: Option<Line> = None;
+
+        builder.buf.push_str("<html><head>");
+        {
+            let doc = doc.lock().unwrap();
+            if let Some(title) = doc.title() {
+                builder.buf.push_str(&format!("<title>{}</title>", escape_html(&title)));
+            }
+            if let Some(author) = doc.author() {
+                builder.buf.push_str(&format!("<meta name=\"author\" content=\"{}\"/>", escape_html(&author).replace('"', "&quot;")));
+            }
+        }
+        builder.buf.push_str("</head><body>\n");
+
+        for (index, lines) in pages.into_iter().enumerate() {
+            if !running.load(Ordering::Relaxed) {
+                return None;
+            }
+            let mut doc = doc.lock().unwrap();
+            let (width, height) = doc.dims(index).unwrap_or((1.0, 1.0));
+            let lines: Vec<Line> = lines.into_iter()
+                                        .filter(|line| !is_running(line, height, &running_keys))
+                                        .collect();
+            let line_height = median_height(&lines);
+
+            builder.push_anchor(index);
+
+            if let Some(images) = doc.images(Location::Exact(index)).map(|(images, _)| images) {
+                let mut pixmap = None;
+                for (i, rect) in images.iter().enumerate() {
+                    if rect.width() < MIN_IMAGE_SIZE || rect.height() < MIN_IMAGE_SIZE ||
+                       (!lines.is_empty() && rect.width() * rect.height() > MAX_IMAGE_AREA * width * height) {
+                        continue;
+                    }
+                    if pixmap.is_none() {
+                        pixmap = doc.pixmap(Location::Exact(index), IMAGE_SCALE).map(|(pixmap, _)| pixmap);
+                    }
+                    if let Some(buf) = pixmap.as_ref().and_then(|p| {
+                        encode_png(&crop(p, rect, IMAGE_SCALE))
+                            .map_err(|e| eprintln!("Can't encode image: {}.", e)).ok()
+                    }) {
+                        let name = format!("page-{}-{}.png", index, i);
+                        builder.resources.insert(name.clone(), buf);
+                        builder.figures.push((name, (100.0 * rect.width() / width).min(100.0)));
+                    }
+                }
+            }
+
+            for line in lines {
+                let heading = line.rect.height() > HEADING_RATIO * line_height;
+                let is_break = previous.as_ref().map_or(true, |prev| {
+                    heading != builder.heading || is_paragraph_break(prev, &line, line_height)
+                });
+
+                if is_break {
+                    builder.flush();
+                    builder.heading = heading;
+                }
+
+                previous = Some(Line { rect: line.rect, words: line.words.last().cloned().into_iter().collect() });
+                builder.push_line(line);
+            }
+        }
+
+        builder.flush();
+        builder.buf.push_str("</body></html>");
+
+        let mut html = HtmlDocument::new_from_memory(&builder.buf);
+        html.set_resource_fetcher(builder.resources);
+
+        let toc = doc.lock().unwrap().toc().map(|toc| reflow_toc(&toc));
+
+        Some(ReflowDocument {
+            html,
+            anchors: builder.anchors,
+            toc,
+        })
+    }
+
+    // Returns the location, in the original document, of the word at the given offset.
+    fn static_location(&self, offset: usize) -> Option<TextLocation> {
+        let index = match self.anchors.binary_search_by_key(&offset, |(o, _)| *o) {
+            Ok(index) => index,
+            Err(0) => return None,
+            Err(index) => index - 1,
+        };
+        Some(self.anchors[index].1)
+    }
+
+    fn toc_offsets(&mut self, toc: &[TocEntry], offsets: &mut Vec<(usize, usize)>) {
+        for entry in toc {
+            if let Some(offset) = self.html.resolve_location(entry.location.clone()) {
+                offsets.push((offset, entry.index));
+            }
+            self.toc_offsets(&entry.children, offsets);
+        }
+    }
+}
+
+fn page_lines(doc: &mut dyn Document, index: usize) -> Vec<Line> {
+    let lines = doc.lines(Location::Exact(index)).map(|(lines, _)| lines).unwrap_or_default();
+    let words = doc.words(Location::Exact(index)).map(|(words, _)| words).unwrap_or_default();
+    let mut result: Vec<Line> = lines.into_iter()
+                                     .map(|line| Line { rect: line.rect, words: Vec::new() })
+                                     .collect();
+    let mut cursor = 0;
+
+    for word in words {
+        if let Some(i) = (cursor..result.len()).find(|&i| result[i].rect.overlaps(&word.rect)) {
+            cursor = i;
+            result[i].words.push(word);
+        } else {
+            cursor = result.len();
+            result.push(Line { rect: word.rect, words: vec![word] });
+        }
+    }
+
+    result.retain(|line| !line.words.is_empty());
+    result
+}
+
+// Returns the value of the given lowercase roman numeral, if it's written in its canonical form.
+fn roman_value(text: &str) -> Option<usize> {
+    const NUMERALS: [(usize, &str); 13] = [(1000, "m"), (900, "cm"), (500, "d"), (400, "cd"),
+                                           (100, "c"), (90, "xc"), (50, "l"), (40, "xl"),
+                                           (10, "x"), (9, "ix"), (5, "v"), (4, "iv"), (1, "i")];
+    let mut rest = text;
+    let mut value = 0;
+
+    for &(n, numeral) in NUMERALS.iter() {
+        while rest.starts_with(numeral) {
+            rest = &rest[numeral.len()..];
+            value += n;
+        }
+    }
+
+    if !rest.is_empty() || value == 0 {
+        return None;
+    }
+
+    // Reject the non-canonical forms, e.g. *iiii* or *viv*.
+    let mut canonical = String::new();
+    let mut n = value;
+    for &(v, numeral) in NUMERALS.iter() {
+        while n >= v {
+            canonical.push_str(numeral);
+            n -= v;
+        }
+    }
+
+    if canonical == text {
+        Some(value)
+    } else {
+        None
+    }
+}
+
+fn running_key(line: &Line) -> String {
+    let text = line.text().to_lowercase();
+    // Page numbers, be they arabic or roman.
+    if line.words.len() == 1 && roman_value(&text).is_some() {
+        return String::new();
+    }
+    text.chars().filter(|c| !c.is_numeric() && !c.is_whitespace()).collect()
+}
+
+fn in_running_zone(line: &Line, height: f32) -> bool {
+    line.rect.max.y < RUNNING_ZONE * height || line.rect.min.y > (1.0 - RUNNING_ZONE) * height
+}
+
+fn running_lines(heights: &[f32], pages: &[Vec<Line>]) -> FxHashSet<String> {
+    let mut counts: FxHashMap<String, usize> = FxHashMap::default();
+
+    for (lines, &height) in pages.iter().zip(heights) {
+        let keys: FxHashSet<String> = lines.iter()
+                                           .filter(|line| in_running_zone(line, height))
+                                           .map(running_key)
+                                           .collect();
+        for key in keys {
+            *counts.entry(key).or_insert(0) += 1;
+        }
+    }
+
+    counts.into_iter()
+          .filter(|(key, count)| key.is_empty() || *count >= RUNNING_REPEATS)
+          .map(|(key, _)| key)
+          .collect()
+}
+
+fn is_running(line: &Line, height: f32, running: &FxHashSet<String>) -> bool {
+    in_running_zone(line, height) && running.contains(&running_key(line))
+}
+
+fn median_height(lines: &[Line]) -> f32 {
+    let mut heights: Vec<f32> = lines.iter().map(|line| line.rect.height()).collect();
+    heights.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    heights.get(heights.len() / 2).cloned().unwrap_or(0.0)
+}
+
+fn line_starts_lowercase(words: &[BoundedText]) -> bool {
+    words.first().and_then(|w| w.text.chars().next())
+         .map_or(false, char::is_lowercase)
+}
+
+fn is_paragraph_break(prev: &Line, line: &Line, line_height: f32) -> bool {
+    let ends_sentence = prev.last_char().map_or(false, |c| ".!?:\"”".contains(c));
+    let below = line.rect.min.y > prev.rect.min.y + 0.5 * line_height;
+    let same_column = line.rect.min.x < prev.rect.max.x + line_height &&
+                      line.rect.max.x > prev.rect.min.x - line_height;
+
+    if below && same_column {
+        line.rect.min.y - prev.rect.max.y > PARAGRAPH_GAP * line_height ||
+        line.rect.min.x - prev.rect.min.x > PARAGRAPH_INDENT * line_height ||
+        (ends_sentence && line.rect.max.x - prev.rect.max.x > 2.0 * line_height)
+    } else if !below && line.rect.min.x >= prev.rect.max.x {
+        // Continuation of the same line.
+        false
+    } else {
+        // New column or new page.
+        ends_sentence
+    }
+}
+
+fn crop(pixmap: &Pixmap, rect: &Boundary, scale: f32) -> Pixmap {
+    let x0 = ((rect.min.x * scale).floor().max(0.0) as u32).min(pixmap.width);
+    let y0 = ((rect.min.y * scale).floor().max(0.0) as u32).min(pixmap.height);
+    let x1 = ((rect.max.x * scale).ceil().max(0.0) as u32).min(pixmap.width);
+    let y1 = ((rect.max.y * scale).ceil().max(0.0) as u32).min(pixmap.height);
+    let width = x1 - x0;
+    let height = y1 - y0;
+    let mut data = Vec::with_capacity((width * height) as usize);
+
+    for y in y0..y1 {
+        let start = (y * pixmap.width + x0) as usize;
+        data.extend_from_slice(&pixmap.data[start..start + width as usize]);
+    }
+
+    Pixmap { width, height, data }
+}
+
+fn encode_png(pixmap: &Pixmap) -> Result<Vec<u8>, Error> {
+    let mut buf = Vec::new();
+    {
+        let mut encoder = png::Encoder::new(&mut buf, pixmap.width, pixmap.height);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.set_color(png::ColorType::Grayscale);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&pixmap.data)?;
+    }
+    Ok(buf)
+}
+
+fn reflow_toc(toc: &[TocEntry]) -> Vec<TocEntry> {
+    toc.iter().map(|entry| {
+        let location = match entry.location {
+            Location::Exact(index) => Location::Uri(format!("#page-{}", index)),
+            ref location => location.clone(),
+        };
+        TocEntry {
+            title: entry.title.clone(),
+            location,
+            index: entry.index,
+            children: reflow_toc(&entry.children),
+        }
+    }).collect()
+}
+
+fn find_entry(toc: &[TocEntry], index: usize) -> Option<&TocEntry> {
+    for entry in toc {
+        if entry.index == index {
+            return Some(entry);
+        }
+        if let Some(entry) = find_entry(&entry.children, index) {
+            return Some(entry);
+        }
+    }
+    None
+}
+
+impl Document for ReflowDocument {
+    #[inline]
+    fn dims(&self, index: usize) -> Option<(f32, f32)> {
+        self.html.dims(index)
+    }
+
+    fn pages_count(&self) -> usize {
+        self.html.pages_count()
+    }
+
+    fn toc(&mut self) -> Option<Vec<TocEntry>> {
+        self.toc.clone()
+    }
+
+    fn chapter<'a>(&mut self, offset: usize, toc: &'a [TocEntry]) -> Option<&'a TocEntry> {
+        let next_offset = self.resolve_location(Location::Next(offset))
+                              .unwrap_or(usize::MAX);
+        let mut offsets = Vec::new();
+        self.toc_offsets(toc, &mut offsets);
+        offsets.into_iter()
+               .filter(|(o, _)| *o < next_offset)
+               .max_by_key(|(o, _)| *o)
+               .and_then(|(_, index)| find_entry(toc, index))
+    }
+
+    fn chapter_relative<'a>(&mut self, offset: usize, dir: CycleDir, toc: &'a [TocEntry]) -> Option<&'a TocEntry> {
+        let next_offset = self.resolve_location(Location::Next(offset))
+                              .unwrap_or(usize::MAX);
+        let mut offsets = Vec::new();
+        self.toc_offsets(toc, &mut offsets);
+        let start_offset = offsets.iter()
+                                  .filter(|(o, _)| *o < next_offset)
+                                  .map(|(o, _)| *o)
+                                  .max()
+                                  .unwrap_or(offset);
+        let entry = match dir {
+            CycleDir::Previous => offsets.into_iter()
+                                         .filter(|(o, _)| *o < start_offset)
+                                         .max_by_key(|(o, _)| *o),
+            CycleDir::Next => offsets.into_iter()
+                                     .filter(|(o, _)| *o >= next_offset)
+                                     .min_by_key(|(o, _)| *o),
+        };
+        entry.and_then(|(_, index)| find_entry(toc, index))
+    }
+
+    fn resolve_location(&mut self, loc: Location) -> Option<usize> {
+        self.html.resolve_location(loc)
+    }
+
+    fn words(&mut self, loc: Location) -> Option<(Vec<BoundedText>, usize)> {
+        let (mut words, offset) = self.html.words(loc)?;
+        for word in &mut words {
+            if let TextLocation::Dynamic(offset) = word.location {
+                if let Some(location) = self.static_location(offset) {
+                    word.location = location;
+                }
+            }
+        }
+        Some((words, offset))
+    }
+
+    fn lines(&mut self, loc: Location) -> Option<(Vec<BoundedText>, usize)> {
+        self.html.lines(loc)
+    }
+
+    fn links(&mut self, loc: Location) -> Option<(Vec<BoundedText>, usize)> {
+        self.html.links(loc)
+    }
+
+    fn pixmap(&mut self, loc: Location, scale: f32) -> Option<(Pixmap, usize)> {
+        self.html.pixmap(loc, scale)
+    }
+
+    fn layout(&mut self, width: u32, height: u32, font_size: f32, dpi: u16) {
+        self.html.layout(width, height, font_size, dpi);
+    }
+
+    fn set_text_align(&mut self, text_align: TextAlign) {
+        self.html.set_text_align(text_align);
+    }
+
+    fn set_font_family(&mut self, family_name: &str, search_path: &str) {
+        self.html.set_font_family(family_name, search_path);
+    }
+
+    fn set_margin_width(&mut self, width: i32) {
+        self.html.set_margin_width(width);
+    }
+
+    fn set_line_height(&mut self, line_height: f32) {
+        self.html.set_line_height(line_height);
+    }
+
+    fn set_dithering(&mut self, dithering: Option<(DitherMode, u8)>) {
+        self.html.set_dithering(dithering);
+    }
+
+    fn title(&self) -> Option<String> {
+        self.html.title()
+    }
+
+    fn author(&self) -> Option<String> {
+        self.html.author()
+    }
+
+    fn metadata(&self, key: &str) -> Option<String> {
+        self.html.metadata(key)
+    }
+
+    fn is_reflowable(&self) -> bool {
+        true
+    }
+
+    fn has_synthetic_page_numbers(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn word(text: &str, rect: Boundary, page: usize, index: usize) -> BoundedText {
+        BoundedText { text: text.to_string(), rect, location: TextLocation::Static(page, index) }
+    }
+
+    fn line(text: &str, rect: Boundary) -> Line {
+        let words = text.split_whitespace().enumerate()
+                        .map(|(i, t)| word(t, rect, 0, i))
+                        .collect();
+        Line { rect, words }
+    }
+
+    fn paragraph_text(builder: &Builder) -> Vec<&str> {
+        builder.paragraph.iter().filter_map(|item| match item {
+            Item::Word(word) => Some(word.text.as_str()),
+            Item::Anchor(_) => None,
+        }).collect()
+    }
+
+    #[test]
+    fn test_hyphen_joining() {
+        let mut builder = Builder::default();
+        builder.push_line(line("the recon-", bndr!(50.0, 50.0, 550.0, 60.0)));
+        builder.push_line(line("struction of Jean-", bndr!(50.0, 62.0, 550.0, 72.0)));
+        builder.push_line(line("Paul and -", bndr!(50.0, 74.0, 550.0, 84.0)));
+        builder.push_line(line("so on", bndr!(50.0, 86.0, 550.0, 96.0)));
+        assert_eq!(paragraph_text(&builder),
+                   vec!["the", "reconstruction", "of", "Jean-", "Paul", "and", "-", "so", "on"]);
+    }
+
+    #[test]
+    fn test_paragraph_break() {
+        let prev = line("the end of a line", bndr!(50.0, 50.0, 550.0, 60.0));
+        let next = line("and the next one", bndr!(50.0, 62.0, 550.0, 72.0));
+        assert!(!is_paragraph_break(&prev, &next, 10.0));
+        let gap = line("after a blank line", bndr!(50.0, 80.0, 550.0, 90.0));
+        assert!(is_paragraph_break(&prev, &gap, 10.0));
+        let indented = line("an indented line", bndr!(70.0, 62.0, 550.0, 72.0));
+        assert!(is_paragraph_break(&prev, &indented, 10.0));
+        let short = line("A short sentence.", bndr!(50.0, 50.0, 300.0, 60.0));
+        assert!(is_paragraph_break(&short, &next, 10.0));
+        let bottom = line("the end of a page", bndr!(50.0, 750.0, 550.0, 760.0));
+        let top = line("and the top of the next one", bndr!(50.0, 50.0, 550.0, 60.0));
+        assert!(!is_paragraph_break(&bottom, &top, 10.0));
+        let last = line("The end.", bndr!(50.0, 750.0, 200.0, 760.0));
+        assert!(is_paragraph_break(&last, &top, 10.0));
+    }
+
+    #[test]
+    fn test_roman_value() {
+        assert_eq!(roman_value("xiv"), Some(14));
+        assert_eq!(roman_value("mcmxcix"), Some(1999));
+        assert_eq!(roman_value("iiii"), None);
+        assert_eq!(roman_value("viv"), None);
+        assert_eq!(roman_value("civil"), None);
+        assert_eq!(roman_value("mild"), None);
+        assert_eq!(roman_value(""), None);
+    }
+
+    #[test]
+    fn test_running_lines() {
+        let heights = vec![800.0; 3];
+        let pages: Vec<Vec<Line>> = ["12", "xiv", "xv"].iter().map(|number| {
+            vec![line("A Tale of Two Cities", bndr!(50.0, 20.0, 550.0, 30.0)),
+                 line("It was the best of times.", bndr!(50.0, 100.0, 550.0, 110.0)),
+                 line(number, bndr!(290.0, 770.0, 310.0, 780.0))]
+        }).collect();
+        let running = running_lines(&heights, &pages);
+        for lines in &pages {
+            assert!(is_running(&lines[0], 800.0, &running));
+            assert!(!is_running(&lines[1], 800.0, &running));
+            assert!(is_running(&lines[2], 800.0, &running));
+        }
+        let civil = line("civil", bndr!(290.0, 770.0, 310.0, 780.0));
+        assert!(!is_running(&civil, 800.0, &running));
+        let numbers = line("iv ii", bndr!(290.0, 770.0, 310.0, 780.0));
+        assert!(!is_running(&numbers, 800.0, &running));
+    }
+
+    #[test]
+    fn test_static_location() {
+        let doc = ReflowDocument {
+            html: HtmlDocument::new_from_memory(""),
+            anchors: vec![(10, TextLocation::Static(0, 0)),
+                          (20, TextLocation::Static(0, 1)),
+                          (30, TextLocation::Static(1, 0))],
+            toc: None,
+        };
+        assert_eq!(doc.static_location(5), None);
+        assert_eq!(doc.static_location(10), Some(TextLocation::Static(0, 0)));
+        assert_eq!(doc.static_location(25), Some(TextLocation::Static(0, 1)));
+        assert_eq!(doc.static_location(100), Some(TextLocation::Static(1, 0)));
+    }
+
+    #[test]
+    fn test_reflow_toc() {
+        let toc = vec![TocEntry {
+            title: "One".to_string(),
+            location: Location::Exact(3),
+            index: 0,
+            children: vec![TocEntry {
+                title: "Two".to_string(),
+                location: Location::Uri("#note".to_string()),
+                index: 1,
+                children: Vec::new(),
+            }],
+        }];
+        let toc = reflow_toc(&toc);
+        match toc[0].location {
+            Location::Uri(ref uri) => assert_eq!(uri, "#page-3"),
+            _ => panic!("unexpected location"),
+        }
+        match toc[0].children[0].location {
+            Location::Uri(ref uri) => assert_eq!(uri, "#note"),
+            _ => panic!("unexpected location"),
+        }
+        assert_eq!(toc[0].children[0].index, 1);
+    }
+}
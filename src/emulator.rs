@@ -13,6 +13,7 @@ mod dictionary;
 mod document;
 mod library;
 mod metadata;
+mod export;
 mod settings;
 mod frontlight;
 mod lightsensor;
@@ -481,6 +482,9 @@ fn main() -> Result<(), Error> {
                                                   msg, &tx, &mut context);
                     view.children_mut().push(Box::new(notif) as Box<dyn View>);
                 },
+                Event::UpdateFingerprint(path, fp) => {
+                    context.library.update_fingerprint(path, fp);
+                },
                 Event::AddDocument(..) => {
                     if view.is::<Home>() {
                         view.handle_event(&evt, &tx, &mut bus, &mut context);
@@ -0,0 +1,277 @@
+use std::fs;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use chrono::{Local, DateTime};
+use serde::Serialize;
+use anyhow::{Error, format_err};
+use crate::document::{TextLocation, open};
+use crate::metadata::{Info, HighlightStyle};
+use crate::helpers::{datetime_format, escape_html};
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ExportFormat {
+    Markdown,
+    Json,
+    Html,
+}
+
+impl ExportFormat {
+    pub fn all() -> [ExportFormat; 3] {
+        [ExportFormat::Markdown, ExportFormat::Json, ExportFormat::Html]
+    }
+
+    pub fn from_name(name: &str) -> Option<ExportFormat> {
+        match name.to_lowercase().as_str() {
+            "markdown" | "md" => Some(ExportFormat::Markdown),
+            "json" => Some(ExportFormat::Json),
+            "html" => Some(ExportFormat::Html),
+            _ => None,
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Markdown => "md",
+            ExportFormat::Json => "json",
+            ExportFormat::Html => "html",
+        }
+    }
+}
+
+impl fmt::Display for ExportFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ExportFormat::Markdown => write!(f, "Markdown"),
+            ExportFormat::Json => write!(f, "JSON"),
+            ExportFormat::Html => write!(f, "HTML"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct ExportedAnnotation {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    chapter: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    page: Option<String>,
+    text: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    note: String,
+    style: HighlightStyle,
+    #[serde(with = "datetime_format")]
+    modified: DateTime<Local>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct ExportedDocument<'a> {
+    title: String,
+    #[serde(skip_serializing_if = "str::is_empty")]
+    author: &'a str,
+    path: &'a Path,
+    annotations: Vec<ExportedAnnotation>,
+}
+
+// Gathers the annotations of the document, sorted by location, with their chapters and pages.
+fn collect(home: &Path, info: &Info) -> Vec<ExportedAnnotation> {
+    let r = match info.reader.as_ref() {
+        Some(r) => r,
+        None => return Vec::new(),
+    };
+
+    let mut doc = open(home.join(&info.file.path));
+    let toc = doc.as_mut().and_then(|doc| doc.toc());
+    let page_labels = doc.as_mut().and_then(|doc| doc.page_labels()).unwrap_or_default();
+
+    let mut annotations = r.annotations.clone();
+    annotations.sort_by_key(|annot| annot.selection[0]);
+
+    annotations.into_iter().map(|annot| {
+        let start = annot.selection[0];
+        let chapter = doc.as_mut().zip(toc.as_ref())
+                         .and_then(|(doc, toc)| doc.chapter(start.location(), toc))
+                         .map(|chap| chap.title.clone());
+        let page = match start {
+            TextLocation::Static(page, _) => {
+                Some(r.page_name(page)
+                      .or_else(|| page_labels.get(&page).cloned())
+                      .unwrap_or_else(|| (page + 1).to_string()))
+            },
+            _ => None,
+        };
+        ExportedAnnotation {
+            chapter,
+            page,
+            text: annot.text.trim().to_string(),
+            note: annot.note,
+            style: annot.style,
+            modified: annot.modified,
+        }
+    }).collect()
+}
+
+fn location_line(annot: &ExportedAnnotation) -> String {
+    let mut parts = Vec::new();
+    if let Some(page) = annot.page.as_ref() {
+        parts.push(format!("p. {}", page));
+    }
+    parts.push(annot.style.to_string());
+    parts.push(annot.modified.format("%Y-%m-%d %H:%M").to_string());
+    parts.join(" · ")
+}
+
+fn to_markdown(doc: &ExportedDocument) -> String {
+    let mut buf = format!("# {}\n", doc.title);
+    if !doc.author.is_empty() {
+        buf.push_str(&format!("\n*{}*\n", doc.author));
+    }
+
+    let mut chapter = None;
+    for annot in &doc.annotations {
+        if annot.chapter.is_some() && annot.chapter != chapter {
+            chapter = annot.chapter.clone();
+            buf.push_str(&format!("\n## {}\n", chapter.as_ref().unwrap()));
+        }
+        buf.push('\n');
+        for line in annot.text.lines() {
+            buf.push_str(&format!("> {}\n", line));
+        }
+        if !annot.note.is_empty() {
+            buf.push_str(&format!("\n{}\n", annot.note));
+        }
+        buf.push_str(&format!("\n*{}*\n", location_line(annot)));
+    }
+
+    buf
+}
+
+fn style_class(style: HighlightStyle) -> &'static str {
+    match style {
+        HighlightStyle::Light => "light",
+        HighlightStyle::Medium => "medium",
+        HighlightStyle::Dark => "dark",
+        HighlightStyle::Underline => "underline",
+        HighlightStyle::StrikeThrough => "strike-through",
+        HighlightStyle::Boxed => "boxed",
+    }
+}
+
+fn to_html(doc: &ExportedDocument) -> String {
+    let title = escape_html(&doc.title);
+    let mut buf = format!("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+                           <title>{}</title>\n<style>\n\
+                           blockquote {{ margin: 1em 0 0.3em 0; padding-left: 1em; border-left: 3px solid #999; }}\n\
+                           .light span {{ background: #eee; }}\n\
+                           .medium span {{ background: #ccc; }}\n\
+                           .dark span {{ background: #999; }}\n\
+                           .underline span {{ text-decoration: underline; }}\n\
+                           .strike-through span {{ text-decoration: line-through; }}\n\
+                           .boxed span {{ border: 1px solid; }}\n\
+                           .note {{ margin: 0.3em 0 0 1em; font-style: italic; }}\n\
+                           .info {{ margin: 0.3em 0 0 0; font-size: 0.8em; color: #666; }}\n\
+                           </style>\n</head>\n<body>\n<h1>{}</h1>\n", title, title);
+    if !doc.author.is_empty() {
+        buf.push_str(&format!("<p class=\"author\">{}</p>\n", escape_html(doc.author)));
+    }
+
+    let mut chapter = None;
+    for annot in &doc.annotations {
+        if annot.chapter.is_some() && annot.chapter != chapter {
+            chapter = annot.chapter.clone();
+            buf.push_str(&format!("<h2>{}</h2>\n", escape_html(chapter.as_ref().unwrap())));
+        }
+        buf.push_str(&format!("<blockquote class=\"{}\"><span>{}</span></blockquote>\n",
+                              style_class(annot.style), escape_html(&annot.text)));
+        if !annot.note.is_empty() {
+            buf.push_str(&format!("<p class=\"note\">{}</p>\n", escape_html(&annot.note)));
+        }
+        buf.push_str(&format!("<p class=\"info\">{}</p>\n", escape_html(&location_line(annot))));
+    }
+
+    buf.push_str("</body>\n</html>\n");
+    buf
+}
+
+// Writes the annotations of the given document into the given directory.
+// Returns the path of the written file.
+pub fn export_annotations(home: &Path, info: &Info, format: ExportFormat, dir: &Path) -> Result<PathBuf, Error> {
+    let annotations = collect(home, info);
+
+    if annotations.is_empty() {
+        return Err(format_err!("No annotations to export."));
+    }
+
+    let doc = ExportedDocument {
+        title: info.title(),
+        author: &info.author,
+        path: &info.file.path,
+        annotations,
+    };
+
+    let content = match format {
+        ExportFormat::Markdown => to_markdown(&doc),
+        ExportFormat::Json => serde_json::to_string_pretty(&doc)?,
+        ExportFormat::Html => to_html(&doc),
+    };
+
+    // The exported files mirror the tree of the library, so that documents
+    // with the same name, in different directories, don't collide.
+    let name = info.file.path.file_name()
+                   .ok_or_else(|| format_err!("Invalid path: {}.", info.file.path.display()))?;
+    let path = dir.join(&info.file.path)
+                  .with_file_name(format!("{}.{}", name.to_string_lossy(), format.extension()));
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&path, content)?;
+
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn annotation(chapter: Option<&str>, page: Option<&str>, text: &str, note: &str) -> ExportedAnnotation {
+        ExportedAnnotation {
+            chapter: chapter.map(String::from),
+            page: page.map(String::from),
+            text: text.to_string(),
+            note: note.to_string(),
+            style: HighlightStyle::default(),
+            modified: Local.datetime_from_str("2020-10-19 09:30:00", datetime_format::FORMAT).unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_markdown() {
+        let doc = ExportedDocument {
+            title: "Walden".to_string(),
+            author: "Henry David Thoreau",
+            path: Path::new("walden.pdf"),
+            annotations: vec![annotation(Some("Economy"), Some("iv"), "Most men\nlead lives", ""),
+                              annotation(Some("Economy"), None, "of quiet desperation.", "Famous.")],
+        };
+        assert_eq!(to_markdown(&doc),
+                   "# Walden\n\n*Henry David Thoreau*\n\n## Economy\n\n\
+                    > Most men\n> lead lives\n\n*p. iv · Medium · 2020-10-19 09:30*\n\n\
+                    > of quiet desperation.\n\nFamous.\n\n*Medium · 2020-10-19 09:30*\n");
+    }
+
+    #[test]
+    fn test_html_escaping() {
+        let doc = ExportedDocument {
+            title: "A & B".to_string(),
+            author: "",
+            path: Path::new("ab.epub"),
+            annotations: vec![annotation(None, None, "<i>", "")],
+        };
+        let html = to_html(&doc);
+        assert!(html.contains("<title>A &amp; B</title>"));
+        assert!(html.contains("<blockquote class=\"medium\"><span>&lt;i&gt;</span></blockquote>"));
+        assert!(!html.contains("class=\"author\""));
+    }
+}
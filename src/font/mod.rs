@@ -79,6 +79,12 @@ pub const MD_SIZE: Style = Style {
     size: FONT_SIZES[0],
 };
 
+pub const MARGIN_NOTE: Style = Style {
+    family: Family::SansSerif,
+    variant: Variant::ITALIC,
+    size: FONT_SIZES[0],
+};
+
 #[cfg(any(not(target_os = "linux"), target_arch = "arm"))]
 #[link(name="mupdf")]
 extern {
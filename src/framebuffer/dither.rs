@@ -0,0 +1,120 @@
+use super::image::Pixmap;
+use crate::metadata::DitherMode;
+
+const BAYER_MATRIX: [[u8; 4]; 4] = [[ 0,  8,  2, 10],
+                                    [12,  4, 14,  6],
+                                    [ 3, 11,  1,  9],
+                                    [15,  7, 13,  5]];
+
+// Offsets and weights of the diffusion of the quantization error.
+const FLOYD_STEINBERG_KERNEL: [(i32, i32, f32); 4] = [(1, 0, 7.0 / 16.0), (-1, 1, 3.0 / 16.0),
+                                                      (0, 1, 5.0 / 16.0), (1, 1, 1.0 / 16.0)];
+const ATKINSON_KERNEL: [(i32, i32, f32); 6] = [(1, 0, 1.0 / 8.0), (2, 0, 1.0 / 8.0),
+                                               (-1, 1, 1.0 / 8.0), (0, 1, 1.0 / 8.0),
+                                               (1, 1, 1.0 / 8.0), (0, 2, 1.0 / 8.0)];
+
+// Reduces the pixmap to the given number of evenly spaced gray levels.
+pub fn dither(pixmap: &mut Pixmap, mode: DitherMode, levels: u8) {
+    let levels = levels.max(2);
+    match mode {
+        DitherMode::Bayer => ordered(pixmap, levels),
+        DitherMode::FloydSteinberg => diffuse(pixmap, levels, &FLOYD_STEINBERG_KERNEL),
+        DitherMode::Atkinson => diffuse(pixmap, levels, &ATKINSON_KERNEL),
+    }
+}
+
+#[inline]
+fn quantize(value: f32, levels: u8) -> u8 {
+    let step = 255.0 / (levels - 1) as f32;
+    ((value / step).round().max(0.0).min((levels - 1) as f32) * step).round() as u8
+}
+
+fn ordered(pixmap: &mut Pixmap, levels: u8) {
+    let width = pixmap.width as usize;
+    let step = 255.0 / (levels - 1) as f32;
+
+    for (i, v) in pixmap.data.iter_mut().enumerate() {
+        let (x, y) = (i % width, i / width);
+        let threshold = (BAYER_MATRIX[y % 4][x % 4] as f32 + 0.5) / 16.0 - 0.5;
+        *v = quantize(*v as f32 + threshold * step, levels);
+    }
+}
+
+fn diffuse(pixmap: &mut Pixmap, levels: u8, kernel: &[(i32, i32, f32)]) {
+    let width = pixmap.width as i32;
+    let height = pixmap.height as i32;
+    let mut values: Vec<f32> = pixmap.data.iter().map(|&v| v as f32).collect();
+
+    for y in 0..height {
+        for x in 0..width {
+            let index = (y * width + x) as usize;
+            let old = values[index];
+            let new = quantize(old, levels);
+            pixmap.data[index] = new;
+            let error = old - new as f32;
+            for &(dx, dy, weight) in kernel {
+                let (nx, ny) = (x + dx, y + dy);
+                if nx >= 0 && nx < width && ny < height {
+                    values[(ny * width + nx) as usize] += error * weight;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MODES: [DitherMode; 3] = [DitherMode::Bayer, DitherMode::FloydSteinberg, DitherMode::Atkinson];
+
+    // A horizontal gradient from black to white.
+    fn gradient(width: u32, height: u32) -> Pixmap {
+        let mut pixmap = Pixmap::new(width, height);
+        for (i, v) in pixmap.data.iter_mut().enumerate() {
+            *v = ((i as u32 % width) * 255 / (width - 1)) as u8;
+        }
+        pixmap
+    }
+
+    fn mean(pixmap: &Pixmap) -> f32 {
+        pixmap.data.iter().map(|&v| v as f32).sum::<f32>() / pixmap.data.len() as f32
+    }
+
+    #[test]
+    fn test_quantize() {
+        assert_eq!(quantize(-20.0, 2), 0);
+        assert_eq!(quantize(127.0, 2), 0);
+        assert_eq!(quantize(128.0, 2), 255);
+        assert_eq!(quantize(100.0, 16), 102);
+        assert_eq!(quantize(300.0, 16), 255);
+    }
+
+    #[test]
+    fn test_two_levels() {
+        for &mode in &MODES {
+            let mut pixmap = gradient(64, 16);
+            dither(&mut pixmap, mode, 2);
+            assert!(pixmap.data.iter().all(|&v| v == 0 || v == 255), "{:?}", mode);
+        }
+    }
+
+    #[test]
+    fn test_sixteen_levels() {
+        for &mode in &MODES {
+            let mut pixmap = gradient(64, 16);
+            dither(&mut pixmap, mode, 16);
+            assert!(pixmap.data.iter().all(|&v| v % 17 == 0), "{:?}", mode);
+        }
+    }
+
+    #[test]
+    fn test_diffusion_keeps_mean() {
+        for &mode in &MODES[1..] {
+            let mut pixmap = Pixmap::new(64, 64);
+            pixmap.data.iter_mut().for_each(|v| *v = 128);
+            dither(&mut pixmap, mode, 2);
+            assert!((mean(&pixmap) - 128.0).abs() < 1.0, "{:?}", mode);
+        }
+    }
+}
@@ -2,6 +2,7 @@ mod mxcfb_sys;
 mod kobo;
 mod image;
 mod remarkable;
+mod dither;
 
 use anyhow::Error;
 use crate::geom::{Point, Rectangle, surface_area, nearest_segment_point, lerp};
@@ -11,6 +12,7 @@ use crate::color::{BLACK, WHITE};
 pub use self::kobo::KoboFramebuffer;
 pub use self::remarkable::RemarkableFramebuffer;
 pub use self::image::Pixmap;
+pub use self::dither::dither;
 use crate::settings::RefreshQuality;
 
 #[derive(Debug, Copy, Clone)]
@@ -387,6 +389,16 @@ pub trait Framebuffer {
 
     fn draw_segment(&mut self, start: Point, end: Point, start_radius: f32, end_radius: f32, color: u8) {
         let rect = Rectangle::from_segment(start, end, start_radius.ceil() as i32, end_radius.ceil() as i32);
+        self.draw_clipped_segment(start, end, start_radius, end_radius, color, &rect);
+    }
+
+    // Only draws the part of the segment that lies within the given rectangle.
+    fn draw_clipped_segment(&mut self, start: Point, end: Point, start_radius: f32, end_radius: f32, color: u8, clip: &Rectangle) {
+        let rect = match Rectangle::from_segment(start, end, start_radius.ceil() as i32, end_radius.ceil() as i32)
+                                  .intersection(clip) {
+            Some(rect) => rect,
+            None => return,
+        };
         let a = vec2!(start.x as f32, start.y as f32);
         let b = vec2!(end.x as f32, end.y as f32);
 
@@ -1076,6 +1076,13 @@ impl Boundary {
         rect.min.y >= self.min.y && rect.max.y <= self.max.y
     }
 
+    pub fn absorb(&mut self, rect: &Boundary) {
+        self.min.x = self.min.x.min(rect.min.x);
+        self.min.y = self.min.y.min(rect.min.y);
+        self.max.x = self.max.x.max(rect.max.x);
+        self.max.y = self.max.y.max(rect.max.y);
+    }
+
     pub fn width(&self) -> f32 {
         self.max.x - self.min.x
     }
@@ -9,6 +9,7 @@ mod font;
 mod document;
 mod library;
 mod metadata;
+mod export;
 mod settings;
 mod frontlight;
 mod lightsensor;
@@ -16,15 +17,16 @@ mod lightsensor;
 use std::env;
 use std::fs;
 use std::io::Read;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use getopts::Options;
 use chrono::{Local, TimeZone};
 use anyhow::{Error, Context, format_err};
 use crate::helpers::datetime_format;
 use crate::library::Library;
-use crate::settings::{LibraryMode, ImportSettings};
-use crate::metadata::{Info, extract_metadata_from_epub, extract_metadata_from_filename};
-use crate::metadata::{consolidate, rename_from_info};
+use crate::settings::{LibraryMode, ImportSettings, ExportSettings};
+use crate::export::{ExportFormat, export_annotations};
+use crate::metadata::{Info, extract_metadata_from_epub, extract_metadata_from_cbz, extract_metadata_from_filename};
+use crate::metadata::{extract_metadata_from_pdf, consolidate, rename_from_info};
 
 fn main() -> Result<(), Error> {
     let args: Vec<String> = env::args().skip(1).collect();
@@ -34,7 +36,10 @@ fn main() -> Result<(), Error> {
     opts.optflag("h", "help", "Print this help message.");
     opts.optflag("I", "import", "Import new files or update existing files.");
     opts.optflag("C", "clean-up", "Remove entries with dangling paths.");
+    opts.optflag("X", "export-annotations", "Export the annotations of the given document, or of every document.");
     opts.optflag("E", "extract-metadata-epub", "Extract metadata from ePUBs.");
+    opts.optflag("Z", "extract-metadata-cbz", "Extract metadata from CBZs (ComicInfo.xml).");
+    opts.optflag("P", "extract-metadata-pdf", "Extract metadata from PDFs and DJVUs.");
     opts.optflag("F", "extract-metadata-filename", "Extract metadata from filenames.");
     opts.optflag("S", "consolidate", "Autocorrect simple typographic mistakes.");
     opts.optflag("N", "rename-from-info", "Rename files based on their information.");
@@ -42,11 +47,13 @@ fn main() -> Result<(), Error> {
     opts.optopt("k", "allowed-kinds", "Comma separated list of allowed kinds.", "ALLOWED_KINDS");
     opts.optopt("a", "added-after", "Only process entries added after the given date-time.", "ADDED_DATETIME");
     opts.optopt("m", "library-mode", "The library mode (`database` or `filesystem`).", "LIBRARY_MODE");
+    opts.optopt("f", "export-format", "The export format (`markdown`, `json` or `html`).", "EXPORT_FORMAT");
+    opts.optopt("o", "export-path", "The directory where the annotations are exported.", "EXPORT_PATH");
 
     let matches = opts.parse(&args).context("Failed to parse the command line arguments.")?;
 
     if matches.opt_present("h") {
-        println!("{}", opts.usage("Usage: plato-import -h|-I|-C|-EFSN [-t] [-k ALLOWED_KINDS] [-a ADDED_DATETIME] [-m LIBRARY_MODE] LIBRARY_PATH"));
+        println!("{}", opts.usage("Usage: plato-import -h|-I|-C|-EZPFSN|-X [-t] [-k ALLOWED_KINDS] [-a ADDED_DATETIME] [-m LIBRARY_MODE] [-f EXPORT_FORMAT] [-o EXPORT_PATH] LIBRARY_PATH [DOCUMENT_PATH]"));
         return Ok(());
     }
 
@@ -58,6 +65,8 @@ fn main() -> Result<(), Error> {
 
     let mut import_settings = ImportSettings {
         extract_epub_metadata: false,
+        extract_cbz_metadata: false,
+        extract_pdf_metadata: false,
         traverse_hidden: matches.opt_present("t"),
         .. Default::default()
     };
@@ -80,12 +89,44 @@ fn main() -> Result<(), Error> {
 
     let mut library = Library::new(&library_path, mode);
 
+    if matches.opt_present("X") {
+        let format = match matches.opt_str("f") {
+            Some(name) => ExportFormat::from_name(&name)
+                                      .ok_or_else(|| format_err!("Unknown export format: {}.", name))?,
+            None => ExportFormat::Markdown,
+        };
+
+        let export_path = matches.opt_str("o").map(PathBuf::from)
+                                 .unwrap_or_else(|| library_path.join(ExportSettings::default().path));
+
+        let documents = library.annotated_documents();
+        let documents = if let Some(document_path) = matches.free.get(1).map(Path::new) {
+            let document_path = document_path.strip_prefix(&library_path).unwrap_or(document_path);
+            let info = documents.into_iter().find(|info| info.file.path == document_path)
+                                .ok_or_else(|| format_err!("No annotations found for {}.", document_path.display()))?;
+            vec![info]
+        } else {
+            documents
+        };
+
+        for info in &documents {
+            match export_annotations(&library_path, info, format, &export_path) {
+                Ok(path) => println!("{} → {}.", info.file.path.display(), path.display()),
+                Err(e) => eprintln!("Can't export {}: {}", info.file.path.display(), e),
+            }
+        }
+
+        return Ok(());
+    }
+
     if matches.opt_present("I") {
         library.import(&library_path, &import_settings);
     } else if matches.opt_present("C") {
         library.clean_up();
     } else {
         let opt_extract_metadata_epub = matches.opt_present("E");
+        let opt_extract_metadata_cbz = matches.opt_present("Z");
+        let opt_extract_metadata_pdf = matches.opt_present("P");
         let opt_extract_metadata_filename = matches.opt_present("F");
         let opt_consolidate = matches.opt_present("S");
         let opt_rename_from_info = matches.opt_present("N");
@@ -96,6 +137,14 @@ fn main() -> Result<(), Error> {
                     extract_metadata_from_epub(path, info);
                 }
 
+                if opt_extract_metadata_cbz {
+                    extract_metadata_from_cbz(path, info);
+                }
+
+                if opt_extract_metadata_pdf {
+                    extract_metadata_from_pdf(path, info);
+                }
+
                 if opt_extract_metadata_filename {
                     extract_metadata_from_filename(path, info);
                 }
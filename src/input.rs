@@ -8,9 +8,9 @@ use std::sync::mpsc::{self, Sender, Receiver};
 use std::os::unix::io::AsRawFd;
 use std::ffi::CString;
 use crate::framebuffer::Display;
-use crate::settings::ButtonScheme;
+use crate::settings::{ButtonScheme, PenButton};
 use crate::device::{CURRENT_DEVICE, Model};
-use crate::geom::{Point, LinearDir};
+use crate::geom::{Point, Vec2, LinearDir};
 use anyhow::{Error, Context};
 use libremarkable::input::ecodes;
 use libremarkable::framebuffer::common;
@@ -34,6 +34,8 @@ pub const SYN_MT_REPORT: u16 = 0x02;
 pub const ABS_X: u16 = ecodes::ABS_X; // reMarkable specific
 pub const ABS_Y: u16 = ecodes::ABS_Y; //  reMarkable specific
 pub const ABS_PRESSURE: u16 = ecodes::ABS_PRESSURE; // reMarkable MT Pressure
+pub const ABS_TILT_X: u16 = 0x1a;
+pub const ABS_TILT_Y: u16 = 0x1b;
 pub const MSC_RAW: u16 = 0x03;
 pub const SYN_REPORT: u16 = 0x00;
 
@@ -53,6 +55,12 @@ pub const VAL_RELEASE: i32 = 0;
 pub const VAL_PRESS: i32 = 1;
 pub const VAL_REPEAT: i32 = 2;
 
+// Finger id of the Wacom pen.
+pub const PEN_SLOT: i32 = 100;
+pub const WACOM_MAX_PRESSURE: f32 = 4095.0;
+// The tilt is expressed in hundredths of degree.
+pub const WACOM_TILT_SCALE: f32 = 0.01;
+
 // Key codes
 pub const KEY_POWER: u16 = ecodes::KEY_POWER;
 pub const KEY_HOME: u16 = ecodes::KEY_HOME;
@@ -64,6 +72,12 @@ pub const KEY_FORWARD: u16 = ecodes::KEY_RIGHT;
 pub const KEY_ROTATE_DISPLAY: u16 = 0xffff;
 pub const KEY_BUTTON_SCHEME: u16 = 0xfffe;
 pub const SLEEP_COVER: u16 = 59;
+// Sent by the digitizer when the pen (resp. the eraser) enters or leaves its range.
+pub const BTN_TOOL_PEN: u16 = 0x140;
+pub const BTN_TOOL_RUBBER: u16 = 0x141;
+// The side buttons of the pen.
+pub const BTN_STYLUS: u16 = 0x14b;
+pub const BTN_STYLUS2: u16 = 0x14c;
 
 pub struct InputFilterCommand {
     pub path: String,
@@ -117,6 +131,25 @@ pub enum FingerStatus {
     Up,
 }
 
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum PenStatus {
+    // The pen entered the range of the digitizer.
+    Enter,
+    // The pen moved above the screen.
+    Hover,
+    Down,
+    Motion,
+    Up,
+    // The pen left the range of the digitizer.
+    Leave,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum PenTool {
+    Pen,
+    Eraser,
+}
+
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub enum ButtonStatus {
     Pressed,
@@ -200,6 +233,20 @@ pub enum DeviceEvent {
         status: FingerStatus,
         position: Point,
     },
+    // The pen also sends finger events, with the `PEN_SLOT` id, when it touches the screen.
+    Pen {
+        time: f64,
+        status: PenStatus,
+        tool: PenTool,
+        position: Point,
+        // Between 0 and 1.
+        pressure: f32,
+        // Angles, in degrees, between the pen and the normal of the screen,
+        // along the horizontal and vertical axes of the screen.
+        tilt: Vec2,
+        // The side button held, if any.
+        button: Option<PenButton>,
+    },
     Button {
         time: f64,
         code: ButtonCode,
@@ -356,15 +403,16 @@ fn parse_usb_events(tx: &Sender<DeviceEvent>) {
     }
 }
 
-pub fn device_events(rx: Receiver<InputEvent>, display: Display, button_scheme: ButtonScheme) -> Receiver<DeviceEvent> {
+// When `palm_rejection` is given, the touches that begin while the pen is in range,
+// or less than the given number of seconds after it left, are ignored until they end.
+pub fn device_events(rx: Receiver<InputEvent>, display: Display, button_scheme: ButtonScheme, palm_rejection: Option<f64>) -> Receiver<DeviceEvent> {
     let (ty, ry) = mpsc::channel();
-    thread::spawn(move || parse_device_events(&rx, &ty, display, button_scheme));
+    thread::spawn(move || parse_device_events(&rx, &ty, display, button_scheme, palm_rejection));
     ry
 }
 
-pub fn parse_device_events(rx: &Receiver<InputEvent>, ty: &Sender<DeviceEvent>, display: Display, button_scheme: ButtonScheme) {
+pub fn parse_device_events(rx: &Receiver<InputEvent>, ty: &Sender<DeviceEvent>, display: Display, button_scheme: ButtonScheme, palm_rejection: Option<f64>) {
     let mut current_slot: i32 = 0; // Basically for which finger id to events are meant
-    const PEN_SLOT: i32 = 100; // Figer id for wacom pen
     let mut last_activity = -60;
     let Display { mut dims, mut rotation } = display;
 
@@ -377,6 +425,7 @@ pub fn parse_device_events(rx: &Receiver<InputEvent>, ty: &Sender<DeviceEvent>,
         
         last_pressed: bool,
         pressed: bool,
+        rejected: bool, // Palm contact, ignored until it ends.
     };
     impl Default for EvFinger {
         fn default() -> EvFinger {
@@ -385,11 +434,36 @@ pub fn parse_device_events(rx: &Receiver<InputEvent>, ty: &Sender<DeviceEvent>,
                 pos_updated: false,
                 last_pressed: false,
                 pressed: false,
+                rejected: false,
             }
         }
     }
 
     let mut ev_fingers: HashMap<i32, EvFinger> = HashMap::new();
+
+    // State of the pen, besides its position and contact.
+    #[derive(Debug)]
+    struct EvPen {
+        tool: PenTool,
+        in_range: bool,
+        last_in_range: bool,
+        left_at: Option<f64>,
+        pressure: i32,
+        tilt: (i32, i32),
+        buttons: [bool; 2],
+        updated: bool,
+    }
+
+    let mut ev_pen = EvPen {
+        tool: PenTool::Pen,
+        in_range: false,
+        last_in_range: false,
+        left_at: None,
+        pressure: 0,
+        tilt: (0, 0),
+        buttons: [false; 2],
+        updated: false,
+    };
  
     let mut tc = match CURRENT_DEVICE.proto {
         TouchProto::Single => SINGLE_TOUCH_CODES,
@@ -480,6 +554,14 @@ pub fn parse_device_events(rx: &Receiver<InputEvent>, ty: &Sender<DeviceEvent>,
                 }
             }else if evt.code == ecodes::ABS_PRESSURE {
                 ev_fingers.entry(PEN_SLOT).or_default().pressed = evt.value > 0;
+                ev_pen.pressure = evt.value;
+                ev_pen.updated = true;
+            } else if evt.code == ABS_TILT_X {
+                ev_pen.tilt.0 = evt.value;
+                ev_pen.updated = true;
+            } else if evt.code == ABS_TILT_Y {
+                ev_pen.tilt.1 = evt.value;
+                ev_pen.updated = true;
             }
         } else if evt.kind == EV_SYN {
             // The absolute value accounts for the wrapping around that might occur,
@@ -490,8 +572,81 @@ pub fn parse_device_events(rx: &Receiver<InputEvent>, ty: &Sender<DeviceEvent>,
             }
 
             if evt.code == SYN_REPORT {
+                // Send the state of the pen, before the finger events it entails.
+                let (tilt_x, tilt_y) = if should_wacom_swap {
+                    (ev_pen.tilt.0, ev_pen.tilt.1)
+                } else {
+                    (ev_pen.tilt.1, ev_pen.tilt.0)
+                };
+                let tilt_x = if mirror_wacom_x { -tilt_x } else { tilt_x };
+                let tilt_y = if mirror_wacom_y { -tilt_y } else { tilt_y };
+                let tilt = vec2!(tilt_x as f32, tilt_y as f32) * WACOM_TILT_SCALE;
+                let pressure = (ev_pen.pressure as f32 / WACOM_MAX_PRESSURE).min(1.0).max(0.0);
+                let button = if ev_pen.buttons[1] {
+                    Some(PenButton::Upper)
+                } else if ev_pen.buttons[0] {
+                    Some(PenButton::Lower)
+                } else {
+                    None
+                };
+                let pen = ev_fingers.entry(PEN_SLOT).or_default();
+                let mut statuses = Vec::new();
+
+                if ev_pen.in_range && !ev_pen.last_in_range {
+                    statuses.push(PenStatus::Enter);
+                }
+
+                if !pen.last_pressed && pen.pressed {
+                    statuses.push(PenStatus::Down);
+                } else if pen.last_pressed && !pen.pressed {
+                    statuses.push(PenStatus::Up);
+                } else if pen.pos_updated || ev_pen.updated {
+                    if pen.pressed {
+                        statuses.push(PenStatus::Motion);
+                    } else if ev_pen.in_range {
+                        statuses.push(PenStatus::Hover);
+                    }
+                }
+
+                if !ev_pen.in_range && ev_pen.last_in_range {
+                    statuses.push(PenStatus::Leave);
+                }
+
+                for status in statuses {
+                    ty.send(DeviceEvent::Pen {
+                        time: seconds(evt.time),
+                        status,
+                        tool: ev_pen.tool,
+                        position: pen.pos,
+                        pressure,
+                        tilt,
+                        button,
+                    }).ok();
+                }
+
+                if !ev_pen.in_range && ev_pen.last_in_range {
+                    ev_pen.left_at = Some(seconds(evt.time));
+                }
+
+                ev_pen.last_in_range = ev_pen.in_range;
+                ev_pen.updated = false;
+
+                let rejecting_palm = palm_rejection.map_or(false, |delay| {
+                    ev_pen.in_range || ev_pen.left_at.map_or(false, |t| seconds(evt.time) - t < delay)
+                });
+
                 // Send new positions
                 for (slot, mut finger) in ev_fingers.iter_mut() {
+                    if *slot != PEN_SLOT && rejecting_palm && ! finger.last_pressed && finger.pressed {
+                        finger.rejected = true;
+                    }
+
+                    if finger.rejected {
+                        finger.rejected = finger.pressed;
+                        finger.pos_updated = false;
+                        continue;
+                    }
+
                     if ! finger.last_pressed && finger.pressed {
                         // Pressed
                         finger.last_pressed = finger.pressed;
@@ -540,6 +695,14 @@ pub fn parse_device_events(rx: &Receiver<InputEvent>, ty: &Sender<DeviceEvent>,
                 } else {
                     button_scheme = ButtonScheme::Natural;
                 }
+            } else if evt.code == BTN_TOOL_PEN || evt.code == BTN_TOOL_RUBBER {
+                ev_pen.in_range = evt.value != VAL_RELEASE;
+                if ev_pen.in_range {
+                    ev_pen.tool = if evt.code == BTN_TOOL_RUBBER { PenTool::Eraser } else { PenTool::Pen };
+                }
+            } else if evt.code == BTN_STYLUS || evt.code == BTN_STYLUS2 {
+                ev_pen.buttons[(evt.code - BTN_STYLUS) as usize] = evt.value != VAL_RELEASE;
+                ev_pen.updated = true;
             } else if evt.code == KEY_ROTATE_DISPLAY {
                 let next_rotation = evt.value as i8;
                 if next_rotation != rotation {
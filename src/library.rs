@@ -9,7 +9,8 @@ use fxhash::{FxHashMap, FxHashSet, FxBuildHasher};
 use chrono::{Local, TimeZone};
 use filetime::{FileTime, set_file_handle_times};
 use crate::metadata::{Info, ReaderInfo, FileInfo, SimpleStatus, SortMethod};
-use crate::metadata::{sort, sorter, extract_metadata_from_epub};
+use crate::metadata::{sort, sorter, extract_metadata_from_epub, extract_metadata_from_cbz};
+use crate::metadata::extract_metadata_from_pdf;
 use crate::settings::{LibraryMode, ImportSettings};
 use crate::document::file_kind;
 use crate::helpers::{Fingerprint, save_json, load_json, IsHidden};
@@ -17,6 +18,7 @@ use crate::helpers::{Fingerprint, save_json, load_json, IsHidden};
 pub const METADATA_FILENAME: &str = ".metadata.json";
 pub const FAT32_EPOCH_FILENAME: &str = ".fat32-epoch";
 pub const READING_STATES_DIRNAME: &str = ".reading-states";
+pub const PAGINATION_DIRNAME: &str = "pagination";
 
 pub struct Library {
     pub home: PathBuf,
@@ -56,6 +58,11 @@ impl Library {
             fs::create_dir(&path).ok();
         }
 
+        let pagination_path = path.join(PAGINATION_DIRNAME);
+        if !pagination_path.exists() {
+            fs::create_dir(&pagination_path).ok();
+        }
+
         for entry in fs::read_dir(&path).expect(&format!("Failed to read directory \"{:?}\"", &path)) {
             let entry = entry.unwrap();
             let path = entry.path();
@@ -208,6 +215,14 @@ impl Library {
         (files, dirs)
     }
 
+    pub fn annotated_documents(&self) -> Vec<Info> {
+        let query = Regex::new("").unwrap();
+        let (mut files, _) = self.list(&self.home, Some(&query), false);
+        files.retain(|info| info.reader.as_ref()
+                                .map_or(false, |r| !r.annotations.is_empty()));
+        files
+    }
+
     pub fn import<P: AsRef<Path>>(&mut self, prefix: P, settings: &ImportSettings) {
         if self.mode == LibraryMode::Filesystem {
             return;
@@ -239,12 +254,15 @@ impl Library {
             // The path is known: update the fp.
             } else if let Some(fp2) = self.paths.get(relat) {
                 println!("Update fingerprint for {}: {:016X} → {:016X}.", relat.display(), fp2, fp);
-                let info = self.db.remove(fp2).unwrap();
-                self.db.insert(fp, info);
-                self.db[&fp].file.size = md.len();
-                let rp1 = self.reading_state_path(*fp2);
-                let rp2 = self.reading_state_path(fp);
-                fs::rename(rp1, rp2).ok();
+                let fp2 = *fp2;
+                self.move_reading_state(relat, fp2, fp, md.len());
+                // The pages of the previous version are irrelevant.
+                fs::remove_file(self.pagination_file(fp2)).ok();
+                // The annotations of the previous version need to be found again.
+                if let Some(r) = self.db[&fp].reader.as_mut().filter(|r| !r.annotations.is_empty()) {
+                    r.reanchor = true;
+                    self.modified_reading_states.insert(fp);
+                }
                 self.has_db_changed = true;
             // We found a new file: add it to the db.
             } else {
@@ -266,6 +284,12 @@ impl Library {
                 if settings.extract_epub_metadata {
                     extract_metadata_from_epub(prefix.as_ref(), &mut info);
                 }
+                if settings.extract_cbz_metadata {
+                    extract_metadata_from_cbz(prefix.as_ref(), &mut info);
+                }
+                if settings.extract_pdf_metadata {
+                    extract_metadata_from_pdf(prefix.as_ref(), &mut info);
+                }
                 self.db.insert(fp, info);
                 self.has_db_changed = true;
             }
@@ -318,6 +342,7 @@ impl Library {
                                        .and_then(|v| u64::from_str_radix(v, 16).ok()) {
                     if !self.db.contains_key(&fp) {
                         fs::remove_file(entry.path()).ok();
+                        fs::remove_file(self.pagination_file(fp)).ok();
                     }
                 }
             }
@@ -348,6 +373,7 @@ impl Library {
                         self.reading_states.remove(&fp);
                         self.modified_reading_states.remove(&fp);
                         fs::remove_file(entry.path()).ok();
+                        fs::remove_file(self.pagination_file(fp)).ok();
                     }
                 }
             }
@@ -514,6 +540,63 @@ impl Library {
         }
     }
 
+    // Returns `None` if the fingerprint of the document can't be computed.
+    pub fn fingerprint<P: AsRef<Path>>(&self, path: P) -> Option<u64> {
+        self.paths.get(path.as_ref()).cloned().or_else(|| {
+            self.home.join(path.as_ref())
+                .metadata().ok()
+                .and_then(|md| md.fingerprint(self.fat32_epoch).ok())
+        })
+    }
+
+    pub fn pagination_path<P: AsRef<Path>>(&self, path: P) -> Option<PathBuf> {
+        self.fingerprint(path).map(|fp| self.pagination_file(fp))
+    }
+
+    // Registers the new fingerprint of a document that was modified by the application.
+    // Unlike `import`, its annotations aren't flagged to be found again.
+    pub fn update_fingerprint<P: AsRef<Path>>(&mut self, path: P, fp: u64) {
+        let md = match self.home.join(path.as_ref()).metadata() {
+            Ok(md) => md,
+            Err(_) => return,
+        };
+        if let Ok(fp2) = md.fingerprint(self.fat32_epoch) {
+            if fp2 != fp {
+                self.move_reading_state(path.as_ref(), fp, fp2, md.len());
+                fs::rename(self.pagination_file(fp), self.pagination_file(fp2)).ok();
+            }
+        }
+    }
+
+    fn move_reading_state(&mut self, path: &Path, fp: u64, fp2: u64, size: u64) {
+        match self.mode {
+            LibraryMode::Database => {
+                if let Some(mut info) = self.db.remove(&fp) {
+                    info.file.size = size;
+                    self.db.insert(fp2, info);
+                    self.paths.insert(path.to_path_buf(), fp2);
+                    self.has_db_changed = true;
+                }
+            },
+            LibraryMode::Filesystem => {
+                if let Some(reader_info) = self.reading_states.remove(&fp) {
+                    self.reading_states.insert(fp2, reader_info);
+                }
+            },
+        }
+        if self.modified_reading_states.remove(&fp) {
+            self.modified_reading_states.insert(fp2);
+        }
+        fs::rename(self.reading_state_path(fp), self.reading_state_path(fp2)).ok();
+    }
+
+    fn pagination_file(&self, fp: u64) -> PathBuf {
+        self.home
+            .join(READING_STATES_DIRNAME)
+            .join(PAGINATION_DIRNAME)
+            .join(format!("{:016X}.json", fp))
+    }
+
     fn reading_state_path(&self, fp: u64) -> PathBuf {
         self.home
             .join(READING_STATES_DIRNAME)
@@ -13,6 +13,7 @@ mod dictionary;
 mod document;
 mod library;
 mod metadata;
+mod export;
 mod symbolic_path;
 mod rtc;
 mod settings;
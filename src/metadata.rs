@@ -1,5 +1,6 @@
-use std::fs;
+use std::fs::{self, File};
 use std::fmt;
+use std::io::Read;
 use std::ffi::OsStr;
 use std::collections::{BTreeSet, BTreeMap};
 use std::path::{Path, PathBuf};
@@ -7,13 +8,21 @@ use std::cmp::Ordering;
 use regex::Regex;
 use chrono::{Local, DateTime};
 use fxhash::FxHashMap;
-use serde::{Serialize, Deserialize};
+use serde::{Serialize, Deserialize, Deserializer};
 use lazy_static::lazy_static;
 use titlecase::titlecase;
+use zip::ZipArchive;
+use anyhow::Error;
+use septem::prelude::*;
+use septem::Roman;
 use crate::document::{Document, SimpleTocEntry, TextLocation};
 use crate::document::asciify;
 use crate::document::epub::EpubDocument;
-use crate::helpers::datetime_format;
+use crate::document::pdf::PdfOpener;
+use crate::document::djvu::DjvuOpener;
+use crate::document::html::dom::Node;
+use crate::document::html::xml::XmlParser;
+use crate::helpers::{datetime_format, decode_entities, AsciiExtension};
 
 pub const DEFAULT_CONTRAST_EXPONENT: f32 = 1.0;
 pub const DEFAULT_CONTRAST_GRAY: f32 = 224.0;
@@ -81,22 +90,138 @@ pub struct Annotation {
     pub note: String,
     #[serde(skip_serializing_if = "String::is_empty")]
     pub text: String,
+    // The text surrounding the annotation. Along with `text`, it is used to find
+    // the annotation again when the document changes.
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub prefix: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub suffix: String,
     pub selection: [TextLocation; 2],
+    pub style: HighlightStyle,
+    // The text of the annotation couldn't be found in the document.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub orphan: bool,
     #[serde(with = "datetime_format")]
     pub modified: DateTime<Local>,
 }
 
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, Eq, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum HighlightStyle {
+    Light,
+    Medium,
+    Dark,
+    Underline,
+    StrikeThrough,
+    Boxed,
+}
+
+impl HighlightStyle {
+    pub fn all() -> [HighlightStyle; 6] {
+        [HighlightStyle::Light, HighlightStyle::Medium, HighlightStyle::Dark,
+         HighlightStyle::Underline, HighlightStyle::StrikeThrough, HighlightStyle::Boxed]
+    }
+}
+
+impl Default for HighlightStyle {
+    fn default() -> Self {
+        HighlightStyle::Medium
+    }
+}
+
+impl fmt::Display for HighlightStyle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HighlightStyle::Light => write!(f, "Light"),
+            HighlightStyle::Medium => write!(f, "Medium"),
+            HighlightStyle::Dark => write!(f, "Dark"),
+            HighlightStyle::Underline => write!(f, "Underline"),
+            HighlightStyle::StrikeThrough => write!(f, "Strike Through"),
+            HighlightStyle::Boxed => write!(f, "Boxed"),
+        }
+    }
+}
+
 impl Default for Annotation {
     fn default() -> Self {
         Annotation {
             note: String::new(),
             text: String::new(),
+            prefix: String::new(),
+            suffix: String::new(),
             selection: [TextLocation::Dynamic(0), TextLocation::Dynamic(1)],
+            style: HighlightStyle::default(),
+            orphan: false,
             modified: Local::now(),
         }
     }
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Bookmark {
+    pub location: usize,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub label: String,
+    // The beginning of the text of the bookmarked page.
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub text: String,
+    #[serde(with = "datetime_format")]
+    pub created: DateTime<Local>,
+}
+
+impl Bookmark {
+    pub fn new(location: usize, text: String) -> Bookmark {
+        Bookmark {
+            location,
+            text,
+            .. Default::default()
+        }
+    }
+}
+
+impl Default for Bookmark {
+    fn default() -> Self {
+        Bookmark {
+            location: 0,
+            label: String::new(),
+            text: String::new(),
+            created: Local::now(),
+        }
+    }
+}
+
+// Older versions stored the bookmarks as a set of locations.
+fn deserialize_bookmarks<'de, D>(deserializer: D) -> Result<Vec<Bookmark>, D::Error> where D: Deserializer<'de> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum StoredBookmark {
+        Location(usize),
+        Bookmark(Bookmark),
+    }
+
+    let mut bookmarks = Vec::<StoredBookmark>::deserialize(deserializer)?
+                            .into_iter()
+                            .map(|bkm| match bkm {
+                                StoredBookmark::Location(location) => Bookmark { location, .. Default::default() },
+                                StoredBookmark::Bookmark(bookmark) => bookmark,
+                            })
+                            .collect::<Vec<Bookmark>>();
+    bookmarks.sort_by_key(|bkm| bkm.location);
+    bookmarks.dedup_by_key(|bkm| bkm.location);
+    Ok(bookmarks)
+}
+
+// A stroke of the pen. Each point holds its coordinates and the radius of the pen.
+// The points of fixed-layout documents are relative to the page, and expressed in
+// page units. The points of reflowable documents are relative to the word the
+// stroke is anchored to.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Stroke {
+    pub points: Vec<[f32; 3]>,
+    pub color: u8,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Margin {
     pub top: f32,
@@ -201,6 +326,10 @@ pub struct ReaderInfo {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub top_offset: Option<i32>,
     #[serde(skip_serializing_if = "Option::is_none")]
+    pub left_offset: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub zoom_factor: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub rotation: Option<i8>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub cropping_margins: Option<CroppingMargins>,
@@ -220,24 +349,120 @@ pub struct ReaderInfo {
     pub contrast_exponent: Option<f32>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub contrast_gray: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deskew: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub despeckle: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub auto_levels: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dithering: Option<DitherMode>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reflow: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cover_alone: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub continuous: Option<bool>,
+    // Show the notes of the annotations in a margin, next to the annotated text.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub margin_notes: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
     #[serde(skip_serializing_if = "BTreeMap::is_empty")]
     pub page_names: BTreeMap<usize, String>,
-    #[serde(skip_serializing_if = "BTreeSet::is_empty")]
-    pub bookmarks: BTreeSet<usize>,
+    // The bookmarks, sorted by location.
+    #[serde(skip_serializing_if = "Vec::is_empty", deserialize_with = "deserialize_bookmarks")]
+    pub bookmarks: Vec<Bookmark>,
     #[serde(skip_serializing_if = "Vec::is_empty")]
     pub annotations: Vec<Annotation>,
+    // The document was replaced since the annotations were made.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub reanchor: bool,
+    // The strokes of fixed-layout documents, indexed by page.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub ink: BTreeMap<usize, Vec<Stroke>>,
+    // The strokes of reflowable documents, indexed by the offset of their anchor word.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub word_ink: BTreeMap<usize, Vec<Stroke>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, Eq, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum DitherMode {
+    FloydSteinberg,
+    Atkinson,
+    Bayer,
+}
+
+impl fmt::Display for DitherMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DitherMode::FloydSteinberg => write!(f, "Floyd–Steinberg"),
+            DitherMode::Atkinson => write!(f, "Atkinson"),
+            DitherMode::Bayer => write!(f, "Bayer"),
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
 pub enum ZoomMode {
     FitToPage,
     FitToWidth,
+    FitToColumn,
+    Custom,
 }
 
 impl ReaderInfo {
     pub fn progress(&self) -> f32 {
         (self.current_page / self.pages_count) as f32
     }
+
+    pub fn bookmark(&self, location: usize) -> Option<&Bookmark> {
+        self.bookmarks.binary_search_by_key(&location, |bkm| bkm.location)
+            .ok().map(|index| &self.bookmarks[index])
+    }
+
+    // Adds a bookmark at the given location, or removes the existing one.
+    // Returns whether a bookmark was added.
+    pub fn toggle_bookmark(&mut self, location: usize, text: String) -> bool {
+        match self.bookmarks.binary_search_by_key(&location, |bkm| bkm.location) {
+            Ok(index) => {
+                self.bookmarks.remove(index);
+                false
+            },
+            Err(index) => {
+                self.bookmarks.insert(index, Bookmark::new(location, text));
+                true
+            },
+        }
+    }
+
+    // Returns the name of the given page, derived from the closest named page before it.
+    pub fn page_name(&self, index: usize) -> Option<String> {
+        self.page_names.range(..=index).next_back()
+            .and_then(|(i, name)| {
+                let delta = (index - *i) as u32;
+                if delta == 0 {
+                    Some(name.to_string())
+                } else if let Ok(n) = u32::from_str_radix(name, 10) {
+                    Some((n + delta).to_string())
+                } else if let Ok(n) = Roman::from_str(name) {
+                    Roman::from(*n + delta).ok().map(|r| {
+                        if name.chars().all(|c| c.is_ascii_lowercase()) {
+                            r.to_lowercase()
+                        } else {
+                            r.to_uppercase()
+                        }
+                    })
+                } else if let Some(n) = name.chars().next().and_then(|c| c.to_alphabetic_digit())
+                                            .filter(|_| name.len() == 1) {
+                    std::char::from_u32(65 + n + delta).filter(|c| c.is_ascii_uppercase())
+                                                        .map(|c| c.to_string())
+                } else {
+                    None
+                }
+            })
+    }
 }
 
 impl Default for ReaderInfo {
@@ -249,6 +474,8 @@ impl Default for ReaderInfo {
             finished: false,
             zoom_mode: None,
             top_offset: None,
+            left_offset: None,
+            zoom_factor: None,
             rotation: None,
             cropping_margins: None,
             margin_width: None,
@@ -259,9 +486,21 @@ impl Default for ReaderInfo {
             line_height: None,
             contrast_exponent: None,
             contrast_gray: None,
+            deskew: None,
+            despeckle: None,
+            auto_levels: None,
+            dithering: None,
+            reflow: None,
+            cover_alone: None,
+            continuous: None,
+            margin_notes: None,
+            password: None,
             page_names: BTreeMap::new(),
-            bookmarks: BTreeSet::new(),
+            bookmarks: Vec::new(),
             annotations: Vec::new(),
+            reanchor: false,
+            ink: BTreeMap::new(),
+            word_ink: BTreeMap::new(),
         }
     }
 }
@@ -609,6 +848,176 @@ pub fn extract_metadata_from_epub(prefix: &Path, info: &mut Info) {
     }
 }
 
+#[inline]
+pub fn extract_metadata_from_pdf(prefix: &Path, info: &mut Info) {
+    if !info.title.is_empty() {
+        return;
+    }
+
+    let path = prefix.join(&info.file.path);
+
+    match info.file.kind.as_ref() {
+        "pdf" => {
+            match PdfOpener::new().and_then(|o| o.open(&path)) {
+                Some(doc) => {
+                    if doc.is_protected() {
+                        return;
+                    }
+                    let field = |value: Option<String>| value.map(|v| v.trim().to_string()).unwrap_or_default();
+                    info.title = field(doc.title());
+                    info.author = field(doc.author());
+                    info.year = field(doc.year());
+                    insert_keywords(&field(doc.subject()), &mut info.categories);
+                    insert_keywords(&field(doc.keywords()), &mut info.categories);
+
+                    if info.title.is_empty() || info.author.is_empty() {
+                        if let Some(text) = doc.xmp_metadata() {
+                            fill_from_xmp(&text, info);
+                        }
+                    }
+                },
+                None => eprintln!("Can't open {}.", info.file.path.display()),
+            }
+        },
+        "djvu" | "djv" => {
+            match DjvuOpener::new().and_then(|o| o.open(&path)) {
+                Some(doc) => {
+                    let field = |key: &str| doc.metadata(key).map(|v| v.trim().to_string()).unwrap_or_default();
+                    info.title = field("title");
+                    info.author = field("author");
+                    info.year = field("year");
+                    info.publisher = field("publisher");
+                    info.series = field("series");
+                    info.volume = field("volume");
+                    info.number = field("number");
+                    info.edition = field("edition");
+                    insert_keywords(&field("subject"), &mut info.categories);
+                    insert_keywords(&field("keywords"), &mut info.categories);
+                },
+                None => eprintln!("Can't open {}.", info.file.path.display()),
+            }
+        },
+        _ => (),
+    }
+}
+
+fn insert_keywords(text: &str, categories: &mut BTreeSet<String>) {
+    for keyword in text.split(|c| c == ',' || c == ';').map(str::trim).filter(|k| !k.is_empty()) {
+        categories.insert(keyword.to_string());
+    }
+}
+
+// Fills the title and the author missing from the document information dictionary
+// with the Dublin Core properties of the given XMP packet.
+fn fill_from_xmp(text: &str, info: &mut Info) {
+    let root = XmlParser::new(text).parse();
+    if info.title.is_empty() {
+        info.title = xmp_values(&root, "title").into_iter().next().unwrap_or_default();
+    }
+    if info.author.is_empty() {
+        info.author = xmp_values(&root, "creator").join(", ");
+    }
+}
+
+// Returns the values of the given Dublin Core property of an XMP packet.
+// The values are either stored directly or in an `rdf:Alt`, `rdf:Bag` or `rdf:Seq` container.
+fn xmp_values(root: &Node, name: &str) -> Vec<String> {
+    let mut values = Vec::new();
+
+    if let Some(node) = root.find(name) {
+        if let Some(text) = node.text() {
+            values.push(decode_entities(text).trim().to_string());
+        } else if let Some(container) = node.children().and_then(|children| children.iter().find(|child| child.is_element())) {
+            if let Some(children) = container.children() {
+                for child in children.iter().filter(|child| child.tag_name() == Some("li")) {
+                    if let Some(text) = child.text() {
+                        values.push(decode_entities(text).trim().to_string());
+                    }
+                }
+            }
+        }
+    }
+
+    values.retain(|v| !v.is_empty());
+    values
+}
+
+#[inline]
+pub fn extract_metadata_from_cbz(prefix: &Path, info: &mut Info) {
+    if !info.title.is_empty() || info.file.kind != "cbz" {
+        return;
+    }
+
+    let path = prefix.join(&info.file.path);
+
+    match comic_info(&path) {
+        Ok(Some(text)) => parse_comic_info(&text, info),
+        Ok(None) => (),
+        Err(e) => eprintln!("Can't open {}: {}", info.file.path.display(), e),
+    }
+}
+
+// Fills the metadata with the fields of the given *ComicInfo.xml* document.
+fn parse_comic_info(text: &str, info: &mut Info) {
+    let root = XmlParser::new(text).parse();
+    let field = |name: &str| {
+        root.find(name)
+            .and_then(Node::text)
+            .map(|text| decode_entities(text).trim().to_string())
+            .unwrap_or_default()
+    };
+
+    info.title = field("Title");
+    info.series = field("Series");
+    info.number = field("Number");
+    info.volume = field("Volume");
+    info.year = field("Year");
+    info.publisher = field("Publisher");
+    info.language = field("LanguageISO");
+
+    // Issues without a title are named after their series.
+    if info.title.is_empty() {
+        info.title = info.series.clone();
+        info.series.clear();
+    }
+
+    let mut authors: Vec<String> = Vec::new();
+    for name in ["Writer", "Penciller"].iter() {
+        for author in field(name).split(',').map(str::trim).filter(|a| !a.is_empty()) {
+            if !authors.iter().any(|a| a == author) {
+                authors.push(author.to_string());
+            }
+        }
+    }
+    info.author = authors.join(", ");
+
+    for name in ["Genre", "Tags"].iter() {
+        for categ in field(name).split(',').map(str::trim).filter(|c| !c.is_empty()) {
+            info.categories.insert(categ.to_string());
+        }
+    }
+}
+
+fn comic_info(path: &Path) -> Result<Option<String>, Error> {
+    let file = File::open(path)?;
+    let mut archive = ZipArchive::new(file)?;
+    let name = (0..archive.len()).filter_map(|index| {
+        archive.by_index(index).ok().map(|zf| zf.name().to_string())
+    }).find(|name| {
+        name.rsplit('/').next()
+            .map_or(false, |base| base.eq_ignore_ascii_case("ComicInfo.xml"))
+    });
+
+    if let Some(name) = name {
+        let mut zf = archive.by_name(&name)?;
+        let mut text = String::new();
+        zf.read_to_string(&mut text)?;
+        Ok(Some(text))
+    } else {
+        Ok(None)
+    }
+}
+
 pub fn extract_metadata_from_filename(_prefix: &Path, info: &mut Info) {
     if !info.title.is_empty() {
         return;
@@ -726,3 +1135,99 @@ pub fn file_name_from_info(info: &Info) -> String {
         .replace('!', "")
         .replace(':', "")
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_legacy_bookmarks() {
+        let r: ReaderInfo = serde_json::from_str(r#"{"bookmarks": [42, 7]}"#).unwrap();
+        assert_eq!(r.bookmarks.iter().map(|bkm| bkm.location).collect::<Vec<usize>>(), vec![7, 42]);
+        let json = serde_json::to_string(&r).unwrap();
+        let r: ReaderInfo = serde_json::from_str(&json).unwrap();
+        assert_eq!(r.bookmark(42).map(|bkm| bkm.label.as_str()), Some(""));
+        let r: ReaderInfo = serde_json::from_str(r#"{"bookmarks": [{"location": 3, "label": "Map",
+                                                                     "created": "2020-10-19 09:30:00"}]}"#).unwrap();
+        assert_eq!(r.bookmark(3).map(|bkm| bkm.label.as_str()), Some("Map"));
+    }
+
+    const XMP: &str = r#"<x:xmpmeta xmlns:x="adobe:ns:meta/">
+  <rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+    <rdf:Description rdf:about="" xmlns:dc="http://purl.org/dc/elements/1.1/">
+      <dc:title>
+        <rdf:Alt>
+          <rdf:li xml:lang="x-default">Flatland &amp; Beyond</rdf:li>
+        </rdf:Alt>
+      </dc:title>
+      <dc:creator>
+        <rdf:Seq>
+          <rdf:li>Edwin A. Abbott</rdf:li>
+          <rdf:li> </rdf:li>
+          <rdf:li>A. Square</rdf:li>
+        </rdf:Seq>
+      </dc:creator>
+    </rdf:Description>
+  </rdf:RDF>
+</x:xmpmeta>"#;
+
+    #[test]
+    fn test_xmp_values() {
+        let root = XmlParser::new(XMP).parse();
+        assert_eq!(xmp_values(&root, "title"), vec!["Flatland & Beyond"]);
+        assert_eq!(xmp_values(&root, "creator"), vec!["Edwin A. Abbott", "A. Square"]);
+        assert!(xmp_values(&root, "subject").is_empty());
+        let root = XmlParser::new("<dc:title>Flatland</dc:title>").parse();
+        assert_eq!(xmp_values(&root, "title"), vec!["Flatland"]);
+    }
+
+    #[test]
+    fn test_xmp_precedence() {
+        let mut info = Info::default();
+        fill_from_xmp(XMP, &mut info);
+        assert_eq!(info.title, "Flatland & Beyond");
+        assert_eq!(info.author, "Edwin A. Abbott, A. Square");
+        let mut info = Info { title: "Flatland".to_string(), .. Default::default() };
+        fill_from_xmp(XMP, &mut info);
+        assert_eq!(info.title, "Flatland");
+        assert_eq!(info.author, "Edwin A. Abbott, A. Square");
+    }
+
+    #[test]
+    fn test_comic_info() {
+        let text = r#"<?xml version="1.0" encoding="utf-8"?>
+<ComicInfo xmlns:xsd="http://www.w3.org/2001/XMLSchema">
+  <Title>The Fall</Title>
+  <Series>Saga &amp; Co</Series>
+  <Number>7</Number>
+  <Volume>2</Volume>
+  <Year>2014</Year>
+  <Publisher>Image</Publisher>
+  <Writer>Brian K. Vaughan</Writer>
+  <Penciller>Fiona Staples, Brian K. Vaughan</Penciller>
+  <Genre>Science Fiction, Fantasy</Genre>
+  <Tags>Space</Tags>
+</ComicInfo>"#;
+        let mut info = Info::default();
+        parse_comic_info(text, &mut info);
+        assert_eq!(info.title, "The Fall");
+        assert_eq!(info.series, "Saga & Co");
+        assert_eq!(info.number, "7");
+        assert_eq!(info.volume, "2");
+        assert_eq!(info.year, "2014");
+        assert_eq!(info.publisher, "Image");
+        assert_eq!(info.author, "Brian K. Vaughan, Fiona Staples");
+        assert_eq!(info.categories.iter().map(String::as_str).collect::<Vec<&str>>(),
+                   vec!["Fantasy", "Science Fiction", "Space"]);
+    }
+
+    #[test]
+    fn test_comic_info_without_title() {
+        let text = "<ComicInfo><Series>Saga</Series><Number>7</Number></ComicInfo>";
+        let mut info = Info::default();
+        parse_comic_info(text, &mut info);
+        assert_eq!(info.title, "Saga");
+        assert!(info.series.is_empty());
+        assert_eq!(info.number, "7");
+    }
+}
@@ -1,4 +1,5 @@
 #include <mupdf/fitz.h>
+#include <mupdf/pdf.h>
 
 #define WRAP(name, ret_type, failure_val, call, ...) \
     ret_type mp_##name(fz_context *ctx, ##__VA_ARGS__) { \
@@ -16,3 +17,172 @@ WRAP(load_links, fz_link*, NULL, fz_load_links(ctx, page), fz_page *page)
 WRAP(count_pages, int, -1, fz_count_pages(ctx, doc), fz_document *doc)
 WRAP(new_pixmap_from_page, fz_pixmap*, NULL, fz_new_pixmap_from_page(ctx, page, mat, cs, alpha), fz_page *page, fz_matrix mat, fz_colorspace *cs, int alpha)
 WRAP(new_stext_page_from_page, fz_stext_page*, NULL, fz_new_stext_page_from_page(ctx, page, options), fz_page *page, fz_stext_options *options)
+
+fz_buffer *mp_load_xmp_metadata(fz_context *ctx, fz_document *doc) {
+    fz_buffer *buf = NULL;
+    pdf_document *pdf = pdf_specifics(ctx, doc);
+    if (!pdf) {
+        return NULL;
+    }
+    fz_try (ctx) {
+        pdf_obj *metadata = pdf_dict_getl(ctx, pdf_trailer(ctx, pdf), PDF_NAME(Root), PDF_NAME(Metadata), NULL);
+        if (metadata) {
+            buf = pdf_load_stream(ctx, metadata);
+        }
+    }
+    fz_catch (ctx) {
+        buf = NULL;
+    }
+    return buf;
+}
+
+typedef struct {
+    int index;
+    int style;
+    int start;
+    char prefix[64];
+} mp_page_label;
+
+static void walk_page_labels(fz_context *ctx, pdf_obj *node, mp_page_label *labels, int size, int *count, int depth) {
+    pdf_obj *kids, *nums;
+    int i, n;
+
+    if (depth > 32) {
+        return;
+    }
+
+    kids = pdf_dict_get(ctx, node, PDF_NAME(Kids));
+    n = pdf_array_len(ctx, kids);
+    for (i = 0; i < n; i++) {
+        walk_page_labels(ctx, pdf_array_get(ctx, kids, i), labels, size, count, depth + 1);
+    }
+
+    nums = pdf_dict_get(ctx, node, PDF_NAME(Nums));
+    n = pdf_array_len(ctx, nums);
+    for (i = 0; i + 1 < n; i += 2) {
+        if (*count < size) {
+            pdf_obj *dict = pdf_array_get(ctx, nums, i + 1);
+            pdf_obj *start = pdf_dict_get(ctx, dict, PDF_NAME(St));
+            mp_page_label *label = &labels[*count];
+            label->index = pdf_to_int(ctx, pdf_array_get(ctx, nums, i));
+            label->style = pdf_to_name(ctx, pdf_dict_get(ctx, dict, PDF_NAME(S)))[0];
+            label->start = start ? pdf_to_int(ctx, start) : 1;
+            fz_strlcpy(label->prefix, pdf_to_text_string(ctx, pdf_dict_get(ctx, dict, PDF_NAME(P))), sizeof label->prefix);
+        }
+        *count += 1;
+    }
+}
+
+// Returns the number of page label ranges, which might exceed *size*.
+int mp_load_page_labels(fz_context *ctx, fz_document *doc, mp_page_label *labels, int size) {
+    int count = 0;
+    pdf_document *pdf = pdf_specifics(ctx, doc);
+    if (!pdf) {
+        return 0;
+    }
+    fz_try (ctx) {
+        pdf_obj *root = pdf_dict_getl(ctx, pdf_trailer(ctx, pdf), PDF_NAME(Root), PDF_NAME(PageLabels), NULL);
+        if (root) {
+            walk_page_labels(ctx, root, labels, size, &count, 0);
+        }
+    }
+    fz_catch (ctx) {
+        count = 0;
+    }
+    return count;
+}
+
+// Square annotations are created for each quad, the others span all the quads.
+int mp_add_markup_annotation(fz_context *ctx, fz_document *doc, int page_index, int type, const fz_quad *quads, int count, float gray, const char *contents, const char *name) {
+    int ret = 0;
+    int i;
+    char nm[128];
+    pdf_page *page = NULL;
+    pdf_annot *annot = NULL;
+    pdf_document *pdf = pdf_specifics(ctx, doc);
+    if (!pdf) {
+        return -1;
+    }
+    fz_var(page);
+    fz_var(annot);
+    fz_try (ctx) {
+        page = pdf_load_page(ctx, pdf, page_index);
+        for (i = 0; i < count; i++) {
+            if (type != PDF_ANNOT_SQUARE && i > 0) {
+                break;
+            }
+            annot = pdf_create_annot(ctx, page, type);
+            if (type == PDF_ANNOT_SQUARE) {
+                pdf_set_annot_rect(ctx, annot, fz_rect_from_quad(quads[i]));
+            } else {
+                pdf_set_annot_quad_points(ctx, annot, count, quads);
+            }
+            pdf_set_annot_color(ctx, annot, 1, &gray);
+            fz_snprintf(nm, sizeof nm, "%s-%d", name, i);
+            pdf_dict_put_text_string(ctx, annot->obj, PDF_NAME(NM), nm);
+            if (i == 0 && contents && contents[0]) {
+                pdf_set_annot_contents(ctx, annot, contents);
+            }
+            pdf_update_annot(ctx, annot);
+            pdf_drop_annot(ctx, annot);
+            annot = NULL;
+        }
+    }
+    fz_always (ctx) {
+        pdf_drop_annot(ctx, annot);
+        fz_drop_page(ctx, (fz_page *) page);
+    }
+    fz_catch (ctx) {
+        ret = -1;
+    }
+    return ret;
+}
+
+// Deletes the annotations of the page whose names start with the given prefix.
+int mp_remove_named_annotations(fz_context *ctx, fz_document *doc, int page_index, const char *prefix) {
+    int count = 0;
+    size_t len = strlen(prefix);
+    pdf_page *page = NULL;
+    pdf_annot *annot, *next;
+    pdf_document *pdf = pdf_specifics(ctx, doc);
+    if (!pdf) {
+        return -1;
+    }
+    fz_var(page);
+    fz_var(count);
+    fz_try (ctx) {
+        page = pdf_load_page(ctx, pdf, page_index);
+        for (annot = pdf_first_annot(ctx, page); annot; annot = next) {
+            const char *nm = pdf_to_text_string(ctx, pdf_dict_get(ctx, annot->obj, PDF_NAME(NM)));
+            next = pdf_next_annot(ctx, annot);
+            if (!strncmp(nm, prefix, len)) {
+                pdf_delete_annot(ctx, page, annot);
+                count += 1;
+            }
+        }
+    }
+    fz_always (ctx) {
+        fz_drop_page(ctx, (fz_page *) page);
+    }
+    fz_catch (ctx) {
+        count = -1;
+    }
+    return count;
+}
+
+int mp_save_document(fz_context *ctx, fz_document *doc, const char *path, int incremental) {
+    int ret = 0;
+    pdf_document *pdf = pdf_specifics(ctx, doc);
+    if (!pdf) {
+        return -1;
+    }
+    fz_try (ctx) {
+        pdf_write_options opts = pdf_default_write_options;
+        opts.do_incremental = incremental;
+        pdf_save_document(ctx, pdf, path, &opts);
+    }
+    fz_catch (ctx) {
+        ret = -1;
+    }
+    return ret;
+}
@@ -100,17 +100,24 @@ impl fmt::Display for InputSource {
 
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(rename_all = "kebab-case")]
+#[serde(default, rename_all = "kebab-case")]
 pub struct RemarkableSettings {
     pub refresh_quality: RefreshQuality,
     pub input_sources: Vec<InputSource>,
+    // Ignore the new touches while the pen is near the screen.
+    pub palm_rejection: bool,
+    // Time, in milliseconds, during which the touches are still ignored
+    // after the pen has left.
+    pub palm_rejection_delay: u64,
 }
 
 impl Default for RemarkableSettings {
     fn default() -> Self {
         RemarkableSettings {
             refresh_quality: RefreshQuality::default(),
-            input_sources: vec![InputSource::Touch, InputSource::Pen]
+            input_sources: vec![InputSource::Touch, InputSource::Pen],
+            palm_rejection: true,
+            palm_rejection_delay: 500,
         }
     }
 }
@@ -142,6 +149,7 @@ pub struct Settings {
     pub dictionary: DictionarySettings,
     pub sketch: SketchSettings,
     pub calculator: CalculatorSettings,
+    pub export: ExportSettings,
     pub battery: BatterySettings,
     pub frontlight_levels: LightLevels,
 }
@@ -188,6 +196,8 @@ pub struct ImportSettings {
     pub startup_trigger: bool,
     pub traverse_hidden: bool,
     pub extract_epub_metadata: bool,
+    pub extract_cbz_metadata: bool,
+    pub extract_pdf_metadata: bool,
     pub allowed_kinds: FxHashSet<String>,
 }
 
@@ -226,11 +236,20 @@ pub struct CalculatorSettings {
     pub history_size: usize,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct ExportSettings {
+    // The directory, relative to the library, where the annotations are exported.
+    pub path: PathBuf,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(default, rename_all = "kebab-case")]
 pub struct Pen {
     pub size: i32,
     pub dynamic: bool,
+    // Whether the pressure of the stylus sets the width of the strokes.
+    pub pressure: bool,
     pub color: u8,
     pub min_speed: f32,
     pub max_speed: f32,
@@ -242,6 +261,7 @@ impl Default for Pen {
             size: 2,
             color: BLACK,
             dynamic: true,
+            pressure: true,
             min_speed: mm_to_px(3.0, CURRENT_DEVICE.dpi),
             max_speed: mm_to_px(152.4, CURRENT_DEVICE.dpi),
         }
@@ -258,6 +278,14 @@ impl Default for SketchSettings {
     }
 }
 
+impl Default for ExportSettings {
+    fn default() -> Self {
+        ExportSettings {
+            path: PathBuf::from("Annotations"),
+        }
+    }
+}
+
 impl Default for CalculatorSettings {
     fn default() -> Self {
         CalculatorSettings {
@@ -337,6 +365,16 @@ pub struct ReaderSettings {
     pub text_align: TextAlign,
     pub margin_width: i32,
     pub line_height: f32,
+    pub remember_passwords: bool,
+    pub annotate_in_place: bool,
+    // The directory, relative to the library, where the annotated copies of the PDF files are
+    // written. It's hidden so that the copies aren't imported.
+    pub annotated_path: PathBuf,
+    // Show two pages side by side in landscape orientation.
+    pub two_pages: bool,
+    // The button of the pen that must be held to select text with it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pen_selection_button: Option<PenButton>,
     pub refresh_rate: RefreshRateSettings,
 }
 
@@ -347,6 +385,13 @@ pub struct BatterySettings {
     pub power_off: f32,
 }
 
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PenButton {
+    Lower,
+    Upper,
+}
+
 #[derive(Debug, Copy, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub enum FinishedAction {
@@ -384,6 +429,11 @@ impl Default for ReaderSettings {
             text_align: DEFAULT_TEXT_ALIGN,
             margin_width: DEFAULT_MARGIN_WIDTH,
             line_height: DEFAULT_LINE_HEIGHT,
+            remember_passwords: false,
+            annotate_in_place: false,
+            annotated_path: PathBuf::from(".annotated"),
+            two_pages: true,
+            pen_selection_button: None,
         }
     }
 }
@@ -395,6 +445,8 @@ impl Default for ImportSettings {
             startup_trigger: true,
             traverse_hidden: false,
             extract_epub_metadata: true,
+            extract_cbz_metadata: true,
+            extract_pdf_metadata: true,
             allowed_kinds: ["pdf", "djvu", "epub",
                             "fb2", "xps", "oxps", "cbz"].iter().map(|k| k.to_string()).collect(),
         }
@@ -437,6 +489,7 @@ impl Default for Settings {
             dictionary: DictionarySettings::default(),
             sketch: SketchSettings::default(),
             calculator: CalculatorSettings::default(),
+            export: ExportSettings::default(),
             battery: BatterySettings::default(),
             frontlight_levels: LightLevels::default(),
             frontlight_presets: Vec::new(),
@@ -0,0 +1,491 @@
+use std::sync::{Arc, Mutex, mpsc};
+use fxhash::FxHashMap;
+use chrono::Local;
+use crate::device::CURRENT_DEVICE;
+use crate::framebuffer::{Framebuffer, UpdateMode};
+use crate::geom::{Rectangle, halves};
+use crate::unit::scale_by_dpi;
+use crate::font::Fonts;
+use crate::view::{View, Event, Hub, Bus, ViewId, EntryId, EntryKind};
+use crate::view::{SMALL_BAR_HEIGHT, THICKNESS_MEDIUM};
+use crate::metadata::{Info, Annotation};
+use crate::view::common::locate_by_id;
+use crate::view::common::{toggle_main_menu, toggle_battery_menu, toggle_clock_menu};
+use crate::gesture::GestureEvent;
+use crate::color::BLACK;
+use crate::helpers::escape_html;
+use crate::app::Context;
+use crate::view::filler::Filler;
+use crate::view::named_input::NamedInput;
+use crate::view::menu::{Menu, MenuKind};
+use crate::view::search_bar::SearchBar;
+use crate::view::top_bar::TopBar;
+use crate::view::html_list::{HtmlList, Labels, toggle_keyboard, resize_keyboard, load_labels};
+
+const USER_STYLESHEET: &str = "css/annotations-user.css";
+// Maximum number of characters of the excerpts.
+const EXCERPT_LENGTH: usize = 280;
+
+pub struct Annotations {
+    rect: Rectangle,
+    children: Vec<Box<dyn View>>,
+    // The annotated documents. When the view is opened from a reader,
+    // it only holds the document of the reader.
+    books: Vec<Info>,
+    from_reader: bool,
+    // The location labels of the annotations, by document and start location.
+    labels: Labels,
+    // The listed annotations, as indices in `books` and in their annotations.
+    items: Vec<(usize, usize)>,
+    query: String,
+    // The index, in `items`, of the annotation whose menu was opened.
+    target: Option<usize>,
+    focus: Option<ViewId>,
+}
+
+impl Annotations {
+    pub fn new(rect: Rectangle, book: Option<Info>, hub: &Hub, context: &mut Context) -> Annotations {
+        let mut children = Vec::new();
+        let dpi = CURRENT_DEVICE.dpi;
+        let small_height = scale_by_dpi(SMALL_BAR_HEIGHT, dpi) as i32;
+        let thickness = scale_by_dpi(THICKNESS_MEDIUM, dpi) as i32;
+        let (small_thickness, big_thickness) = halves(thickness);
+        let from_reader = book.is_some();
+
+        let title = book.as_ref().map_or_else(|| "Annotations".to_string(), |info| info.title());
+        let top_bar = TopBar::new(rect![rect.min.x, rect.min.y,
+                                        rect.max.x, rect.min.y + small_height - small_thickness],
+                                  Event::Back,
+                                  title,
+                                  context);
+        children.push(Box::new(top_bar) as Box<dyn View>);
+
+        let separator = Filler::new(rect![rect.min.x, rect.min.y + small_height - small_thickness,
+                                          rect.max.x, rect.min.y + small_height + big_thickness],
+                                    BLACK);
+        children.push(Box::new(separator) as Box<dyn View>);
+
+        let search_bar = SearchBar::new(rect![rect.min.x, rect.min.y + small_height + big_thickness,
+                                              rect.max.x, rect.min.y + 2 * small_height - small_thickness],
+                                        ViewId::AnnotationsSearchInput,
+                                        "Filter", "", context);
+        children.push(Box::new(search_bar) as Box<dyn View>);
+
+        let separator = Filler::new(rect![rect.min.x, rect.min.y + 2 * small_height - small_thickness,
+                                          rect.max.x, rect.min.y + 2 * small_height + big_thickness],
+                                    BLACK);
+        children.push(Box::new(separator) as Box<dyn View>);
+
+        let list = HtmlList::new(rect![rect.min.x, rect.min.y + 2 * small_height + big_thickness,
+                                       rect.max.x, rect.max.y],
+                                 USER_STYLESHEET, context);
+        children.push(Box::new(list) as Box<dyn View>);
+
+        let mut annotations = Annotations {
+            rect,
+            children,
+            books: book.into_iter().collect(),
+            from_reader,
+            labels: Arc::new(Mutex::new(FxHashMap::default())),
+            items: Vec::new(),
+            query: String::new(),
+            target: None,
+            focus: None,
+        };
+
+        if !from_reader {
+            annotations.books = context.library.annotated_documents();
+        }
+
+        annotations.update(true, hub);
+        annotations.load_labels(hub, context);
+
+        hub.send(Event::Render(rect, UpdateMode::Gui)).ok();
+
+        annotations
+    }
+
+    fn toggle_annotation_menu(&mut self, rect: Rectangle, enable: Option<bool>, hub: &Hub, context: &mut Context) {
+        if let Some(index) = locate_by_id(self, ViewId::AnnotationMenu) {
+            if let Some(true) = enable {
+                return;
+            }
+
+            hub.send(Event::Expose(*self.child(index).rect(), UpdateMode::Gui)).ok();
+            self.children.remove(index);
+        } else {
+            if let Some(false) = enable {
+                return;
+            }
+
+            let annot = match self.target.and_then(|i| self.items.get(i)) {
+                Some(&(book, index)) => &self.books[book].reader.as_ref().unwrap().annotations[index],
+                None => return,
+            };
+
+            let sel = annot.selection;
+            let mut entries = vec![EntryKind::Command("Go To".to_string(), EntryId::GoTo(sel[0].location())),
+                                   EntryKind::Separator];
+
+            if annot.note.is_empty() {
+                entries.push(EntryKind::Command("Add Note".to_string(), EntryId::EditAnnotationNote(sel)));
+                entries.push(EntryKind::Separator);
+                entries.push(EntryKind::Command("Remove Highlight".to_string(), EntryId::RemoveAnnotation(sel)));
+            } else {
+                entries.push(EntryKind::Command("Edit Note".to_string(), EntryId::EditAnnotationNote(sel)));
+                entries.push(EntryKind::Command("Remove Note".to_string(), EntryId::RemoveAnnotationNote(sel)));
+                entries.push(EntryKind::Separator);
+                entries.push(EntryKind::Command("Remove Annotation".to_string(), EntryId::RemoveAnnotation(sel)));
+            }
+
+            if annot.orphan {
+                entries.push(EntryKind::Command("Remove Not Found".to_string(), EntryId::RemoveOrphanAnnotations));
+            }
+
+            let annotation_menu = Menu::new(rect, ViewId::AnnotationMenu, MenuKind::Contextual, entries, context);
+            hub.send(Event::Render(*annotation_menu.rect(), UpdateMode::Gui)).ok();
+            self.children.push(Box::new(annotation_menu) as Box<dyn View>);
+        }
+    }
+
+    fn toggle_edit_note(&mut self, text: Option<String>, enable: Option<bool>, hub: &Hub, context: &mut Context) {
+        if let Some(index) = locate_by_id(self, ViewId::EditNote) {
+            if let Some(true) = enable {
+                return;
+            }
+
+            hub.send(Event::Expose(*self.child(index).rect(), UpdateMode::Gui)).ok();
+            self.children.remove(index);
+
+            if self.focus.map(|focus_id| focus_id == ViewId::EditNoteInput).unwrap_or(false) {
+                toggle_keyboard(self, false, hub, context);
+            }
+        } else {
+            if let Some(false) = enable {
+                return;
+            }
+
+            let mut edit_note = NamedInput::new("Note".to_string(), ViewId::EditNote, ViewId::EditNoteInput, 32, context);
+            if let Some(text) = text.as_ref() {
+                let (tx, _rx) = mpsc::channel();
+                edit_note.set_text(text, &tx, context);
+            }
+
+            hub.send(Event::Render(*edit_note.rect(), UpdateMode::Gui)).ok();
+            hub.send(Event::Focus(Some(ViewId::EditNoteInput))).ok();
+
+            self.children.push(Box::new(edit_note) as Box<dyn View>);
+        }
+    }
+
+    // Lists the annotations that match the query, sorted by document and location.
+    fn update(&mut self, reset: bool, hub: &Hub) {
+        let query = self.query.to_lowercase();
+        let from_reader = self.from_reader;
+        self.items.clear();
+
+        for (i, info) in self.books.iter().enumerate() {
+            let annotations = match info.reader.as_ref() {
+                Some(r) => &r.annotations,
+                None => continue,
+            };
+            let title = info.title().to_lowercase();
+            let mut indices = (0..annotations.len()).filter(|&j| {
+                let annot = &annotations[j];
+                query.is_empty() ||
+                annot.text.to_lowercase().contains(&query) ||
+                annot.note.to_lowercase().contains(&query) ||
+                (!from_reader && title.contains(&query))
+            }).collect::<Vec<usize>>();
+            indices.sort_by_key(|&j| annotations[j].selection[0]);
+            self.items.extend(indices.into_iter().map(|j| (i, j)));
+        }
+
+        let mut content = String::new();
+        let mut last_book = None;
+        let labels = self.labels.lock().unwrap();
+
+        for (k, &(i, j)) in self.items.iter().enumerate() {
+            let info = &self.books[i];
+            let annot = &info.reader.as_ref().unwrap().annotations[j];
+
+            if !from_reader && last_book != Some(i) {
+                content.push_str(&format!("<h2 class=\"title\">{}</h2>\n", escape_html(&info.title())));
+                last_book = Some(i);
+            }
+
+            let mut excerpt = annot.text.trim().to_string();
+            if let Some((index, _)) = excerpt.char_indices().nth(EXCERPT_LENGTH) {
+                excerpt.truncate(index);
+                excerpt.push('…');
+            }
+
+            content.push_str("<div class=\"annotation\">\n");
+            content.push_str(&format!("<p class=\"excerpt\"><a href=\"@{}\">{}</a></p>\n", k, escape_html(&excerpt)));
+            if !annot.note.is_empty() {
+                content.push_str(&format!("<p class=\"note\">{}</p>\n", escape_html(&annot.note)));
+            }
+            let mut info_parts = Vec::new();
+            if let Some(label) = labels.get(&info.file.path)
+                                       .and_then(|l| l.get(&annot.selection[0]))
+                                       .filter(|l| !l.is_empty()) {
+                info_parts.push(escape_html(label));
+            }
+            info_parts.push(annot.modified.format("%Y-%m-%d %H:%M").to_string());
+            if annot.orphan {
+                info_parts.push("not found".to_string());
+            }
+            content.push_str(&format!("<p class=\"info\">{}</p>\n", info_parts.join(" · ")));
+            content.push_str("</div>\n");
+        }
+
+        drop(labels);
+
+        if self.items.is_empty() {
+            if self.query.is_empty() {
+                content.push_str("<p class=\"empty\">No annotations.</p>");
+            } else {
+                content.push_str("<p class=\"empty\">No annotations found.</p>");
+            }
+        }
+
+        let count = self.items.len();
+        let name = if count == 1 { "1 annotation".to_string() } else { format!("{} annotations", count) };
+
+        if let Some(list) = self.children[4].downcast_mut::<HtmlList>() {
+            list.update(&content, &name, reset, hub);
+        }
+    }
+
+    fn reseed(&mut self, hub: &Hub, context: &mut Context) {
+        // The annotations might have been modified by a reader.
+        if !self.from_reader {
+            self.books = context.library.annotated_documents();
+            self.update(false, hub);
+            self.load_labels(hub, context);
+        }
+
+        let (tx, _rx) = mpsc::channel();
+        if let Some(top_bar) = self.child_mut(0).downcast_mut::<TopBar>() {
+            top_bar.update_frontlight_icon(&tx, context);
+        }
+        hub.send(Event::ClockTick).ok();
+        hub.send(Event::BatteryTick).ok();
+        hub.send(Event::Render(self.rect, UpdateMode::Gui)).ok();
+    }
+
+    fn load_labels(&self, hub: &Hub, context: &Context) {
+        let jobs = self.books.iter().filter_map(|info| {
+            let r = info.reader.as_ref()?;
+            let locations = r.annotations.iter().map(|annot| annot.selection[0]).collect();
+            Some((info.file.path.clone(), Some(r.clone()), locations))
+        }).collect();
+        load_labels(&self.labels, jobs, &context.library.home, hub);
+    }
+
+    // Applies the given change to the targeted annotation, and saves it.
+    fn edit_target<F>(&mut self, f: F, hub: &Hub, context: &mut Context) where F: FnOnce(&mut Vec<Annotation>, usize) {
+        if let Some(&(book, index)) = self.target.and_then(|i| self.items.get(i)) {
+            let info = &mut self.books[book];
+            if let Some(r) = info.reader.as_mut() {
+                f(&mut r.annotations, index);
+                if !self.from_reader {
+                    context.library.sync_reader_info(&info.file.path, r);
+                }
+            }
+            self.update(false, hub);
+        }
+    }
+
+    // Leaves the view. A reader gets back the modified annotations of its document.
+    fn close(&mut self, bus: &mut Bus) {
+        if self.from_reader {
+            if let Some(r) = self.books.get(0).and_then(|info| info.reader.as_ref()) {
+                bus.push_back(Event::UpdateAnnotations(r.annotations.clone()));
+            }
+        }
+    }
+}
+
+impl View for Annotations {
+    fn handle_event(&mut self, evt: &Event, hub: &Hub, bus: &mut Bus, context: &mut Context) -> bool {
+        match *evt {
+            Event::Submit(ViewId::AnnotationsSearchInput, ref text) => {
+                self.query = text.to_string();
+                toggle_keyboard(self, false, hub, context);
+                self.update(true, hub);
+                true
+            },
+            Event::Submit(ViewId::EditNoteInput, ref note) => {
+                let note = note.to_string();
+                self.edit_target(|annotations, index| {
+                    annotations[index].note = note;
+                    annotations[index].modified = Local::now();
+                }, hub, context);
+                true
+            },
+            Event::ToggleItemMenu(index, rect) => {
+                self.target = Some(index);
+                self.toggle_annotation_menu(rect, Some(true), hub, context);
+                true
+            },
+            Event::Select(EntryId::GoTo(location)) => {
+                if let Some(&(book, _)) = self.target.and_then(|i| self.items.get(i)) {
+                    if self.from_reader {
+                        bus.push_back(Event::Back);
+                        self.close(bus);
+                        bus.push_back(Event::GoTo(location));
+                    } else {
+                        // The reader opens the document at its saved page.
+                        let mut info = self.books[book].clone();
+                        if let Some(r) = info.reader.as_mut() {
+                            r.current_page = location;
+                            r.top_offset = None;
+                            r.finished = false;
+                        }
+                        hub.send(Event::Open(Box::new(info))).ok();
+                    }
+                }
+                true
+            },
+            Event::Select(EntryId::EditAnnotationNote(_)) => {
+                let text = self.target.and_then(|i| self.items.get(i))
+                               .and_then(|&(book, index)| self.books[book].reader.as_ref()
+                                                              .map(|r| r.annotations[index].note.clone()))
+                               .filter(|note| !note.is_empty());
+                self.toggle_edit_note(text, Some(true), hub, context);
+                true
+            },
+            Event::Select(EntryId::RemoveAnnotationNote(_)) => {
+                self.edit_target(|annotations, index| {
+                    annotations[index].note.clear();
+                    annotations[index].modified = Local::now();
+                }, hub, context);
+                true
+            },
+            Event::Select(EntryId::RemoveAnnotation(_)) => {
+                self.edit_target(|annotations, index| {
+                    annotations.remove(index);
+                }, hub, context);
+                self.target = None;
+                true
+            },
+            // Removes the annotations of the targeted document that can't be found.
+            Event::Select(EntryId::RemoveOrphanAnnotations) => {
+                self.edit_target(|annotations, _| {
+                    annotations.retain(|annot| !annot.orphan);
+                }, hub, context);
+                self.target = None;
+                true
+            },
+            Event::Close(ViewId::EditNote) => {
+                toggle_keyboard(self, false, hub, context);
+                false
+            },
+            Event::Close(ViewId::SearchBar) => {
+                if let Some(search_bar) = self.children[2].downcast_mut::<SearchBar>() {
+                    search_bar.set_text("", hub, context);
+                }
+                toggle_keyboard(self, false, hub, context);
+                if !self.query.is_empty() {
+                    self.query.clear();
+                    self.update(true, hub);
+                }
+                true
+            },
+            Event::Focus(v) => {
+                self.focus = v;
+                if v.is_some() {
+                    toggle_keyboard(self, true, hub, context);
+                }
+                true
+            },
+            Event::ToggleNear(ViewId::SearchMenu, _) => true,
+            Event::ToggleNear(ViewId::MainMenu, rect) => {
+                toggle_main_menu(self, rect, None, hub, context);
+                true
+            },
+            Event::ToggleNear(ViewId::BatteryMenu, rect) => {
+                toggle_battery_menu(self, rect, None, hub, context);
+                true
+            },
+            Event::ToggleNear(ViewId::ClockMenu, rect) => {
+                toggle_clock_menu(self, rect, None, hub, context);
+                true
+            },
+            Event::Reseed => {
+                self.reseed(hub, context);
+                true
+            },
+            Event::LoadLabels => {
+                self.update(false, hub);
+                true
+            },
+            Event::Back => {
+                self.close(bus);
+                false
+            },
+            Event::Gesture(GestureEvent::Cross(_)) => {
+                bus.push_back(Event::Back);
+                self.close(bus);
+                true
+            },
+            _ => false,
+        }
+    }
+
+    fn render(&self, _fb: &mut dyn Framebuffer, _rect: Rectangle, _fonts: &mut Fonts) {
+    }
+
+    fn resize(&mut self, rect: Rectangle, hub: &Hub, context: &mut Context) {
+        let dpi = CURRENT_DEVICE.dpi;
+        let small_height = scale_by_dpi(SMALL_BAR_HEIGHT, dpi) as i32;
+        let thickness = scale_by_dpi(THICKNESS_MEDIUM, dpi) as i32;
+        let (small_thickness, big_thickness) = halves(thickness);
+
+        self.children[0].resize(rect![rect.min.x, rect.min.y,
+                                      rect.max.x, rect.min.y + small_height - small_thickness],
+                                hub, context);
+
+        self.children[1].resize(rect![rect.min.x, rect.min.y + small_height - small_thickness,
+                                      rect.max.x, rect.min.y + small_height + big_thickness],
+                                hub, context);
+
+        self.children[2].resize(rect![rect.min.x, rect.min.y + small_height + big_thickness,
+                                      rect.max.x, rect.min.y + 2 * small_height - small_thickness],
+                                hub, context);
+
+        self.children[3].resize(rect![rect.min.x, rect.min.y + 2 * small_height - small_thickness,
+                                      rect.max.x, rect.min.y + 2 * small_height + big_thickness],
+                                hub, context);
+
+        self.children[4].resize(rect![rect.min.x, rect.min.y + 2 * small_height + big_thickness,
+                                      rect.max.x, rect.max.y],
+                                hub, context);
+
+        let index = resize_keyboard(self, rect, hub, context);
+
+        for i in index..self.children.len() {
+            self.children[i].resize(rect, hub, context);
+        }
+
+        self.rect = rect;
+        hub.send(Event::Render(self.rect, UpdateMode::Full)).ok();
+    }
+
+    fn rect(&self) -> &Rectangle {
+        &self.rect
+    }
+
+    fn rect_mut(&mut self) -> &mut Rectangle {
+        &mut self.rect
+    }
+
+    fn children(&self) -> &Vec<Box<dyn View>> {
+        &self.children
+    }
+
+    fn children_mut(&mut self) -> &mut Vec<Box<dyn View>> {
+        &mut self.children
+    }
+}
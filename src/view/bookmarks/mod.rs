@@ -0,0 +1,337 @@
+use std::sync::{Arc, Mutex, mpsc};
+use fxhash::FxHashMap;
+use crate::device::CURRENT_DEVICE;
+use crate::framebuffer::{Framebuffer, UpdateMode};
+use crate::geom::{Rectangle, halves};
+use crate::unit::scale_by_dpi;
+use crate::font::Fonts;
+use crate::view::{View, Event, Hub, Bus, ViewId, EntryId, EntryKind};
+use crate::view::{SMALL_BAR_HEIGHT, THICKNESS_MEDIUM};
+use crate::document::TextLocation;
+use crate::metadata::Info;
+use crate::view::common::locate_by_id;
+use crate::view::common::{toggle_main_menu, toggle_battery_menu, toggle_clock_menu};
+use crate::gesture::GestureEvent;
+use crate::color::BLACK;
+use crate::helpers::escape_html;
+use crate::app::Context;
+use crate::view::filler::Filler;
+use crate::view::named_input::NamedInput;
+use crate::view::menu::{Menu, MenuKind};
+use crate::view::top_bar::TopBar;
+use crate::view::html_list::{HtmlList, Labels, toggle_keyboard, resize_keyboard, load_labels};
+
+const USER_STYLESHEET: &str = "css/bookmarks-user.css";
+
+pub struct Bookmarks {
+    rect: Rectangle,
+    children: Vec<Box<dyn View>>,
+    info: Info,
+    // The location labels of the bookmarks.
+    labels: Labels,
+    // The index of the bookmark whose menu was opened.
+    target: Option<usize>,
+    focus: Option<ViewId>,
+}
+
+impl Bookmarks {
+    pub fn new(rect: Rectangle, info: Info, hub: &Hub, context: &mut Context) -> Bookmarks {
+        let mut children = Vec::new();
+        let dpi = CURRENT_DEVICE.dpi;
+        let small_height = scale_by_dpi(SMALL_BAR_HEIGHT, dpi) as i32;
+        let thickness = scale_by_dpi(THICKNESS_MEDIUM, dpi) as i32;
+        let (small_thickness, big_thickness) = halves(thickness);
+
+        let top_bar = TopBar::new(rect![rect.min.x, rect.min.y,
+                                        rect.max.x, rect.min.y + small_height - small_thickness],
+                                  Event::Back,
+                                  info.title(),
+                                  context);
+        children.push(Box::new(top_bar) as Box<dyn View>);
+
+        let separator = Filler::new(rect![rect.min.x, rect.min.y + small_height - small_thickness,
+                                          rect.max.x, rect.min.y + small_height + big_thickness],
+                                    BLACK);
+        children.push(Box::new(separator) as Box<dyn View>);
+
+        let list = HtmlList::new(rect![rect.min.x, rect.min.y + small_height + big_thickness,
+                                       rect.max.x, rect.max.y],
+                                 USER_STYLESHEET, context);
+        children.push(Box::new(list) as Box<dyn View>);
+
+        let mut bookmarks = Bookmarks {
+            rect,
+            children,
+            info,
+            labels: Arc::new(Mutex::new(FxHashMap::default())),
+            target: None,
+            focus: None,
+        };
+
+        bookmarks.update(true, hub);
+        bookmarks.load_labels(hub, context);
+
+        hub.send(Event::Render(rect, UpdateMode::Gui)).ok();
+
+        bookmarks
+    }
+
+    fn toggle_bookmark_menu(&mut self, rect: Rectangle, enable: Option<bool>, hub: &Hub, context: &mut Context) {
+        if let Some(index) = locate_by_id(self, ViewId::BookmarkMenu) {
+            if let Some(true) = enable {
+                return;
+            }
+
+            hub.send(Event::Expose(*self.child(index).rect(), UpdateMode::Gui)).ok();
+            self.children.remove(index);
+        } else {
+            if let Some(false) = enable {
+                return;
+            }
+
+            let location = match self.target.and_then(|i| self.info.reader.as_ref()?.bookmarks.get(i)) {
+                Some(bkm) => bkm.location,
+                None => return,
+            };
+
+            let entries = vec![EntryKind::Command("Go To".to_string(), EntryId::GoTo(location)),
+                               EntryKind::Separator,
+                               EntryKind::Command("Rename".to_string(), EntryId::RenameBookmark(location)),
+                               EntryKind::Command("Remove".to_string(), EntryId::RemoveBookmark(location))];
+
+            let bookmark_menu = Menu::new(rect, ViewId::BookmarkMenu, MenuKind::Contextual, entries, context);
+            hub.send(Event::Render(*bookmark_menu.rect(), UpdateMode::Gui)).ok();
+            self.children.push(Box::new(bookmark_menu) as Box<dyn View>);
+        }
+    }
+
+    fn toggle_rename_bookmark(&mut self, text: Option<String>, enable: Option<bool>, hub: &Hub, context: &mut Context) {
+        if let Some(index) = locate_by_id(self, ViewId::RenameBookmark) {
+            if let Some(true) = enable {
+                return;
+            }
+
+            hub.send(Event::Expose(*self.child(index).rect(), UpdateMode::Gui)).ok();
+            self.children.remove(index);
+
+            if self.focus.map(|focus_id| focus_id == ViewId::RenameBookmarkInput).unwrap_or(false) {
+                toggle_keyboard(self, false, hub, context);
+            }
+        } else {
+            if let Some(false) = enable {
+                return;
+            }
+
+            let mut rename_bookmark = NamedInput::new("Label".to_string(), ViewId::RenameBookmark, ViewId::RenameBookmarkInput, 24, context);
+            if let Some(text) = text.as_ref() {
+                let (tx, _rx) = mpsc::channel();
+                rename_bookmark.set_text(text, &tx, context);
+            }
+
+            hub.send(Event::Render(*rename_bookmark.rect(), UpdateMode::Gui)).ok();
+            hub.send(Event::Focus(Some(ViewId::RenameBookmarkInput))).ok();
+
+            self.children.push(Box::new(rename_bookmark) as Box<dyn View>);
+        }
+    }
+
+    fn update(&mut self, reset: bool, hub: &Hub) {
+        let mut content = String::new();
+        let bookmarks = self.info.reader.as_ref().map(|r| r.bookmarks.as_slice()).unwrap_or_default();
+        let labels = self.labels.lock().unwrap();
+        let doc_labels = labels.get(&self.info.file.path);
+
+        for (k, bkm) in bookmarks.iter().enumerate() {
+            let location_label = doc_labels.and_then(|l| l.get(&TextLocation::Static(bkm.location, 0)))
+                                           .filter(|l| !l.is_empty());
+            let title = if !bkm.label.is_empty() {
+                bkm.label.clone()
+            } else if let Some(label) = location_label {
+                label.clone()
+            } else {
+                "Bookmark".to_string()
+            };
+
+            content.push_str("<div class=\"bookmark\">\n");
+            content.push_str(&format!("<p class=\"label\"><a href=\"@{}\">{}</a></p>\n", k, escape_html(&title)));
+            if !bkm.text.is_empty() {
+                content.push_str(&format!("<p class=\"excerpt\">{}</p>\n", escape_html(&bkm.text)));
+            }
+            let mut info_parts = Vec::new();
+            if let Some(label) = location_label.filter(|_| !bkm.label.is_empty()) {
+                info_parts.push(escape_html(label));
+            }
+            info_parts.push(bkm.created.format("%Y-%m-%d %H:%M").to_string());
+            content.push_str(&format!("<p class=\"info\">{}</p>\n", info_parts.join(" · ")));
+            content.push_str("</div>\n");
+        }
+
+        if bookmarks.is_empty() {
+            content.push_str("<p class=\"empty\">No bookmarks.</p>");
+        }
+
+        let count = bookmarks.len();
+        let name = if count == 1 { "1 bookmark".to_string() } else { format!("{} bookmarks", count) };
+        drop(labels);
+
+        if let Some(list) = self.children[2].downcast_mut::<HtmlList>() {
+            list.update(&content, &name, reset, hub);
+        }
+    }
+
+    fn reseed(&mut self, hub: &Hub, context: &mut Context) {
+        let (tx, _rx) = mpsc::channel();
+        if let Some(top_bar) = self.child_mut(0).downcast_mut::<TopBar>() {
+            top_bar.update_frontlight_icon(&tx, context);
+        }
+        hub.send(Event::ClockTick).ok();
+        hub.send(Event::BatteryTick).ok();
+        hub.send(Event::Render(self.rect, UpdateMode::Gui)).ok();
+    }
+
+    fn load_labels(&self, hub: &Hub, context: &Context) {
+        let r = match self.info.reader.as_ref() {
+            Some(r) => r,
+            None => return,
+        };
+        let locations = r.bookmarks.iter().map(|bkm| TextLocation::Static(bkm.location, 0)).collect();
+        let jobs = vec![(self.info.file.path.clone(), Some(r.clone()), locations)];
+        load_labels(&self.labels, jobs, &context.library.home, hub);
+    }
+
+    // Leaves the view. The reader gets back the modified bookmarks of its document.
+    fn close(&mut self, bus: &mut Bus) {
+        if let Some(r) = self.info.reader.as_ref() {
+            bus.push_back(Event::UpdateBookmarks(r.bookmarks.clone()));
+        }
+    }
+}
+
+impl View for Bookmarks {
+    fn handle_event(&mut self, evt: &Event, hub: &Hub, bus: &mut Bus, context: &mut Context) -> bool {
+        match *evt {
+            Event::Submit(ViewId::RenameBookmarkInput, ref label) => {
+                if let Some(bkm) = self.target.and_then(|i| self.info.reader.as_mut()?.bookmarks.get_mut(i)) {
+                    bkm.label = label.trim().to_string();
+                }
+                self.update(false, hub);
+                true
+            },
+            Event::ToggleItemMenu(index, rect) => {
+                self.target = Some(index);
+                self.toggle_bookmark_menu(rect, Some(true), hub, context);
+                true
+            },
+            Event::Select(EntryId::GoTo(location)) => {
+                bus.push_back(Event::Back);
+                self.close(bus);
+                bus.push_back(Event::GoToBookmark(location));
+                true
+            },
+            Event::Select(EntryId::RenameBookmark(location)) => {
+                let text = self.info.reader.as_ref()
+                               .and_then(|r| r.bookmark(location))
+                               .map(|bkm| bkm.label.clone())
+                               .filter(|label| !label.is_empty());
+                self.toggle_rename_bookmark(text, Some(true), hub, context);
+                true
+            },
+            Event::Select(EntryId::RemoveBookmark(location)) => {
+                if let Some(r) = self.info.reader.as_mut() {
+                    r.bookmarks.retain(|bkm| bkm.location != location);
+                }
+                self.target = None;
+                self.update(false, hub);
+                true
+            },
+            Event::Close(ViewId::RenameBookmark) => {
+                toggle_keyboard(self, false, hub, context);
+                false
+            },
+            Event::Focus(v) => {
+                self.focus = v;
+                if v.is_some() {
+                    toggle_keyboard(self, true, hub, context);
+                }
+                true
+            },
+            Event::ToggleNear(ViewId::MainMenu, rect) => {
+                toggle_main_menu(self, rect, None, hub, context);
+                true
+            },
+            Event::ToggleNear(ViewId::BatteryMenu, rect) => {
+                toggle_battery_menu(self, rect, None, hub, context);
+                true
+            },
+            Event::ToggleNear(ViewId::ClockMenu, rect) => {
+                toggle_clock_menu(self, rect, None, hub, context);
+                true
+            },
+            Event::Reseed => {
+                self.reseed(hub, context);
+                true
+            },
+            Event::LoadLabels => {
+                self.update(false, hub);
+                true
+            },
+            Event::Back => {
+                self.close(bus);
+                false
+            },
+            Event::Gesture(GestureEvent::Cross(_)) => {
+                bus.push_back(Event::Back);
+                self.close(bus);
+                true
+            },
+            _ => false,
+        }
+    }
+
+    fn render(&self, _fb: &mut dyn Framebuffer, _rect: Rectangle, _fonts: &mut Fonts) {
+    }
+
+    fn resize(&mut self, rect: Rectangle, hub: &Hub, context: &mut Context) {
+        let dpi = CURRENT_DEVICE.dpi;
+        let small_height = scale_by_dpi(SMALL_BAR_HEIGHT, dpi) as i32;
+        let thickness = scale_by_dpi(THICKNESS_MEDIUM, dpi) as i32;
+        let (small_thickness, big_thickness) = halves(thickness);
+
+        self.children[0].resize(rect![rect.min.x, rect.min.y,
+                                      rect.max.x, rect.min.y + small_height - small_thickness],
+                                hub, context);
+
+        self.children[1].resize(rect![rect.min.x, rect.min.y + small_height - small_thickness,
+                                      rect.max.x, rect.min.y + small_height + big_thickness],
+                                hub, context);
+
+        self.children[2].resize(rect![rect.min.x, rect.min.y + small_height + big_thickness,
+                                      rect.max.x, rect.max.y],
+                                hub, context);
+
+        let index = resize_keyboard(self, rect, hub, context);
+
+        for i in index..self.children.len() {
+            self.children[i].resize(rect, hub, context);
+        }
+
+        self.rect = rect;
+        hub.send(Event::Render(self.rect, UpdateMode::Full)).ok();
+    }
+
+    fn rect(&self) -> &Rectangle {
+        &self.rect
+    }
+
+    fn rect_mut(&mut self) -> &mut Rectangle {
+        &mut self.rect
+    }
+
+    fn children(&self) -> &Vec<Box<dyn View>> {
+        &self.children
+    }
+
+    fn children_mut(&mut self) -> &mut Vec<Box<dyn View>> {
+        &mut self.children
+    }
+}
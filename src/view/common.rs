@@ -8,6 +8,7 @@ use crate::geom::{Point, Rectangle};
 use super::{View, Event, Hub, ViewId, AppCmd, EntryId, EntryKind};
 use super::menu::{Menu, MenuKind};
 use super::notification::Notification;
+use super::home::Home;
 use crate::app::Context;
 use super::RefreshQuality;
 
@@ -81,12 +82,17 @@ pub fn toggle_main_menu(view: &mut dyn View, rect: Rectangle, enable: Option<boo
                                    n == rotation)
         ).collect::<Vec<EntryKind>>();
 
-        let apps = vec![EntryKind::Command("Dictionary".to_string(),
+        let mut apps = vec![EntryKind::Command("Dictionary".to_string(),
                                            EntryId::Launch(AppCmd::Dictionary { query: "".to_string(), language: "".to_string() })),
                         EntryKind::Command("Calculator".to_string(),
                                            EntryId::Launch(AppCmd::Calculator)),
                         EntryKind::Command("Sketch".to_string(),
                                            EntryId::Launch(AppCmd::Sketch))];
+
+        if view.is::<Home>() {
+            apps.push(EntryKind::Command("Annotations".to_string(),
+                                         EntryId::Launch(AppCmd::Annotations)));
+        }
         
         let refresh_qualities = vec![EntryKind::RadioButton("Fast".to_string(),
                                            EntryId::RefreshQuality(RefreshQuality::Fast),
@@ -0,0 +1,352 @@
+mod bottom_bar;
+
+use std::thread;
+use std::sync::{Arc, Mutex, mpsc};
+use std::path::{Path, PathBuf};
+use fxhash::FxHashMap;
+use crate::device::CURRENT_DEVICE;
+use crate::framebuffer::{Framebuffer, UpdateMode, Pixmap};
+use crate::geom::{Rectangle, Point, Dir, CycleDir, halves};
+use crate::unit::scale_by_dpi;
+use crate::font::Fonts;
+use crate::view::{View, Event, Hub, Bus};
+use crate::view::{SMALL_BAR_HEIGHT, BIG_BAR_HEIGHT, THICKNESS_MEDIUM};
+use crate::document::{Document, Location, TextLocation, open};
+use crate::document::html::HtmlDocument;
+use crate::metadata::ReaderInfo;
+use crate::view::common::locate;
+use crate::gesture::GestureEvent;
+use crate::color::BLACK;
+use crate::app::Context;
+use crate::view::filler::Filler;
+use crate::view::image::Image;
+use crate::view::keyboard::Keyboard;
+use self::bottom_bar::BottomBar;
+
+// Shared by the lists, which can be styled further by their own user stylesheets.
+const VIEWER_STYLESHEET: &str = "css/html-list.css";
+
+// The location labels of the listed items, by document and location.
+pub type Labels = Arc<Mutex<FxHashMap<PathBuf, FxHashMap<TextLocation, String>>>>;
+
+// A paginated list of items, written in HTML, followed by a bottom bar.
+// The items are the links whose targets are `@` followed by their index.
+pub struct HtmlList {
+    rect: Rectangle,
+    children: Vec<Box<dyn View>>,
+    doc: HtmlDocument,
+    location: usize,
+}
+
+impl HtmlList {
+    pub fn new(rect: Rectangle, user_stylesheet: &str, context: &mut Context) -> HtmlList {
+        let mut children = Vec::new();
+        let dpi = CURRENT_DEVICE.dpi;
+        let small_height = scale_by_dpi(SMALL_BAR_HEIGHT, dpi) as i32;
+        let thickness = scale_by_dpi(THICKNESS_MEDIUM, dpi) as i32;
+        let (small_thickness, big_thickness) = halves(thickness);
+
+        let image_rect = rect![rect.min.x, rect.min.y,
+                               rect.max.x, rect.max.y - small_height - small_thickness];
+
+        let image = Image::new(image_rect, Pixmap::new(1, 1));
+        children.push(Box::new(image) as Box<dyn View>);
+
+        let mut doc = HtmlDocument::new_from_memory("");
+        doc.layout(image_rect.width(), image_rect.height(), context.settings.reader.font_size, dpi);
+        doc.set_margin_width(context.settings.reader.margin_width);
+        doc.set_viewer_stylesheet(VIEWER_STYLESHEET);
+        doc.set_user_stylesheet(user_stylesheet);
+
+        let separator = Filler::new(rect![rect.min.x, rect.max.y - small_height - small_thickness,
+                                          rect.max.x, rect.max.y - small_height + big_thickness],
+                                    BLACK);
+        children.push(Box::new(separator) as Box<dyn View>);
+
+        let bottom_bar = BottomBar::new(rect![rect.min.x, rect.max.y - small_height + big_thickness,
+                                              rect.max.x, rect.max.y],
+                                        "", false, false);
+        children.push(Box::new(bottom_bar) as Box<dyn View>);
+
+        HtmlList {
+            rect,
+            children,
+            doc,
+            location: 0,
+        }
+    }
+
+    // Replaces the content of the list, and the name shown in the bottom bar.
+    pub fn update(&mut self, content: &str, name: &str, reset: bool, hub: &Hub) {
+        self.doc.update(content);
+
+        if reset {
+            self.location = 0;
+        }
+
+        if let Some(image) = self.children[0].downcast_mut::<Image>() {
+            if let Some((pixmap, loc)) = self.doc.pixmap(Location::Exact(self.location), 1.0) {
+                image.update(pixmap, hub);
+                self.location = loc;
+            }
+        }
+
+        if let Some(bottom_bar) = self.children[2].downcast_mut::<BottomBar>() {
+            bottom_bar.update_name(name, hub);
+        }
+
+        self.update_icons(hub);
+    }
+
+    fn update_icons(&mut self, hub: &Hub) {
+        let has_prev = self.doc.resolve_location(Location::Previous(self.location)).is_some();
+        let has_next = self.doc.resolve_location(Location::Next(self.location)).is_some();
+        if let Some(bottom_bar) = self.children[2].downcast_mut::<BottomBar>() {
+            bottom_bar.update_icons(has_prev, has_next, hub);
+        }
+    }
+
+    fn go_to_neighbor(&mut self, dir: CycleDir, hub: &Hub) {
+        let location = match dir {
+            CycleDir::Previous => Location::Previous(self.location),
+            CycleDir::Next => Location::Next(self.location),
+        };
+        if let Some(image) = self.children[0].downcast_mut::<Image>() {
+            if let Some((pixmap, loc)) = self.doc.pixmap(location, 1.0) {
+                image.update(pixmap, hub);
+                self.location = loc;
+            }
+        }
+        self.update_icons(hub);
+    }
+
+    fn follow_link(&mut self, pt: Point, hub: &Hub, bus: &mut Bus) {
+        let offset = self.children[0].rect().min;
+
+        if let Some((links, _)) = self.doc.links(Location::Exact(self.location)) {
+            for link in links {
+                let rect = link.rect.to_rect() + offset;
+                if rect.includes(pt) && link.text.starts_with('@') {
+                    if let Ok(index) = link.text[1..].parse::<usize>() {
+                        let radius = scale_by_dpi(24.0, CURRENT_DEVICE.dpi) as i32;
+                        bus.push_back(Event::ToggleItemMenu(index, Rectangle::from_disk(pt, radius)));
+                    }
+                    return;
+                }
+            }
+        }
+
+        let half_width = self.rect.width() as i32 / 2;
+        if pt.x - offset.x < half_width {
+            self.go_to_neighbor(CycleDir::Previous, hub);
+        } else {
+            self.go_to_neighbor(CycleDir::Next, hub);
+        }
+    }
+}
+
+impl View for HtmlList {
+    fn handle_event(&mut self, evt: &Event, hub: &Hub, bus: &mut Bus, _context: &mut Context) -> bool {
+        match *evt {
+            Event::Page(dir) => {
+                self.go_to_neighbor(dir, hub);
+                true
+            },
+            Event::Gesture(GestureEvent::Swipe { dir, start, .. }) if self.rect.includes(start) => {
+                match dir {
+                    Dir::West => self.go_to_neighbor(CycleDir::Next, hub),
+                    Dir::East => self.go_to_neighbor(CycleDir::Previous, hub),
+                    _ => (),
+                }
+                true
+            },
+            Event::Gesture(GestureEvent::Tap(center)) if self.children[0].rect().includes(center) => {
+                self.follow_link(center, hub, bus);
+                true
+            },
+            _ => false,
+        }
+    }
+
+    fn render(&self, _fb: &mut dyn Framebuffer, _rect: Rectangle, _fonts: &mut Fonts) {
+    }
+
+    fn resize(&mut self, rect: Rectangle, hub: &Hub, context: &mut Context) {
+        let dpi = CURRENT_DEVICE.dpi;
+        let small_height = scale_by_dpi(SMALL_BAR_HEIGHT, dpi) as i32;
+        let thickness = scale_by_dpi(THICKNESS_MEDIUM, dpi) as i32;
+        let (small_thickness, big_thickness) = halves(thickness);
+
+        let image_rect = rect![rect.min.x, rect.min.y,
+                               rect.max.x, rect.max.y - small_height - small_thickness];
+        self.doc.layout(image_rect.width(), image_rect.height(), context.settings.reader.font_size, dpi);
+        if let Some(image) = self.children[0].downcast_mut::<Image>() {
+            if let Some((pixmap, loc)) = self.doc.pixmap(Location::Exact(self.location), 1.0) {
+                let (tx, _rx) = mpsc::channel();
+                image.update(pixmap, &tx);
+                self.location = loc;
+            }
+        }
+        self.children[0].resize(image_rect, hub, context);
+
+        self.children[1].resize(rect![rect.min.x, rect.max.y - small_height - small_thickness,
+                                      rect.max.x, rect.max.y - small_height + big_thickness],
+                                hub, context);
+
+        self.children[2].resize(rect![rect.min.x, rect.max.y - small_height + big_thickness,
+                                      rect.max.x, rect.max.y],
+                                hub, context);
+        self.update_icons(hub);
+
+        self.rect = rect;
+    }
+
+    fn rect(&self) -> &Rectangle {
+        &self.rect
+    }
+
+    fn rect_mut(&mut self) -> &mut Rectangle {
+        &mut self.rect
+    }
+
+    fn children(&self) -> &Vec<Box<dyn View>> {
+        &self.children
+    }
+
+    fn children_mut(&mut self) -> &mut Vec<Box<dyn View>> {
+        &mut self.children
+    }
+}
+
+// Shows or hides the keyboard, and its separator, right after the list of the given view.
+pub fn toggle_keyboard(view: &mut dyn View, enable: bool, hub: &Hub, context: &mut Context) {
+    if let Some(index) = locate::<Keyboard>(view) {
+        if enable {
+            return;
+        }
+
+        let mut rect = *view.child(index).rect();
+        rect.absorb(view.child(index-1).rect());
+        view.children_mut().drain(index - 1 ..= index);
+
+        hub.send(Event::Expose(rect, UpdateMode::Gui)).ok();
+        hub.send(Event::Focus(None)).ok();
+    } else {
+        if !enable {
+            return;
+        }
+
+        let dpi = CURRENT_DEVICE.dpi;
+        let (small_height, big_height) = (scale_by_dpi(SMALL_BAR_HEIGHT, dpi) as i32,
+                                          scale_by_dpi(BIG_BAR_HEIGHT, dpi) as i32);
+        let thickness = scale_by_dpi(THICKNESS_MEDIUM, dpi) as i32;
+        let (small_thickness, big_thickness) = halves(thickness);
+        let rect = *view.rect();
+
+        let mut kb_rect = rect![rect.min.x,
+                                rect.max.y - (small_height + 3 * big_height) as i32 + big_thickness,
+                                rect.max.x,
+                                rect.max.y - small_height - small_thickness];
+
+        let index = locate::<HtmlList>(view).unwrap() + 1;
+
+        let keyboard = Keyboard::new(&mut kb_rect, false, context);
+        view.children_mut().insert(index, Box::new(keyboard) as Box<dyn View>);
+
+        let separator = Filler::new(rect![rect.min.x, kb_rect.min.y - thickness,
+                                          rect.max.x, kb_rect.min.y],
+                                    BLACK);
+        view.children_mut().insert(index, Box::new(separator) as Box<dyn View>);
+
+        for i in index..=index+1 {
+            hub.send(Event::Render(*view.child(i).rect(), UpdateMode::Gui)).ok();
+        }
+    }
+}
+
+// Resizes the keyboard, and its separator, if they follow the list of the given view.
+// Returns the index of the first child that comes after them.
+pub fn resize_keyboard(view: &mut dyn View, rect: Rectangle, hub: &Hub, context: &mut Context) -> usize {
+    let index = locate::<HtmlList>(view).map_or(0, |index| index + 1);
+
+    if view.len() > index + 1 && view.child(index + 1).is::<Keyboard>() {
+        let dpi = CURRENT_DEVICE.dpi;
+        let (small_height, big_height) = (scale_by_dpi(SMALL_BAR_HEIGHT, dpi) as i32,
+                                          scale_by_dpi(BIG_BAR_HEIGHT, dpi) as i32);
+        let thickness = scale_by_dpi(THICKNESS_MEDIUM, dpi) as i32;
+        let (small_thickness, big_thickness) = halves(thickness);
+        let kb_rect = rect![rect.min.x,
+                            rect.max.y - (small_height + 3 * big_height) as i32 + big_thickness,
+                            rect.max.x,
+                            rect.max.y - small_height - small_thickness];
+        view.child_mut(index + 1).resize(kb_rect, hub, context);
+        let kb_rect = *view.child(index + 1).rect();
+        view.child_mut(index).resize(rect![rect.min.x, kb_rect.min.y - thickness,
+                                           rect.max.x, kb_rect.min.y],
+                                     hub, context);
+        index + 2
+    } else {
+        index
+    }
+}
+
+// Returns the chapter and the page of each of the given locations of the document.
+fn location_labels(path: &Path, reader: Option<&ReaderInfo>, locations: &[TextLocation]) -> Vec<(TextLocation, String)> {
+    let mut doc = open(path);
+    let toc = doc.as_mut().and_then(|doc| doc.toc());
+    let page_labels = doc.as_mut().and_then(|doc| doc.page_labels()).unwrap_or_default();
+    // The locations of reflowable documents are offsets, not pages.
+    let paginated = doc.as_ref().map_or(false, |doc| !doc.is_reflowable());
+
+    locations.iter().map(|&loc| {
+        let mut parts = Vec::new();
+        if let (Some(doc), Some(toc)) = (doc.as_mut(), toc.as_ref()) {
+            if let Some(chap) = doc.chapter(loc.location(), toc) {
+                parts.push(chap.title.clone());
+            }
+        }
+        if let TextLocation::Static(page, _) = loc {
+            if paginated {
+                let name = reader.and_then(|r| r.page_name(page))
+                                 .or_else(|| page_labels.get(&page).cloned())
+                                 .unwrap_or_else(|| (page + 1).to_string());
+                parts.push(format!("p. {}", name));
+            }
+        }
+        (loc, parts.join(" — "))
+    }).collect()
+}
+
+// Computes, in a background thread, the labels of the given locations that aren't already known,
+// since each document has to be opened. Sends `Event::LoadLabels` when they're ready.
+pub fn load_labels(labels: &Labels, jobs: Vec<(PathBuf, Option<ReaderInfo>, Vec<TextLocation>)>, home: &Path, hub: &Hub) {
+    let jobs = {
+        let known = labels.lock().unwrap();
+        jobs.into_iter().filter_map(|(path, reader, mut locations)| {
+            if let Some(k) = known.get(&path) {
+                locations.retain(|loc| !k.contains_key(loc));
+            }
+            if locations.is_empty() {
+                None
+            } else {
+                Some((path, reader, locations))
+            }
+        }).collect::<Vec<(PathBuf, Option<ReaderInfo>, Vec<TextLocation>)>>()
+    };
+
+    if jobs.is_empty() {
+        return;
+    }
+
+    let home = home.to_path_buf();
+    let labels = Arc::clone(labels);
+    let hub2 = hub.clone();
+
+    thread::spawn(move || {
+        for (path, reader, locations) in jobs {
+            let computed = location_labels(&home.join(&path), reader.as_ref(), &locations);
+            labels.lock().unwrap().entry(path).or_default().extend(computed);
+        }
+        hub2.send(Event::LoadLabels).ok();
+    });
+}
@@ -40,6 +40,9 @@ pub mod reader;
 pub mod dictionary;
 pub mod calculator;
 pub mod sketch;
+pub mod html_list;
+pub mod annotations;
+pub mod bookmarks;
 
 use std::time::Duration;
 use std::path::PathBuf;
@@ -51,7 +54,8 @@ use downcast_rs::{Downcast, impl_downcast};
 use crate::font::Fonts;
 use crate::document::{Location, TextLocation, TocEntry};
 use crate::settings::{ButtonScheme, FirstColumn, SecondColumn, RotationLock, RefreshQuality, InputSource};
-use crate::metadata::{Info, ZoomMode, SortMethod, TextAlign, SimpleStatus, PageScheme, Margin};
+use crate::export::ExportFormat;
+use crate::metadata::{Info, ZoomMode, SortMethod, TextAlign, SimpleStatus, PageScheme, Margin, CroppingMargins, DitherMode, HighlightStyle, Annotation, Bookmark};
 use crate::geom::{LinearDir, CycleDir, Rectangle, Boundary};
 use crate::framebuffer::{Framebuffer, UpdateMode};
 use crate::input::{DeviceEvent, FingerStatus};
@@ -240,9 +244,18 @@ pub enum Event {
     Keyboard(KeyboardEvent),
     Key(KeyKind),
     AddDocument(Box<Info>),
+    UpdateFingerprint(PathBuf, u64),
     Open(Box<Info>),
+    OpenAnnotations(Box<Info>),
+    UpdateAnnotations(Vec<Annotation>),
+    OpenBookmarks(Box<Info>),
+    UpdateBookmarks(Vec<Bookmark>),
+    ReanchorAnnotations(Vec<([TextLocation; 2], Option<[TextLocation; 2]>)>),
     OpenToc(Vec<TocEntry>, usize),
     LoadPixmap(usize),
+    Reflowed,
+    LoadLabels,
+    ToggleItemMenu(usize, Rectangle),
     Update(UpdateMode),
     Invalid(Box<Info>),
     Notify(String),
@@ -250,8 +263,10 @@ pub enum Event {
     ResultsPage(CycleDir),
     GoTo(usize),
     GoToLocation(Location),
+    GoToBookmark(usize),
     ResultsGoTo(usize),
     CropMargins(Box<Margin>),
+    AutoCroppedMargins(Box<CroppingMargins>),
     Chapter(CycleDir),
     Sort(SortMethod),
     SelectDirectory(PathBuf),
@@ -305,6 +320,7 @@ pub enum Event {
 pub enum AppCmd {
     Sketch,
     Calculator,
+    Annotations,
     Dictionary {
         query: String,
         language: String,
@@ -320,6 +336,7 @@ pub enum ViewId {
     TitleMenu,
     SelectionMenu,
     AnnotationMenu,
+    BookmarkMenu,
     BatteryMenu,
     ClockMenu,
     SearchTargetMenu,
@@ -350,11 +367,16 @@ pub enum ViewId {
     NamePageInput,
     EditNote,
     EditNoteInput,
+    RenameBookmark,
+    RenameBookmarkInput,
     EditLanguages,
     EditLanguagesInput,
+    Unlock,
+    UnlockInput,
     HomeSearchInput,
     ReaderSearchInput,
     DictionarySearchInput,
+    AnnotationsSearchInput,
     CalculatorInput,
     SearchBar,
     AddressBar,
@@ -462,17 +484,38 @@ pub enum EntryId {
     SecondColumn(SecondColumn),
     ApplyCroppings(usize, PageScheme),
     RemoveCroppings,
+    AutoCropMargins(PageScheme),
     SetZoomMode(ZoomMode),
+    SetDithering(Option<DitherMode>),
     SetPageName,
     RemovePageName,
-    HighlightSelection,
+    HighlightSelection(HighlightStyle),
     AnnotateSelection,
     DefineSelection,
     SearchForSelection,
     AdjustSelection,
     RemoveAnnotation([TextLocation; 2]),
+    RemoveOrphanAnnotations,
     EditAnnotationNote([TextLocation; 2]),
     RemoveAnnotationNote([TextLocation; 2]),
+    SetAnnotationStyle([TextLocation; 2], HighlightStyle),
+    SaveAnnotatedDocument,
+    ShowAnnotations,
+    ExportAnnotations(ExportFormat),
+    ShowBookmarks,
+    RenameBookmark(usize),
+    RemoveBookmark(usize),
+    ToggleReflow,
+    ToggleCoverAlone,
+    ToggleContinuous,
+    ToggleMarginNotes,
+    ToggleInk,
+    ToggleInkEraser,
+    ToggleInkVisibility,
+    UndoInk,
+    ToggleDeskew,
+    ToggleDespeckle,
+    ToggleAutoLevels,
     GoTo(usize),
     GoToSelectedPageName,
     SearchDirection(LinearDir),
@@ -500,6 +543,7 @@ pub enum EntryId {
     SetPenSize(i32),
     SetPenColor(u8),
     TogglePenDynamism,
+    TogglePenPressure,
     ReloadDictionaries,
     New,
     Refresh,
@@ -13,6 +13,7 @@ pub struct PageLabel {
     children: Vec<Box<dyn View>>,
     current_page: usize,
     pages_count: usize,
+    name: Option<String>,
     synthetic: bool,
 }
 
@@ -23,10 +24,20 @@ impl PageLabel {
             children: vec![],
             current_page,
             pages_count,
+            name: None,
             synthetic,
         }
     }
 
+    pub fn name(mut self, name: Option<String>) -> PageLabel {
+        self.name = name;
+        self
+    }
+
+    pub fn set_name(&mut self, name: Option<String>) {
+        self.name = name;
+    }
+
     pub fn update(&mut self, current_page: usize, pages_count: usize, hub: &Hub) {
         self.current_page = current_page;
         self.pages_count = pages_count;
@@ -45,6 +56,16 @@ impl PageLabel {
              self.pages_count as f64, 0)
         };
         let percent = 100.0 * self.current_page as f32 / self.pages_count as f32;
+        if let Some(name) = self.name.as_ref().filter(|_| !self.synthetic) {
+            let current_page = self.current_page + 1;
+            return match size {
+                0 => format!("Page {} ({} of {})", name, current_page, self.pages_count),
+                1 => format!("P. {} ({}/{})", name, current_page, self.pages_count),
+                2 => format!("{} ({}/{})", name, current_page, self.pages_count),
+                3 => format!("{} ({:.1}%)", name, percent),
+                _ => name.to_string(),
+            };
+        }
         match size {
             0 => format!("Page {1:.0$} of {2:.0$} ({3:.1}%)", precision, current_page, pages_count, percent),
             1 => format!("P. {1:.0$} of {2:.0$} ({3:.1}%)", precision, current_page, pages_count, percent),
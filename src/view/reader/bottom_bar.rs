@@ -21,7 +21,7 @@ pub struct BottomBar {
 }
 
 impl BottomBar {
-    pub fn new(rect: Rectangle, doc: &mut dyn Document, toc: Option<Vec<TocEntry>>, current_page: usize, pages_count: usize, neighbors: &Neighbors, synthetic: bool) -> BottomBar {
+    pub fn new(rect: Rectangle, doc: &mut dyn Document, toc: Option<Vec<TocEntry>>, current_page: usize, pages_count: usize, page_name: Option<String>, neighbors: &Neighbors, synthetic: bool) -> BottomBar {
         let mut children = Vec::new();
         let side = rect.height() as i32;
         let is_prev_disabled = neighbors.previous_page.is_none();
@@ -59,7 +59,8 @@ impl BottomBar {
                                               pt!(rect.max.x - side, rect.max.y)],
                                         current_page,
                                         pages_count,
-                                        synthetic);
+                                        synthetic)
+                                   .name(page_name);
         children.push(Box::new(page_label) as Box<dyn View>);
 
         let next_rect = rect![rect.max - side, rect.max];
@@ -82,8 +83,9 @@ impl BottomBar {
         }
     }
 
-    pub fn update_page_label(&mut self, current_page: usize, pages_count: usize, hub: &Hub) {
+    pub fn update_page_label(&mut self, current_page: usize, pages_count: usize, page_name: Option<String>, hub: &Hub) {
         let page_label = self.child_mut(2).downcast_mut::<PageLabel>().unwrap();
+        page_label.set_name(page_name);
         page_label.update(current_page, pages_count, hub);
     }
 
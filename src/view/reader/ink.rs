@@ -0,0 +1,139 @@
+use std::collections::BTreeMap;
+use crate::geom::{Vec2, nearest_segment_point};
+use crate::metadata::Stroke;
+
+pub enum InkAction {
+    Draw(usize),
+    // The erased strokes, with their keys and indices.
+    Erase(Vec<(usize, usize, Stroke)>),
+}
+
+// Returns whether the stroke passes within the given distance of the point.
+pub fn stroke_hit(stroke: &Stroke, p: Vec2, distance: f32) -> bool {
+    let points = &stroke.points;
+    let to_vec = |point: &[f32; 3]| Vec2::new(point[0], point[1]);
+
+    if let [point] = points.as_slice() {
+        return (to_vec(point) - p).length() <= distance + point[2];
+    }
+
+    points.windows(2).any(|pair| {
+        let (n, _) = nearest_segment_point(p, to_vec(&pair[0]), to_vec(&pair[1]));
+        (n - p).length() <= distance + pair[0][2].max(pair[1][2])
+    })
+}
+
+// Removes the strokes of the given key hit by the eraser and returns them.
+// Each index is the one the stroke had when it was removed, hence the erased
+// strokes must be re-inserted in reverse order.
+pub fn erase_ink(ink: &mut BTreeMap<usize, Vec<Stroke>>, key: usize, p: Vec2, distance: f32) -> Vec<(usize, usize, Stroke)> {
+    let mut erased = Vec::new();
+
+    if let Some(strokes) = ink.get_mut(&key) {
+        let mut index = 0;
+        while index < strokes.len() {
+            if stroke_hit(&strokes[index], p, distance) {
+                erased.push((key, index, strokes.remove(index)));
+            } else {
+                index += 1;
+            }
+        }
+        if strokes.is_empty() {
+            ink.remove(&key);
+        }
+    }
+
+    erased
+}
+
+pub fn undo_ink(ink: &mut BTreeMap<usize, Vec<Stroke>>, action: InkAction) {
+    match action {
+        InkAction::Draw(key) => {
+            if let Some(strokes) = ink.get_mut(&key) {
+                strokes.pop();
+                if strokes.is_empty() {
+                    ink.remove(&key);
+                }
+            }
+        },
+        InkAction::Erase(erased) => {
+            for (key, index, stroke) in erased.into_iter().rev() {
+                let strokes = ink.entry(key).or_insert_with(Vec::new);
+                strokes.insert(index.min(strokes.len()), stroke);
+            }
+        },
+    }
+}
+
+// Drops the last action if it's an erasure that didn't erase anything.
+pub fn drop_empty_erase(history: &mut Vec<InkAction>) {
+    if let Some(InkAction::Erase(erased)) = history.last() {
+        if erased.is_empty() {
+            history.pop();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stroke(points: &[(f32, f32)], color: u8) -> Stroke {
+        Stroke { points: points.iter().map(|&(x, y)| [x, y, 1.0]).collect(), color }
+    }
+
+    fn colors(ink: &BTreeMap<usize, Vec<Stroke>>, key: usize) -> Vec<u8> {
+        ink.get(&key).map(|strokes| strokes.iter().map(|s| s.color).collect()).unwrap_or_default()
+    }
+
+    #[test]
+    fn test_stroke_hit() {
+        let dot = stroke(&[(10.0, 10.0)], 0);
+        assert!(stroke_hit(&dot, Vec2::new(12.0, 10.0), 1.5));
+        assert!(!stroke_hit(&dot, Vec2::new(13.0, 10.0), 1.5));
+        let line = stroke(&[(0.0, 0.0), (20.0, 0.0)], 0);
+        assert!(stroke_hit(&line, Vec2::new(10.0, 2.0), 1.0));
+        assert!(!stroke_hit(&line, Vec2::new(10.0, 3.0), 1.0));
+        assert!(!stroke_hit(&line, Vec2::new(23.0, 0.0), 1.0));
+    }
+
+    #[test]
+    fn test_erase_and_undo() {
+        let mut ink = BTreeMap::new();
+        ink.insert(3, vec![stroke(&[(0.0, 0.0)], 1),
+                           stroke(&[(50.0, 50.0)], 2),
+                           stroke(&[(0.0, 1.0)], 3),
+                           stroke(&[(1.0, 0.0)], 4)]);
+        let mut erased = erase_ink(&mut ink, 3, Vec2::new(0.0, 0.0), 1.0);
+        assert_eq!(colors(&ink, 3), vec![2]);
+        assert_eq!(erased.iter().map(|e| e.1).collect::<Vec<usize>>(), vec![0, 1, 1]);
+        erased.extend(erase_ink(&mut ink, 3, Vec2::new(50.0, 50.0), 1.0));
+        assert!(ink.get(&3).is_none());
+        undo_ink(&mut ink, InkAction::Erase(erased));
+        assert_eq!(colors(&ink, 3), vec![1, 2, 3, 4]);
+        undo_ink(&mut ink, InkAction::Draw(3));
+        assert_eq!(colors(&ink, 3), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_undo_draw() {
+        let mut ink = BTreeMap::new();
+        ink.insert(5, vec![stroke(&[(0.0, 0.0)], 1)]);
+        undo_ink(&mut ink, InkAction::Draw(5));
+        assert!(ink.is_empty());
+        undo_ink(&mut ink, InkAction::Draw(5));
+        assert!(ink.is_empty());
+    }
+
+    #[test]
+    fn test_drop_empty_erase() {
+        let mut history = vec![InkAction::Draw(0), InkAction::Erase(Vec::new())];
+        drop_empty_erase(&mut history);
+        assert_eq!(history.len(), 1);
+        drop_empty_erase(&mut history);
+        assert_eq!(history.len(), 1);
+        history.push(InkAction::Erase(vec![(0, 0, stroke(&[(0.0, 0.0)], 0))]));
+        drop_empty_erase(&mut history);
+        assert_eq!(history.len(), 2);
+    }
+}
@@ -3,12 +3,15 @@ mod bottom_bar;
 mod results_bar;
 mod margin_cropper;
 mod results_label;
+mod ink;
 
+use std::fs;
+use std::mem;
 use std::thread;
 use std::sync::{Arc, Mutex, mpsc};
-use std::sync::atomic::AtomicBool;
+use std::sync::atomic::{AtomicBool, AtomicUsize};
 use std::sync::atomic::Ordering as AtomicOrdering;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::collections::{VecDeque, BTreeMap};
 use fxhash::{FxHashMap, FxHashSet};
 use chrono::Local;
@@ -16,20 +19,22 @@ use regex::Regex;
 use septem::prelude::*;
 use septem::{Roman, Digit};
 use rand_core::RngCore;
-use crate::input::{DeviceEvent, FingerStatus, ButtonCode, ButtonStatus};
-use crate::framebuffer::{Framebuffer, UpdateMode, Pixmap};
+use anyhow::{Error, format_err};
+use crate::input::{DeviceEvent, FingerStatus, PenStatus, PenTool, ButtonCode, ButtonStatus, PEN_SLOT};
+use crate::framebuffer::{dither, Framebuffer, UpdateMode, Pixmap};
 use crate::view::{View, Event, AppCmd, Hub, Bus, ViewId, EntryKind, EntryId, SliderId};
 use crate::view::{SMALL_BAR_HEIGHT, BIG_BAR_HEIGHT, THICKNESS_MEDIUM};
 use crate::unit::{scale_by_dpi, mm_to_px};
 use crate::device::CURRENT_DEVICE;
 use crate::helpers::AsciiExtension;
-use crate::font::Fonts;
+use crate::font::{Fonts, RenderPlan, font_from_style, MARGIN_NOTE};
 use crate::font::family_names;
 use self::margin_cropper::{MarginCropper, BUTTON_DIAMETER};
 use super::top_bar::TopBar;
 use self::tool_bar::ToolBar;
 use self::bottom_bar::BottomBar;
 use self::results_bar::ResultsBar;
+use self::ink::{InkAction, drop_empty_erase};
 use crate::view::common::{locate, rlocate, locate_by_id};
 use crate::view::common::{toggle_main_menu, toggle_battery_menu, toggle_clock_menu};
 use crate::view::filler::Filler;
@@ -38,38 +43,81 @@ use crate::view::search_bar::SearchBar;
 use crate::view::keyboard::Keyboard;
 use crate::view::menu::{Menu, MenuKind};
 use crate::view::notification::Notification;
+use crate::view::sketch::{TouchState, segment_radii, ERASER_RADIUS_MM};
 use crate::settings::{guess_frontlight, FinishedAction};
 use crate::settings::{DEFAULT_FONT_FAMILY, DEFAULT_TEXT_ALIGN, DEFAULT_LINE_HEIGHT, DEFAULT_MARGIN_WIDTH};
 use crate::frontlight::LightLevels;
 use crate::gesture::GestureEvent;
 use crate::document::{Document, open, Location, TextLocation, BoundedText, Neighbors, BYTES_PER_PAGE};
 use crate::document::{TocEntry, SimpleTocEntry, TocLocation, toc_as_html, chapter_from_index};
-use crate::document::pdf::PdfOpener;
-use crate::metadata::{Info, FileInfo, ReaderInfo, Annotation, TextAlign, ZoomMode, PageScheme};
-use crate::metadata::{Margin, CroppingMargins, make_query};
+use crate::document::pdf::{PdfOpener, PdfMarkup};
+use crate::document::reflow::ReflowDocument;
+use crate::document::columns::{columns_from_lines, columns_from_pixmap};
+use crate::document::autocrop::ink_margin;
+use crate::document::anchor::{WordsText, QUOTE_CONTEXT_LENGTH, selection_text, same_quote, locate_quote};
+use crate::document::enhance::{auto_levels, despeckle, skew_angle, deskew};
+use crate::metadata::{Info, FileInfo, ReaderInfo, Annotation, HighlightStyle, TextAlign, ZoomMode, PageScheme, DitherMode};
+use crate::metadata::{Margin, CroppingMargins, Stroke, make_query};
 use crate::metadata::{DEFAULT_CONTRAST_EXPONENT, DEFAULT_CONTRAST_GRAY};
-use crate::geom::{Point, Rectangle, Boundary, CornerSpec, BorderSpec, Dir, DiagDir, CycleDir, LinearDir, Axis, halves};
+use crate::export::{ExportFormat, export_annotations};
+use crate::geom::{Point, Vec2, Rectangle, Boundary, CornerSpec, BorderSpec, Dir, DiagDir, CycleDir, LinearDir, Axis};
+use crate::geom::halves;
 use crate::color::{BLACK, WHITE};
 use crate::app::Context;
 
 const HISTORY_SIZE: usize = 32;
 const RECT_DIST_JITTER: f32 = 24.0;
 const ANNOTATION_DRIFT: u8 =  0x44;
+const COLUMN_DETECTION_WIDTH: f32 = 600.0;
+const MAX_COLUMN_ZOOM: f32 = 3.0;
+const MIN_ZOOM_FACTOR: f32 = 0.25;
+const MAX_ZOOM_FACTOR: f32 = 3.0;
+// Number of steps per unit of the free zoom factor.
+const ZOOM_STEPS: f32 = 20.0;
+const AUTO_CROP_WIDTH: f32 = 400.0;
+// Maximum number of bytes of the rendered pages kept in memory.
+const MAX_CACHE_SIZE: usize = 32 * 1024 * 1024;
+const AUTO_CROP_SAMPLES: usize = 8;
+// Fraction of the screen height scrolled by the physical buttons in continuous mode.
+const BUTTON_SCROLL_RATIO: f32 = 0.75;
 const HIGHLIGHT_DRIFT: u8 =  0x22;
+const LIGHT_HIGHLIGHT_DRIFT: u8 =  0x11;
+const DARK_HIGHLIGHT_DRIFT: u8 =  0x33;
+// Quick and mostly horizontal pen selections are strike-throughs, which highlight the words.
+const STRIKE_MAX_DURATION: f64 = 0.6;
+const STRIKE_MAX_SLOPE: f32 = 0.25;
+// Fraction of the screen width reserved for the margin notes.
+const NOTES_MARGIN_RATIO: f32 = 0.25;
+// Number of lines shown of a collapsed margin note.
+const NOTE_MAX_LINES: usize = 3;
+// Number of characters of the text of a page stored with its bookmark.
+const BOOKMARK_EXCERPT_LENGTH: usize = 80;
 
 pub struct Reader {
     rect: Rectangle,
     children: Vec<Box<dyn View>>,
     doc: Arc<Mutex<Box<dyn Document>>>,
-    cache: BTreeMap<usize, Resource>,
+    // The fixed-layout document, while its reflowed version is being shown.
+    fixed_doc: Option<Arc<Mutex<Box<dyn Document>>>>,
+    // The reflowable version of the document, while it's being built.
+    reflow_job: Option<ReflowJob>,
+    // The rendered pages, keyed by location and quantized zoom factor.
+    cache: BTreeMap<(usize, u32), Resource>,
     text: FxHashMap<usize, Vec<BoundedText>>,
     annotations: FxHashMap<usize, Vec<Annotation>>,
+    skew_angles: Arc<Mutex<FxHashMap<usize, f32>>>,
+    // Pages rendered in the background, with the generation of their rendering parameters.
+    prefetched: Arc<Mutex<FxHashMap<usize, (usize, Resource)>>>,
+    render_generation: Arc<AtomicUsize>,
+    page_labels: BTreeMap<usize, String>,
     chunks: Vec<RenderChunk>,
     focus: Option<ViewId>,
     search: Option<Search>,
     search_direction: LinearDir,
     held_buttons: FxHashSet<ButtonCode>,
     selection: Option<Selection>,
+    // A pen touch near a word: the selection starts once the pen moves away.
+    pen_press: Option<PenPress>,
     target_annotation: Option<[TextLocation; 2]>,
     history: VecDeque<usize>,
     state: State,
@@ -78,17 +126,46 @@ pub struct Reader {
     pages_count: usize,
     view_port: ViewPort,
     contrast: Contrast,
+    gray_levels: u8,
+    // Vertical margin, in pixels, of the pages of reflowable documents.
+    page_margin: i32,
     synthetic: bool,
     page_turns: usize,
     reflowable: bool,
+    // Whether the pages are shown side by side, in landscape orientation.
+    two_pages: bool,
     ephemeral: bool,
+    locked: bool,
+    // The password was wrong: the prompt stays open when the input closes.
+    retry_unlock: bool,
+    // The password entered to unlock the document.
+    password: Option<String>,
     finished: bool,
+    ink: Ink,
+    // The annotations whose margin notes are shown in full.
+    expanded_notes: FxHashSet<[TextLocation; 2]>,
+}
+
+struct ReflowJob {
+    running: Arc<AtomicBool>,
+    result: Arc<Mutex<Option<ReflowDocument>>>,
+}
+
+// A note shown in the margin, next to its annotation.
+struct NoteBox {
+    selection: [TextLocation; 2],
+    rect: Rectangle,
+    lines: Vec<RenderPlan>,
 }
 
 #[derive(Debug)]
 struct ViewPort {
     zoom_mode: ZoomMode,
+    // Scale relative to fit-to-width, when the zoom mode is custom.
+    zoom_factor: f32,
     top_offset: i32,
+    left_offset: i32,
+    column_index: usize,
     margin_width: i32,
 }
 
@@ -96,7 +173,10 @@ impl Default for ViewPort {
     fn default() -> Self {
         ViewPort {
             zoom_mode: ZoomMode::FitToPage,
+            zoom_factor: 1.0,
             top_offset: 0,
+            left_offset: 0,
+            column_index: 0,
             margin_width: 0,
         }
     }
@@ -114,6 +194,51 @@ struct Selection {
     start: TextLocation,
     end: TextLocation,
     anchor: TextLocation,
+    // Where and when the pen started the selection.
+    pen: Option<(Point, f64)>,
+}
+
+#[derive(Debug, Copy, Clone)]
+struct PenPress {
+    anchor: TextLocation,
+    rect: Rectangle,
+    position: Point,
+    time: f64,
+}
+
+// The stroke being drawn with the pen.
+struct PenStroke {
+    key: usize,
+    // Position, on the screen, of the origin of the coordinates of the stroke.
+    origin: Vec2,
+    scale: f32,
+    clip: Rectangle,
+    stroke: Stroke,
+    touch: TouchState,
+}
+
+struct Ink {
+    // Whether the pen draws on the pages.
+    enabled: bool,
+    visible: bool,
+    eraser: bool,
+    // Where the pen last touched the pages: the gestures that start there are ignored.
+    pen_start: Option<Point>,
+    stroke: Option<PenStroke>,
+    history: Vec<InkAction>,
+}
+
+impl Default for Ink {
+    fn default() -> Self {
+        Ink {
+            enabled: false,
+            visible: true,
+            eraser: false,
+            pen_start: None,
+            stroke: None,
+            history: Vec::new(),
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -121,6 +246,24 @@ struct Resource {
     pixmap: Pixmap,
     frame: Rectangle,
     scale: f32,
+    // The text columns, in reading order, when the zoom mode is fit-to-column.
+    columns: Vec<Rectangle>,
+}
+
+// Everything needed to render a page, possibly in a background thread.
+#[derive(Debug, Clone)]
+struct RenderParams {
+    rect: Rectangle,
+    cropping_margins: Option<CroppingMargins>,
+    margin_width: i32,
+    zoom_mode: ZoomMode,
+    zoom_factor: f32,
+    auto_levels: bool,
+    despeckle: bool,
+    deskew: bool,
+    dithering: Option<DitherMode>,
+    gray_levels: u8,
+    skew_angles: Arc<Mutex<FxHashMap<usize, f32>>>,
 }
 
 #[derive(Debug, Clone)]
@@ -179,15 +322,101 @@ fn scaling_factor(rect: &Rectangle, cropping_margin: &Margin, screen_margin_widt
             let height_ratio = surface_height / frame_height;
             width_ratio.min(height_ratio)
         },
-        ZoomMode::FitToWidth => width_ratio,
+        ZoomMode::FitToWidth | ZoomMode::FitToColumn | ZoomMode::Custom => width_ratio,
+    }
+}
+
+fn page_columns(doc: &mut dyn Document, location: usize, dims: (f32, f32)) -> Vec<Boundary> {
+    let lines = doc.lines(Location::Exact(location))
+                   .map(|(lines, _)| lines)
+                   .unwrap_or_default();
+    if !lines.is_empty() {
+        let rects: Vec<Boundary> = lines.iter().map(|line| line.rect).collect();
+        columns_from_lines(&rects)
+    } else {
+        let scale = COLUMN_DETECTION_WIDTH / dims.0;
+        doc.pixmap(Location::Exact(location), scale)
+           .map(|(pixmap, _)| columns_from_pixmap(&pixmap).into_iter()
+                                                            .map(|column| column / scale)
+                                                            .collect())
+           .unwrap_or_default()
     }
 }
 
+fn render_resource(doc: &mut dyn Document, location: usize, params: &RenderParams) -> Option<Resource> {
+    let cropping_margin = params.cropping_margins.as_ref()
+                                .map(|c| c.margin(location))
+                                .cloned().unwrap_or_default();
+    let dims = doc.dims(location)?;
+    let screen_margin_width = params.margin_width;
+    let mut scale = scaling_factor(&params.rect, &cropping_margin, screen_margin_width, dims, params.zoom_mode);
+    let mut columns = Vec::new();
+
+    if params.zoom_mode == ZoomMode::Custom {
+        scale *= params.zoom_factor;
+    }
+
+    if params.zoom_mode == ZoomMode::FitToColumn {
+        let crop_box = bndr!(cropping_margin.left * dims.0, cropping_margin.top * dims.1,
+                             (1.0 - cropping_margin.right) * dims.0, (1.0 - cropping_margin.bottom) * dims.1);
+        columns = page_columns(doc, location, dims).into_iter()
+                              .filter(|column| column.overlaps(&crop_box))
+                              .collect();
+        let column_width = columns.iter().map(Boundary::width).fold(0.0, f32::max);
+        if column_width > 0.0 {
+            let surface_width = (params.rect.width() as i32 - 2 * screen_margin_width) as f32;
+            scale = (surface_width / column_width).min(MAX_COLUMN_ZOOM * scale);
+        }
+    }
+
+    let (mut pixmap, _) = doc.pixmap(Location::Exact(location), scale)?;
+
+    if params.auto_levels {
+        auto_levels(&mut pixmap);
+    }
+
+    if params.despeckle {
+        despeckle(&mut pixmap);
+    }
+
+    if params.deskew {
+        let angle = *params.skew_angles.lock().unwrap().entry(location)
+                           .or_insert_with(|| skew_angle(&pixmap));
+        deskew(&mut pixmap, angle);
+    }
+
+    if let Some(dithering) = params.dithering {
+        dither(&mut pixmap, dithering, params.gray_levels);
+    }
+
+    let frame = rect![(cropping_margin.left * pixmap.width as f32).ceil() as i32,
+                      (cropping_margin.top * pixmap.height as f32).ceil() as i32,
+                      ((1.0 - cropping_margin.right) * pixmap.width as f32).floor() as i32,
+                      ((1.0 - cropping_margin.bottom) * pixmap.height as f32).floor() as i32];
+    let columns = columns.into_iter()
+                         .filter_map(|column| (column * scale).to_rect().intersection(&frame))
+                         .collect();
+
+    Some(Resource { pixmap, frame, scale, columns })
+}
+
 fn build_pixmap(rect: &Rectangle, doc: &mut dyn Document, location: usize) -> (Pixmap, usize) {
     let scale = scaling_factor(rect, &Margin::default(), 0, doc.dims(location).unwrap(), ZoomMode::FitToPage);
     doc.pixmap(Location::Exact(location), scale).unwrap()
 }
 
+// Returns the location of the page that contains the given offset of a reflowable
+// document, and the vertical position, within this page, of the word at this offset.
+fn locate_offset(doc: &mut dyn Document, offset: usize) -> Option<(usize, f32)> {
+    let location = doc.resolve_location(Location::Exact(offset))?;
+    let (words, _) = doc.words(Location::Exact(location))?;
+    let y_pos = words.iter().find(|w| match w.location {
+                                   TextLocation::Dynamic(o) => o >= offset,
+                                   _ => false,
+                               }).map_or(0.0, |w| w.rect.min.y);
+    Some((location, y_pos))
+}
+
 fn find_cut(frame: &Rectangle, y_pos: i32, scale: f32, dir: LinearDir, lines: &[BoundedText]) -> Option<i32> {
     let y_pos_u = y_pos as f32 / scale;
     let frame_u = frame.to_boundary() / scale;
@@ -211,132 +440,465 @@ fn find_cut(frame: &Rectangle, y_pos: i32, scale: f32, dir: LinearDir, lines: &[
     })
 }
 
-impl Reader {
-    pub fn new(rect: Rectangle, mut info: Info, hub: &Hub, context: &mut Context) -> Option<Reader> {
-        let settings = &context.settings;
-        let path = context.library.home.join(&info.file.path);
-
-        open(&path).and_then(|mut doc| {
-            let (width, height) = context.display.dims;
-            let font_size = info.reader.as_ref().and_then(|r| r.font_size)
-                                .unwrap_or(settings.reader.font_size);
-            let first_location = doc.resolve_location(Location::Exact(0))?;
+fn stroke_point(point: &[f32; 3], origin: Vec2, scale: f32) -> (Point, f32) {
+    (Point::from(origin + Vec2::new(point[0], point[1]) * scale), point[2] * scale)
+}
 
-            doc.layout(width, height, font_size, CURRENT_DEVICE.dpi);
+fn draw_stroke(fb: &mut dyn Framebuffer, stroke: &Stroke, origin: Vec2, scale: f32, clip: &Rectangle) {
+    if let [point] = stroke.points.as_slice() {
+        let (center, radius) = stroke_point(point, origin, scale);
+        fb.draw_clipped_segment(center, center, radius, radius, stroke.color, clip);
+    }
 
-            let margin_width = info.reader.as_ref().and_then(|r| r.margin_width)
-                                   .unwrap_or(settings.reader.margin_width);
+    for pair in stroke.points.windows(2) {
+        let (start, start_radius) = stroke_point(&pair[0], origin, scale);
+        let (end, end_radius) = stroke_point(&pair[1], origin, scale);
+        fb.draw_clipped_segment(start, end, start_radius, end_radius, stroke.color, clip);
+    }
+}
 
-            if margin_width != DEFAULT_MARGIN_WIDTH {
-                doc.set_margin_width(margin_width);
-            }
+// Returns the rectangle, on the screen, covered by the stroke.
+fn stroke_rect(stroke: &Stroke, origin: Vec2, scale: f32) -> Option<Rectangle> {
+    stroke.points.iter().map(|point| {
+        let (center, radius) = stroke_point(point, origin, scale);
+        Rectangle::from_disk(center, radius.ceil() as i32 + 1)
+    }).fold(None, |rect: Option<Rectangle>, r| {
+        Some(rect.map_or(r, |mut rect| { rect.absorb(&r); rect }))
+    })
+}
 
-            let font_family = info.reader.as_ref().and_then(|r| r.font_family.as_ref())
-                                  .unwrap_or(&settings.reader.font_family);
+// Returns how much the pixels of a highlight of the given style are darkened.
+fn highlight_drift(style: HighlightStyle) -> u8 {
+    match style {
+        HighlightStyle::Light => LIGHT_HIGHLIGHT_DRIFT,
+        HighlightStyle::Medium => HIGHLIGHT_DRIFT,
+        HighlightStyle::Dark => DARK_HIGHLIGHT_DRIFT,
+        _ => 0,
+    }
+}
 
-            if font_family != DEFAULT_FONT_FAMILY {
-                doc.set_font_family(font_family, &settings.reader.font_path);
+// Draws the lines of the style on the given line of annotated text.
+fn draw_decoration(fb: &mut dyn Framebuffer, line: &Rectangle, style: HighlightStyle, thickness: i32, clip: &Rectangle) {
+    let rect = match style {
+        HighlightStyle::Underline => rect![line.min.x, line.max.y - thickness, line.max.x, line.max.y],
+        HighlightStyle::StrikeThrough => {
+            let y = (line.min.y + line.max.y - thickness) / 2;
+            rect![line.min.x, y, line.max.x, y + thickness]
+        },
+        HighlightStyle::Boxed => {
+            let sides = [rect![line.min.x, line.min.y, line.max.x, line.min.y + thickness],
+                         rect![line.min.x, line.max.y - thickness, line.max.x, line.max.y],
+                         rect![line.min.x, line.min.y, line.min.x + thickness, line.max.y],
+                         rect![line.max.x - thickness, line.min.y, line.max.x, line.max.y]];
+            for side in &sides {
+                if let Some(ref r) = side.intersection(clip) {
+                    fb.draw_rectangle(r, BLACK);
+                }
             }
+            return;
+        },
+        _ => return,
+    };
+    if let Some(ref r) = rect.intersection(clip) {
+        fb.draw_rectangle(r, BLACK);
+    }
+}
 
-            let line_height = info.reader.as_ref().and_then(|r| r.line_height)
-                                  .unwrap_or(settings.reader.line_height);
+// Returns the PDF annotation that renders the style.
+fn pdf_markup(style: HighlightStyle) -> PdfMarkup {
+    match style {
+        HighlightStyle::Light => PdfMarkup::Highlight(0.85),
+        HighlightStyle::Medium => PdfMarkup::Highlight(0.7),
+        HighlightStyle::Dark => PdfMarkup::Highlight(0.5),
+        HighlightStyle::Underline => PdfMarkup::Underline,
+        HighlightStyle::StrikeThrough => PdfMarkup::StrikeOut,
+        HighlightStyle::Boxed => PdfMarkup::Square,
+    }
+}
 
-            if (line_height - DEFAULT_LINE_HEIGHT).abs() > f32::EPSILON {
-                doc.set_line_height(line_height);
-            }
+// Writes the annotations, with their styles, into a copy of the given PDF file, or into the file
+// itself when both paths are the same. The annotations written by a previous call are replaced.
+// Returns the margins that are blank on a sample of pages. The document is only locked
+// while a page is rendered, so that it stays usable meanwhile.
+fn detect_margins(doc: &Mutex<Box<dyn Document>>, scheme: PageScheme) -> Option<CroppingMargins> {
+    let mut margins: [Option<Margin>; 2] = [None, None];
+    let pages_count = doc.lock().unwrap().pages_count();
+    // Skip the covers, their layout is rarely the one of the other pages.
+    let (first, last) = if pages_count > 4 { (1, pages_count - 1) } else { (0, pages_count) };
+    let samples = AUTO_CROP_SAMPLES.min((last - first + 1) / 2).max(1);
+    let mut indices = (0..samples).flat_map(|i| {
+        let index = first + i * (last - first) / samples;
+        vec![index, index + 1]
+    }).filter(|&index| index < last).collect::<Vec<usize>>();
+    indices.dedup();
+
+    for index in indices {
+        let mut doc = doc.lock().unwrap();
+        let dims = match doc.dims(index) {
+            Some(dims) if dims.0 > 0.0 => dims,
+            _ => continue,
+        };
+        let margin = doc.pixmap(Location::Exact(index), AUTO_CROP_WIDTH / dims.0)
+                        .and_then(|(pixmap, _)| ink_margin(&pixmap));
+        if let Some(margin) = margin {
+            let slot = if scheme == PageScheme::EvenOdd { index % 2 } else { 0 };
+            margins[slot] = Some(match margins[slot].take() {
+                Some(m) => Margin::new(m.top.min(margin.top), m.right.min(margin.right),
+                                       m.bottom.min(margin.bottom), m.left.min(margin.left)),
+                None => margin,
+            });
+        }
+    }
 
-            let text_align = info.reader.as_ref().and_then(|r| r.text_align)
-                                 .unwrap_or(settings.reader.text_align);
+    match (scheme, margins) {
+        (_, [None, None]) => None,
+        (PageScheme::Any, [Some(m), _]) => Some(CroppingMargins::Any(m)),
+        (_, [a, b]) => {
+            let a = a.or_else(|| b.clone()).unwrap();
+            let b = b.unwrap_or_else(|| a.clone());
+            Some(CroppingMargins::EvenOdd([a, b]))
+        },
+    }
+}
 
-            if text_align != DEFAULT_TEXT_ALIGN {
-                doc.set_text_align(text_align);
-            }
+fn write_annotations(path: &Path, target: &Path, annotations: &[Annotation], password: Option<&str>) -> Result<(), Error> {
+    let mut doc = PdfOpener::new().and_then(|o| o.open(path))
+                            .ok_or_else(|| format_err!("Can't open {}.", path.display()))?;
 
-            let mut view_port = ViewPort::default();
-            let mut contrast = Contrast::default();
-            let pages_count = doc.pages_count();
-            let current_page;
+    if doc.is_protected() && !password.map_or(false, |password| doc.authenticate(password)) {
+        return Err(format_err!("Can't unlock {}.", path.display()));
+    }
 
-            // TODO: use get_or_insert_with?
-            if let Some(ref mut r) = info.reader {
-                r.opened = Local::now();
+    for index in 0..doc.pages_count() {
+        doc.remove_markups(index)?;
+    }
 
-                if r.finished {
-                    r.finished = false;
-                    r.current_page = first_location;
-                    r.top_offset = None;
-                }
+    for (i, annot) in annotations.iter().enumerate() {
+        let (first_page, last_page) = match annot.selection {
+            [TextLocation::Static(first_page, _), TextLocation::Static(last_page, _)] => (first_page, last_page),
+            _ => continue,
+        };
+        let [start, end] = annot.selection;
 
-                current_page = doc.resolve_location(Location::Exact(r.current_page))
-                                  .unwrap_or(first_location);
+        for page in first_page..=last_page {
+            let words = match doc.words(Location::Exact(page)) {
+                Some((words, _)) => words,
+                None => continue,
+            };
 
-                if let Some(zoom_mode) = r.zoom_mode {
-                    view_port.zoom_mode = zoom_mode;
+            // Merge the rectangles of the consecutive words of each line.
+            let mut rects: Vec<Boundary> = Vec::new();
+            for word in words.iter().filter(|w| w.location >= start && w.location <= end) {
+                if let Some(last) = rects.last_mut() {
+                    let overlap = last.max.y.min(word.rect.max.y) - last.min.y.max(word.rect.min.y);
+                    if overlap > 0.5 * last.height().min(word.rect.height()) && word.rect.min.x >= last.min.x {
+                        last.absorb(&word.rect);
+                        continue;
+                    }
                 }
+                rects.push(word.rect);
+            }
 
-                if let Some(top_offset) = r.top_offset {
-                    view_port.top_offset = top_offset;
-                }
+            if !rects.is_empty() {
+                let note = if page == first_page { annot.note.as_str() } else { "" };
+                doc.add_markup(page, pdf_markup(annot.style), &rects, note, &format!("{}-{}", i, page))?;
+            }
+        }
+    }
 
-                if !doc.is_reflowable() {
-                    view_port.margin_width = mm_to_px(r.screen_margin_width.unwrap_or(0) as f32,
-                                                      CURRENT_DEVICE.dpi) as i32;
-                }
+    let in_place = path == target;
 
-                if let Some(exponent) = r.contrast_exponent {
-                    contrast.exponent = exponent;
-                }
+    if let Some(parent) = target.parent().filter(|_| !in_place) {
+        fs::create_dir_all(parent)?;
+    }
 
-                if let Some(gray) = r.contrast_gray {
-                    contrast.gray = gray;
-                }
-            } else {
-                current_page = first_location;
+    doc.save(target, in_place)
+}
 
-                info.reader = Some(ReaderInfo {
-                    current_page,
-                    pages_count,
-                    .. Default::default()
-                });
-            }
+// Width of the margin reserved for the notes.
+fn notes_margin_width(width: u32) -> u32 {
+    (NOTES_MARGIN_RATIO * width as f32) as u32
+}
 
-            let synthetic = doc.has_synthetic_page_numbers();
-            let reflowable = doc.is_reflowable();
+// Width available to the text of reflowable documents.
+fn text_width(width: u32, margin_notes: bool) -> u32 {
+    if margin_notes {
+        width - notes_margin_width(width)
+    } else {
+        width
+    }
+}
 
-            println!("{}", info.file.path.display());
+impl Reader {
+    pub fn new(rect: Rectangle, info: Info, hub: &Hub, context: &mut Context) -> Option<Reader> {
+        let path = context.library.home.join(&info.file.path);
 
-            hub.send(Event::Update(UpdateMode::Partial)).ok();
+        open(&path).and_then(|mut doc| {
+            let locked = doc.is_protected() &&
+                         !info.reader.as_ref().and_then(|r| r.password.as_ref())
+                              .map_or(false, |password| doc.authenticate(password));
 
-            Some(Reader {
+            let mut reader = Reader {
                 rect,
                 children: Vec::new(),
                 doc: Arc::new(Mutex::new(doc)),
+                fixed_doc: None,
+                reflow_job: None,
                 cache: BTreeMap::new(),
                 text: FxHashMap::default(),
                 annotations: FxHashMap::default(),
+                skew_angles: Arc::new(Mutex::new(FxHashMap::default())),
+                prefetched: Arc::new(Mutex::new(FxHashMap::default())),
+                render_generation: Arc::new(AtomicUsize::new(0)),
+                page_labels: BTreeMap::new(),
                 chunks: Vec::new(),
                 focus: None,
                 search: None,
                 search_direction: LinearDir::Forward,
                 held_buttons: FxHashSet::default(),
                 selection: None,
+                pen_press: None,
                 target_annotation: None,
                 history: VecDeque::new(),
                 state: State::Idle,
                 info,
-                current_page,
-                pages_count,
-                view_port,
-                synthetic,
+                current_page: 0,
+                pages_count: 0,
+                view_port: ViewPort::default(),
+                synthetic: false,
                 page_turns: 0,
-                contrast,
+                contrast: Contrast::default(),
+                gray_levels: 16,
+                page_margin: 0,
                 ephemeral: false,
-                reflowable,
+                reflowable: false,
+                two_pages: false,
+                locked,
+                retry_unlock: false,
+                password: None,
                 finished: false,
-            })
+                ink: Ink::default(),
+                expanded_notes: FxHashSet::default(),
+            };
+
+            println!("{}", reader.info.file.path.display());
+
+            if locked {
+                reader.toggle_unlock(Some(true), hub, context);
+                Some(reader)
+            } else {
+                reader.load(hub, context).map(|_| reader)
+            }
         })
     }
 
+    fn load(&mut self, hub: &Hub, context: &mut Context) -> Option<()> {
+        if self.fixed_doc.is_none() && self.info.reader.as_ref().and_then(|r| r.reflow) == Some(true) {
+            self.start_reflow(hub, context);
+        }
+
+        let reflow = self.fixed_doc.is_some();
+        let (width, height) = context.display.dims;
+        self.two_pages = context.settings.reader.two_pages && width > height;
+        let layout_width = if self.two_pages { width / 2 } else { text_width(width, self.margin_notes()) };
+        self.gray_levels = if context.fb.monochrome() { 2 } else { 16 };
+        let gray_levels = self.gray_levels;
+        let settings = &context.settings;
+        let info = &mut self.info;
+        let mut doc = self.doc.lock().unwrap();
+        let font_size = info.reader.as_ref().and_then(|r| r.font_size)
+                            .unwrap_or(settings.reader.font_size);
+        let first_location = doc.resolve_location(Location::Exact(0))?;
+
+        doc.layout(layout_width, height, font_size, CURRENT_DEVICE.dpi);
+
+        let margin_width = info.reader.as_ref().and_then(|r| r.margin_width)
+                               .unwrap_or(settings.reader.margin_width);
+
+        if margin_width != DEFAULT_MARGIN_WIDTH {
+            doc.set_margin_width(margin_width);
+        }
+
+        let page_margin = mm_to_px(margin_width as f32, CURRENT_DEVICE.dpi).round() as i32;
+
+        let font_family = info.reader.as_ref().and_then(|r| r.font_family.as_ref())
+                              .unwrap_or(&settings.reader.font_family);
+
+        if font_family != DEFAULT_FONT_FAMILY {
+            doc.set_font_family(font_family, &settings.reader.font_path);
+        }
+
+        let line_height = info.reader.as_ref().and_then(|r| r.line_height)
+                              .unwrap_or(settings.reader.line_height);
+
+        if (line_height - DEFAULT_LINE_HEIGHT).abs() > f32::EPSILON {
+            doc.set_line_height(line_height);
+        }
+
+        let text_align = info.reader.as_ref().and_then(|r| r.text_align)
+                             .unwrap_or(settings.reader.text_align);
+
+        if text_align != DEFAULT_TEXT_ALIGN {
+            doc.set_text_align(text_align);
+        }
+
+        if let Some(dithering) = info.reader.as_ref().and_then(|r| r.dithering) {
+            doc.set_dithering(Some((dithering, gray_levels)));
+        }
+
+        if doc.is_reflowable() && !reflow {
+            if let Some(path) = context.library.pagination_path(&info.file.path) {
+                doc.load_pagination(&path);
+            }
+        }
+
+        let mut view_port = ViewPort::default();
+        let mut contrast = Contrast::default();
+        let pages_count = doc.pages_count();
+        let current_page;
+
+        // TODO: use get_or_insert_with?
+        if let Some(ref mut r) = info.reader {
+            r.opened = Local::now();
+
+            if r.finished {
+                r.finished = false;
+                r.current_page = if reflow { 0 } else { first_location };
+                r.top_offset = None;
+            }
+
+            // In reflow mode, the current page is the one of the fixed-layout document.
+            current_page = if reflow {
+                doc.resolve_location(Location::Uri(format!("#page-{}", r.current_page)))
+                   .and_then(|offset| doc.resolve_location(Location::Exact(offset)))
+            } else {
+                doc.resolve_location(Location::Exact(r.current_page))
+            }.unwrap_or(first_location);
+
+            if !doc.is_reflowable() {
+                if let Some(zoom_mode) = r.zoom_mode {
+                    view_port.zoom_mode = zoom_mode;
+                }
+
+                if let Some(top_offset) = r.top_offset {
+                    view_port.top_offset = top_offset;
+                }
+
+                if let Some(left_offset) = r.left_offset {
+                    view_port.left_offset = left_offset;
+                }
+
+                if let Some(zoom_factor) = r.zoom_factor {
+                    view_port.zoom_factor = zoom_factor;
+                }
+
+                view_port.margin_width = mm_to_px(r.screen_margin_width.unwrap_or(0) as f32,
+                                                  CURRENT_DEVICE.dpi) as i32;
+            } else if r.continuous == Some(true) {
+                view_port.zoom_mode = ZoomMode::FitToWidth;
+
+                // In continuous mode, the current page is the offset of the first visible word.
+                if !reflow {
+                    if let Some((_, y)) = locate_offset(doc.as_mut(), r.current_page) {
+                        view_port.top_offset = (y as i32 - page_margin).max(0);
+                    }
+                }
+            }
+
+            if let Some(exponent) = r.contrast_exponent {
+                contrast.exponent = exponent;
+            }
+
+            if let Some(gray) = r.contrast_gray {
+                contrast.gray = gray;
+            }
+        } else {
+            current_page = first_location;
+
+            info.reader = Some(ReaderInfo {
+                current_page,
+                pages_count,
+                .. Default::default()
+            });
+        }
+
+        self.synthetic = doc.has_synthetic_page_numbers();
+        self.reflowable = doc.is_reflowable();
+        self.page_labels = doc.page_labels().unwrap_or_default();
+        self.current_page = current_page;
+        self.pages_count = pages_count;
+        self.view_port = view_port;
+        self.contrast = contrast;
+        self.page_margin = page_margin;
+
+        hub.send(Event::Update(UpdateMode::Partial)).ok();
+
+        drop(doc);
+        self.check_annotations(hub);
+
+        Some(())
+    }
+
+    // Verifies, in the background, that the annotations still match the text of the document,
+    // and searches for the ones that don't. The ones that can't be found are flagged as orphans,
+    // and are only searched for again when the document is replaced.
+    fn check_annotations(&self, hub: &Hub) {
+        if self.ephemeral || self.fixed_doc.is_some() {
+            return;
+        }
+
+        let (annotations, reanchor) = match self.info.reader.as_ref() {
+            Some(r) if !r.annotations.is_empty() => (r.annotations.clone(), r.reanchor),
+            _ => return,
+        };
+
+        let hub2 = hub.clone();
+        let doc2 = Arc::clone(&self.doc);
+
+        thread::spawn(move || {
+            let lost = annotations.into_iter().filter(|annot| {
+                !annot.text.is_empty() && (reanchor || (!annot.orphan && {
+                    let mut doc = doc2.lock().unwrap();
+                    selection_text(doc.as_mut(), annot.selection)
+                        .map_or(true, |text| !same_quote(&text, &annot.text))
+                }))
+            }).collect::<Vec<Annotation>>();
+
+            if lost.is_empty() {
+                if reanchor {
+                    hub2.send(Event::ReanchorAnnotations(Vec::new())).ok();
+                }
+                return;
+            }
+
+            let mut words_text = WordsText::default();
+            let mut loc = Location::Exact(0);
+
+            loop {
+                let mut doc = doc2.lock().unwrap();
+                if let Some(location) = doc.resolve_location(loc) {
+                    if let Some((words, _)) = doc.words(Location::Exact(location)) {
+                        for word in words {
+                            words_text.push(&word.text, word.location);
+                        }
+                    }
+                    loc = Location::Next(location);
+                } else {
+                    break;
+                }
+            }
+
+            let results = lost.into_iter().map(|annot| {
+                let hint = words_text.position(annot.selection[0]);
+                let selection = locate_quote(&words_text.text, &annot.text, &annot.prefix, &annot.suffix, hint)
+                                    .and_then(|range| words_text.selection(range));
+                (annot.selection, selection)
+            }).collect();
+
+            hub2.send(Event::ReanchorAnnotations(results)).ok();
+        });
+    }
+
     pub fn from_toc(rect: Rectangle, toc: &[TocEntry], chap_index: usize, hub: &Hub, context: &mut Context) -> Reader {
         let html = toc_as_html(toc, chap_index);
 
@@ -382,15 +944,22 @@ impl Reader {
             rect,
             children: vec![],
             doc: Arc::new(Mutex::new(Box::new(doc))),
+            fixed_doc: None,
+            reflow_job: None,
             cache: BTreeMap::new(),
             text: FxHashMap::default(),
             annotations: FxHashMap::default(),
+            skew_angles: Arc::new(Mutex::new(FxHashMap::default())),
+            prefetched: Arc::new(Mutex::new(FxHashMap::default())),
+            render_generation: Arc::new(AtomicUsize::new(0)),
+            page_labels: BTreeMap::new(),
             chunks: Vec::new(),
             focus: None,
             search: None,
             search_direction: LinearDir::Forward,
             held_buttons: FxHashSet::default(),
             selection: None,
+            pen_press: None,
             target_annotation: None,
             history: VecDeque::new(),
             state: State::Idle,
@@ -401,34 +970,231 @@ impl Reader {
             synthetic: false,
             page_turns: 0,
             contrast: Contrast::default(),
+            gray_levels: 16,
+            page_margin: 0,
             ephemeral: true,
             reflowable: true,
+            two_pages: false,
+            locked: false,
+            retry_unlock: false,
+            password: None,
             finished: false,
+            ink: Ink::default(),
+            expanded_notes: FxHashSet::default(),
+        }
+    }
+
+    fn margin_notes(&self) -> bool {
+        !self.two_pages && self.info.reader.as_ref().and_then(|r| r.margin_notes) == Some(true)
+    }
+
+    fn notes_width(&self) -> u32 {
+        if self.reflowable && self.margin_notes() {
+            notes_margin_width(self.rect.width())
+        } else {
+            0
+        }
+    }
+
+    fn toggle_margin_notes(&mut self, hub: &Hub, context: &mut Context) {
+        let margin_notes = !self.margin_notes();
+        if let Some(ref mut r) = self.info.reader {
+            r.margin_notes = if margin_notes { Some(true) } else { None };
+        }
+        let rect = self.rect;
+        self.resize(rect, hub, context);
+    }
+
+    // Lays out the notes of the visible annotations in the margin. Each note is placed next to the
+    // first line of its annotation, or below the previous note. Collapsed notes are truncated.
+    fn note_boxes(&self, fonts: &mut Fonts) -> Vec<NoteBox> {
+        let notes_width = self.notes_width() as i32;
+        if notes_width == 0 {
+            return Vec::new();
+        }
+
+        let mut anchors: Vec<(i32, &Annotation)> = Vec::new();
+
+        for chunk in &self.chunks {
+            let chunk_rect = chunk.frame - chunk.frame.min + chunk.position;
+            if let (Some(annotations), Some(words)) = (self.annotations.get(&chunk.location), self.text.get(&chunk.location)) {
+                for annot in annotations.iter().filter(|annot| !annot.note.is_empty()) {
+                    if anchors.iter().any(|(_, a)| a.selection == annot.selection) {
+                        continue;
+                    }
+                    let [start, end] = annot.selection;
+                    if let Some(word) = words.iter().find(|w| w.location >= start && w.location <= end) {
+                        let rect = (word.rect * chunk.scale).to_rect() - chunk.frame.min + chunk.position;
+                        if chunk_rect.includes(rect.min) {
+                            anchors.push((rect.min.y, annot));
+                        }
+                    }
+                }
+            }
+        }
+
+        anchors.sort_by_key(|(y, _)| *y);
+
+        let font = font_from_style(fonts, &MARGIN_NOTE, CURRENT_DEVICE.dpi);
+        let padding = font.em() as i32 / 2;
+        let line_height = font.line_height();
+        let max_width = notes_width - 2 * padding;
+        let mut boxes = Vec::new();
+        let mut min_y = self.rect.min.y + padding;
+
+        for (y, annot) in anchors {
+            let y = y.max(min_y);
+            let max_lines = ((self.rect.max.y - y) / line_height).max(0) as usize;
+            let max_lines = if self.expanded_notes.contains(&annot.selection) {
+                max_lines
+            } else {
+                max_lines.min(NOTE_MAX_LINES)
+            };
+
+            if max_lines == 0 {
+                break;
+            }
+
+            let mut plan = font.plan(&annot.note.replace('\n', " "), None, None);
+            let mut lines = Vec::new();
+
+            while plan.width > max_width && lines.len() + 1 < max_lines {
+                let (index, width) = font.cut_point(&plan, max_width);
+                if index == 0 {
+                    break;
+                }
+                let mut next_plan = plan.split_off(index, width);
+                font.trim_left(&mut next_plan);
+                lines.push(plan);
+                plan = next_plan;
+            }
+
+            font.crop_right(&mut plan, max_width);
+            lines.push(plan);
+
+            let rect = rect![self.rect.max.x - notes_width, y,
+                             self.rect.max.x, y + lines.len() as i32 * line_height];
+            min_y = rect.max.y + padding;
+            boxes.push(NoteBox { selection: annot.selection, rect, lines });
+        }
+
+        boxes
+    }
+
+    fn is_two_up(&self) -> bool {
+        self.two_pages && self.view_port.zoom_mode == ZoomMode::FitToPage
+    }
+
+    fn cover_alone(&self) -> bool {
+        !self.reflowable && self.info.reader.as_ref().and_then(|r| r.cover_alone) == Some(true)
+    }
+
+    // Returns the location of the left page of the spread that contains the given location.
+    fn spread_start(&self, location: usize) -> usize {
+        if self.reflowable {
+            location
+        } else if self.cover_alone() {
+            if location == 0 { 0 } else { location - (location - 1) % 2 }
+        } else {
+            location - location % 2
+        }
+    }
+
+    // Returns, in continuous mode, the offset of the first visible word of a reflowable document.
+    fn first_visible_offset(&self) -> Option<usize> {
+        if !self.reflowable || self.fixed_doc.is_some() || self.view_port.zoom_mode != ZoomMode::FitToWidth {
+            return None;
+        }
+        let chunk = self.chunks.first()?;
+        self.text.get(&chunk.location)?.iter()
+            .find(|w| (w.rect.max.y * chunk.scale) as i32 > chunk.frame.min.y)
+            .and_then(|w| match w.location {
+                TextLocation::Dynamic(offset) => Some(offset),
+                _ => None,
+            })
+    }
+
+    // Shows, in continuous mode, the word at the given offset at the top of the screen.
+    fn scroll_to_offset(&mut self, offset: usize) {
+        let position = locate_offset(self.doc.lock().unwrap().as_mut(), offset);
+        if let Some((location, y_pos)) = position {
+            self.current_page = location;
+            self.view_port.top_offset = (y_pos as i32 - self.page_margin).max(0);
+        }
+    }
+
+    fn render_params(&self) -> RenderParams {
+        let reader = self.info.reader.as_ref();
+        // In continuous mode, the vertical margins of the pages of reflowable
+        // documents are cropped, so that their contents form a single column.
+        let cropping_margins = if self.reflowable && self.view_port.zoom_mode == ZoomMode::FitToWidth {
+            let ratio = self.page_margin as f32 / self.rect.height() as f32;
+            Some(CroppingMargins::Any(Margin::new(ratio, 0.0, ratio, 0.0)))
+        } else {
+            reader.and_then(|r| r.cropping_margins.clone())
+        };
+        let enhance = |value: Option<bool>| !self.reflowable && value == Some(true);
+        let rect = if self.is_two_up() {
+            rect![self.rect.min, pt!(self.rect.min.x + self.rect.width() as i32 / 2, self.rect.max.y)]
+        } else {
+            self.rect
+        };
+        RenderParams {
+            rect,
+            cropping_margins,
+            margin_width: self.view_port.margin_width,
+            zoom_mode: self.view_port.zoom_mode,
+            zoom_factor: self.view_port.zoom_factor,
+            auto_levels: enhance(reader.and_then(|r| r.auto_levels)),
+            despeckle: enhance(reader.and_then(|r| r.despeckle)),
+            deskew: enhance(reader.and_then(|r| r.deskew)),
+            dithering: reader.and_then(|r| r.dithering).filter(|_| !self.reflowable),
+            gray_levels: self.gray_levels,
+            skew_angles: Arc::clone(&self.skew_angles),
+        }
+    }
+
+    // The cache key of the page at the given location, rendered at the current zoom factor.
+    fn cache_key(&self, location: usize) -> (usize, u32) {
+        if self.view_port.zoom_mode == ZoomMode::Custom {
+            (location, (self.view_port.zoom_factor * ZOOM_STEPS).round() as u32)
+        } else {
+            (location, 0)
         }
     }
 
     fn load_pixmap(&mut self, location: usize) {
-        if self.cache.contains_key(&location) {
+        let key = self.cache_key(location);
+        if self.cache.contains_key(&key) {
             return;
         }
 
+        let params = self.render_params();
         let mut doc = self.doc.lock().unwrap();
-        let cropping_margin = self.info.reader.as_ref()
-                                  .and_then(|r| r.cropping_margins.as_ref()
-                                                 .map(|c| c.margin(location)))
-                                  .cloned().unwrap_or_default();
-        let dims = doc.dims(location).unwrap();
-        let screen_margin_width = self.view_port.margin_width;
-        let scale = scaling_factor(&self.rect, &cropping_margin, screen_margin_width, dims, self.view_port.zoom_mode);
-        if let Some((pixmap, _)) = doc.pixmap(Location::Exact(location), scale) {
-            let frame = rect![(cropping_margin.left * pixmap.width as f32).ceil() as i32,
-                              (cropping_margin.top * pixmap.height as f32).ceil() as i32,
-                              ((1.0 - cropping_margin.right) * pixmap.width as f32).floor() as i32,
-                              ((1.0 - cropping_margin.bottom) * pixmap.height as f32).floor() as i32];
-            self.cache.insert(location, Resource { pixmap, frame, scale });
+        if let Some(resource) = render_resource(doc.as_mut(), location, &params) {
+            self.cache.insert(key, resource);
         }
     }
 
+    // Moves the pixmap rendered in the background into the cache,
+    // unless the rendering parameters have changed in the meantime.
+    fn load_prefetched_pixmap(&mut self, location: usize) {
+        let entry = self.prefetched.lock().unwrap().remove(&location);
+        if let Some((generation, resource)) = entry {
+            let key = self.cache_key(location);
+            if generation == self.render_generation.load(AtomicOrdering::Relaxed) &&
+               !self.cache.contains_key(&key) {
+                self.cache.insert(key, resource);
+            }
+        }
+    }
+
+    fn clear_cache(&mut self) {
+        self.render_generation.fetch_add(1, AtomicOrdering::Relaxed);
+        self.prefetched.lock().unwrap().clear();
+        self.cache.clear();
+    }
+
     fn load_text(&mut self, location: usize) {
         if self.text.contains_key(&location) {
             return;
@@ -461,6 +1227,8 @@ impl Reader {
             }
 
             self.view_port.top_offset = 0;
+            self.view_port.left_offset = 0;
+            self.view_port.column_index = 0;
             self.current_page = location;
             self.update(None, hub, context);
             self.update_bottom_bar(hub);
@@ -531,8 +1299,9 @@ impl Reader {
         if let Some(ref r) = self.info.reader {
             match dir {
                 CycleDir::Next => {
-                    loc_bkm = r.bookmarks.range(self.current_page+1 ..)
-                                         .next().cloned();
+                    loc_bkm = r.bookmarks.iter()
+                               .filter_map(|bkm| self.bookmark_target(bkm.location))
+                               .find(|&location| location > self.current_page);
                     if let Some([_, max]) = self.text_location_range() {
                         loc_annot = r.annotations.iter()
                                      .filter(|annot| annot.selection[0] > max)
@@ -541,8 +1310,9 @@ impl Reader {
                     }
                 },
                 CycleDir::Previous => {
-                    loc_bkm = r.bookmarks.range(.. self.current_page)
-                                         .next_back().cloned();
+                    loc_bkm = r.bookmarks.iter().rev()
+                               .filter_map(|bkm| self.bookmark_target(bkm.location))
+                               .find(|&location| location < self.current_page);
                     if let Some([min, _]) = self.text_location_range() {
                         loc_annot = r.annotations.iter()
                                      .filter(|annot| annot.selection[1] < min)
@@ -582,23 +1352,40 @@ impl Reader {
             return;
         }
 
+        if self.view_port.zoom_mode == ZoomMode::FitToColumn {
+            let available_height = self.rect.height() as i32 - 2 * self.view_port.margin_width;
+            let Resource { frame, .. } = self.cache[&self.cache_key(self.current_page)];
+            let column = self.cache[&self.cache_key(self.current_page)].columns
+                             .get(self.view_port.column_index)
+                             .cloned().unwrap_or(frame);
+            let max_top_offset = (column.height() as i32 - available_height).max(0);
+            let next_top_offset = (self.view_port.top_offset - delta_y).max(0).min(max_top_offset);
+            if next_top_offset != self.view_port.top_offset {
+                self.view_port.top_offset = next_top_offset;
+                self.update(None, hub, context);
+            }
+            return;
+        }
+
         let mut next_top_offset = self.view_port.top_offset - delta_y;
         let mut location = self.current_page;
-        let max_top_offset = self.cache[&location].frame.height().saturating_sub(1) as i32;
+        let max_top_offset = self.cache[&self.cache_key(location)].frame.height().saturating_sub(1) as i32;
         if next_top_offset < 0 {
-            let mut doc = self.doc.lock().unwrap();
-            if let Some(previous_location) = doc.resolve_location(Location::Previous(location)) {
+            let previous_location = self.doc.lock().unwrap().resolve_location(Location::Previous(location));
+            if let Some(previous_location) = previous_location {
                 location = previous_location;
-                let frame = self.cache[&location].frame;
+                self.load_pixmap(location);
+                let frame = self.cache[&self.cache_key(location)].frame;
                 next_top_offset = (frame.height() as i32 + next_top_offset).max(0);
             } else {
                 next_top_offset = 0;
             }
         } else if next_top_offset > max_top_offset {
-            let mut doc = self.doc.lock().unwrap();
-            if let Some(next_location) = doc.resolve_location(Location::Next(location)) {
+            let next_location = self.doc.lock().unwrap().resolve_location(Location::Next(location));
+            if let Some(next_location) = next_location {
                 location = next_location;
-                let frame = self.cache[&location].frame;
+                self.load_pixmap(location);
+                let frame = self.cache[&self.cache_key(location)].frame;
                 let mto = frame.height().saturating_sub(1) as i32;
                 next_top_offset = (next_top_offset - max_top_offset - 1).min(mto);
             } else {
@@ -607,7 +1394,7 @@ impl Reader {
         }
 
         {
-            let Resource { frame, scale, .. } = *self.cache.get(&location).unwrap();
+            let Resource { frame, scale, .. } = self.cache[&self.cache_key(location)];
             let mut doc = self.doc.lock().unwrap();
             if let Some((lines, _)) = doc.lines(Location::Exact(location)) {
                 if let Some(mut y_pos) = find_cut(&frame, frame.min.y + next_top_offset,
@@ -638,13 +1425,58 @@ impl Reader {
         }
     }
 
+    // Scrolls, in continuous mode, by a fixed fraction of the screen.
+    fn button_scroll(&mut self, dir: CycleDir, hub: &Hub, context: &mut Context) {
+        let available_height = self.rect.height() as i32 - 2 * self.view_port.margin_width;
+        let delta_y = (BUTTON_SCROLL_RATIO * available_height as f32) as i32;
+        match dir {
+            CycleDir::Next => self.page_scroll(-delta_y, hub, context),
+            CycleDir::Previous => self.page_scroll(delta_y, hub, context),
+        }
+    }
+
+    // Returns the top offset, within the given column, of the screen that ends at the given offset.
+    fn column_offset_before(&mut self, location: usize, index: usize, end_offset: Option<i32>) -> i32 {
+        let available_height = self.rect.height() as i32 - 2 * self.view_port.margin_width;
+        let Resource { frame, scale, .. } = self.cache[&self.cache_key(location)];
+        let column = self.cache[&self.cache_key(location)].columns.get(index).cloned().unwrap_or(frame);
+        let end_offset = end_offset.unwrap_or_else(|| column.height() as i32);
+        let mut top_offset = (end_offset - available_height).max(0);
+
+        if top_offset > 0 {
+            let mut doc = self.doc.lock().unwrap();
+            if let Some((lines, _)) = doc.lines(Location::Exact(location)) {
+                if let Some(y_pos) = find_cut(&column, column.min.y + top_offset, scale, LinearDir::Forward, &lines) {
+                    top_offset = (y_pos - column.min.y).max(0).min(end_offset - 1);
+                }
+            }
+        }
+
+        top_offset
+    }
+
     fn go_to_neighbor(&mut self, dir: CycleDir, hub: &Hub, context: &mut Context) {
         let current_page = self.current_page;
         let top_offset = self.view_port.top_offset;
+        let left_offset = self.view_port.left_offset;
+        let column_index = self.view_port.column_index;
+        let available_width = self.rect.width() as i32 - 2 * self.view_port.margin_width;
+        let available_height = self.rect.height() as i32 - 2 * self.view_port.margin_width;
 
         let loc = {
             let neighloc = if dir == CycleDir::Previous {
                 match self.view_port.zoom_mode {
+                    ZoomMode::FitToPage if self.is_two_up() && self.reflowable => {
+                        // Go back two pages, or to the first page.
+                        let mut doc = self.doc.lock().unwrap();
+                        match doc.resolve_location(Location::Previous(current_page)) {
+                            Some(location) if doc.resolve_location(Location::Previous(location)).is_some() => {
+                                Location::Previous(location)
+                            },
+                            Some(location) => Location::Exact(location),
+                            None => Location::Previous(current_page),
+                        }
+                    },
                     ZoomMode::FitToPage => Location::Previous(current_page),
                     ZoomMode::FitToWidth => {
                         let first_chunk = self.chunks.first().cloned().unwrap();
@@ -655,7 +1487,7 @@ impl Reader {
                         loop {
                             self.load_pixmap(location);
                             self.load_text(location);
-                            let Resource { mut frame, .. } = self.cache[&location];
+                            let Resource { mut frame, .. } = self.cache[&self.cache_key(location)];
                             if location == first_chunk.location {
                                 frame.max.y = first_chunk.frame.min.y;
                             }
@@ -673,7 +1505,7 @@ impl Reader {
 
                         let mut next_top_offset = (height - available_height).max(0);
                         if height > available_height {
-                            let Resource { frame, scale, .. } = self.cache[&location];
+                            let Resource { frame, scale, .. } = self.cache[&self.cache_key(location)];
                             let mut doc = self.doc.lock().unwrap();
                             if let Some((lines, _)) = doc.lines(Location::Exact(location)) {
                                 if let Some(mut y_pos) = find_cut(&frame, frame.min.y + next_top_offset,
@@ -687,13 +1519,51 @@ impl Reader {
                         self.view_port.top_offset = next_top_offset;
                         Location::Exact(location)
                     },
+                    ZoomMode::FitToColumn => {
+                        if top_offset > 0 {
+                            self.view_port.top_offset = self.column_offset_before(current_page, column_index, Some(top_offset));
+                            Location::Exact(current_page)
+                        } else if column_index > 0 {
+                            self.view_port.column_index = column_index - 1;
+                            self.view_port.top_offset = self.column_offset_before(current_page, column_index - 1, None);
+                            Location::Exact(current_page)
+                        } else {
+                            let previous_location = {
+                                let mut doc = self.doc.lock().unwrap();
+                                doc.resolve_location(Location::Previous(current_page))
+                            };
+                            if let Some(location) = previous_location {
+                                self.load_pixmap(location);
+                                let index = self.cache[&self.cache_key(location)].columns.len().saturating_sub(1);
+                                self.view_port.column_index = index;
+                                self.view_port.top_offset = self.column_offset_before(location, index, None);
+                                Location::Exact(location)
+                            } else {
+                                Location::Previous(current_page)
+                            }
+                        }
+                    },
+                    ZoomMode::Custom => {
+                        if top_offset > 0 {
+                            self.view_port.top_offset = (top_offset - available_height).max(0);
+                            Location::Exact(current_page)
+                        } else if left_offset > 0 {
+                            self.view_port.left_offset = (left_offset - available_width).max(0);
+                            self.view_port.top_offset = i32::MAX;
+                            Location::Exact(current_page)
+                        } else {
+                            self.view_port.left_offset = i32::MAX;
+                            self.view_port.top_offset = i32::MAX;
+                            Location::Previous(current_page)
+                        }
+                    },
                 }
             } else {
                 match self.view_port.zoom_mode {
-                    ZoomMode::FitToPage => Location::Next(current_page),
+                    ZoomMode::FitToPage => Location::Next(self.chunks.last().map_or(current_page, |c| c.location)),
                     ZoomMode::FitToWidth => {
                         let last_chunk = self.chunks.last().unwrap();
-                        let pixmap_frame = self.cache[&last_chunk.location].frame;
+                        let pixmap_frame = self.cache[&self.cache_key(last_chunk.location)].frame;
                         let next_top_offset = last_chunk.frame.max.y - pixmap_frame.min.y;
                         if next_top_offset == pixmap_frame.height() as i32 {
                             self.view_port.top_offset = 0;
@@ -703,13 +1573,49 @@ impl Reader {
                             Location::Exact(last_chunk.location)
                         }
                     },
+                    ZoomMode::FitToColumn => {
+                        let last_chunk = self.chunks.last().cloned().unwrap();
+                        let Resource { frame, .. } = self.cache[&self.cache_key(last_chunk.location)];
+                        let columns_count = self.cache[&self.cache_key(last_chunk.location)].columns.len();
+                        let column = self.cache[&self.cache_key(last_chunk.location)].columns
+                                         .get(column_index).cloned().unwrap_or(frame);
+                        if last_chunk.frame.max.y < column.max.y {
+                            self.view_port.top_offset = last_chunk.frame.max.y - column.min.y;
+                            Location::Exact(last_chunk.location)
+                        } else if column_index + 1 < columns_count {
+                            self.view_port.column_index = column_index + 1;
+                            self.view_port.top_offset = 0;
+                            Location::Exact(last_chunk.location)
+                        } else {
+                            self.view_port.column_index = 0;
+                            self.view_port.top_offset = 0;
+                            Location::Next(last_chunk.location)
+                        }
+                    },
+                    ZoomMode::Custom => {
+                        let last_chunk = self.chunks.last().cloned().unwrap();
+                        let Resource { frame, .. } = self.cache[&self.cache_key(last_chunk.location)];
+                        if last_chunk.frame.max.y < frame.max.y {
+                            self.view_port.top_offset = last_chunk.frame.max.y - frame.min.y;
+                            Location::Exact(last_chunk.location)
+                        } else if last_chunk.frame.max.x < frame.max.x {
+                            self.view_port.left_offset = last_chunk.frame.max.x - frame.min.x;
+                            self.view_port.top_offset = 0;
+                            Location::Exact(last_chunk.location)
+                        } else {
+                            self.view_port.left_offset = 0;
+                            self.view_port.top_offset = 0;
+                            Location::Next(last_chunk.location)
+                        }
+                    },
                 }
             };
             let mut doc = self.doc.lock().unwrap();
             doc.resolve_location(neighloc)
         };
         match loc {
-            Some(location) if location != current_page || self.view_port.top_offset != top_offset => {
+            Some(location) if location != current_page || self.view_port.top_offset != top_offset ||
+                              self.view_port.left_offset != left_offset || self.view_port.column_index != column_index => {
                 if let Some(ref mut s) = self.search {
                     s.current_page = s.highlights.range(..=location).count().saturating_sub(1);
                 }
@@ -723,6 +1629,9 @@ impl Reader {
                 }
             },
             _ => {
+                self.view_port.top_offset = top_offset;
+                self.view_port.left_offset = left_offset;
+                self.view_port.column_index = column_index;
                 match dir {
                     CycleDir::Next => {
                         self.finished = true;
@@ -767,6 +1676,8 @@ impl Reader {
         }
         if let Some(location) = loc {
             self.view_port.top_offset = 0;
+            self.view_port.left_offset = 0;
+            self.view_port.column_index = 0;
             self.current_page = location;
             self.update_results_bar(hub);
             self.update_bottom_bar(hub);
@@ -788,6 +1699,8 @@ impl Reader {
                 s.current_page = s.highlights.range(..=location).count().saturating_sub(1);
             }
             self.view_port.top_offset = 0;
+            self.view_port.left_offset = 0;
+            self.view_port.column_index = 0;
             self.current_page = location;
             self.update_results_bar(hub);
             self.update_bottom_bar(hub);
@@ -798,6 +1711,7 @@ impl Reader {
     fn update_bottom_bar(&mut self, hub: &Hub) {
         if let Some(index) = locate::<BottomBar>(self) {
             let current_page = self.current_page;
+            let page_name = self.page_name(current_page);
             let mut doc = self.doc.lock().unwrap();
             let chapter = self.toc().or_else(|| doc.toc())
                               .as_ref().and_then(|toc| doc.chapter(current_page, toc))
@@ -808,7 +1722,7 @@ impl Reader {
                 previous_page: doc.resolve_location(Location::Previous(current_page)),
                 next_page: doc.resolve_location(Location::Next(current_page)),
             };
-            bottom_bar.update_page_label(self.current_page, self.pages_count, hub);
+            bottom_bar.update_page_label(self.current_page, self.pages_count, page_name, hub);
             bottom_bar.update_icons(&neighbors, hub);
             bottom_bar.update_chapter(&chapter, hub);
         }
@@ -872,7 +1786,7 @@ impl Reader {
                 if words.is_empty() {
                     continue;
                 }
-                for annot in annotations {
+                for annot in annotations.iter().filter(|annot| !annot.orphan) {
                     let [start, end] = annot.selection;
                     if (start >= words[0].location && start <= words[words.len()-1].location) ||
                        (end >= words[0].location && end <= words[words.len()-1].location) {
@@ -886,6 +1800,15 @@ impl Reader {
     }
 
     fn update(&mut self, update_mode: Option<UpdateMode>, hub: &Hub, context: &Context) {
+        let gray_levels = if context.fb.monochrome() { 2 } else { 16 };
+        if gray_levels != self.gray_levels {
+            self.gray_levels = gray_levels;
+            if let Some(dithering) = self.info.reader.as_ref().and_then(|r| r.dithering) {
+                self.doc.lock().unwrap().set_dithering(Some((dithering, gray_levels)));
+                self.clear_cache();
+            }
+        }
+
         self.page_turns += 1;
         let update_mode = update_mode.unwrap_or_else(|| {
             let refresh_rate = if context.fb.inverted() {
@@ -905,11 +1828,39 @@ impl Reader {
         let smw = self.view_port.margin_width;
 
         match self.view_port.zoom_mode {
+            ZoomMode::FitToPage if self.is_two_up() => {
+                location = self.spread_start(location);
+                self.current_page = location;
+                let second_location = if self.cover_alone() && location == 0 {
+                    None
+                } else {
+                    let mut doc = self.doc.lock().unwrap();
+                    doc.resolve_location(Location::Next(location))
+                };
+                let middle = self.rect.min.x + self.rect.width() as i32 / 2;
+                self.load_pixmap(location);
+                self.load_text(location);
+                let Resource { frame, scale, .. } = self.cache[&self.cache_key(location)];
+                let dy = smw + ((self.rect.height() - frame.height()) as i32 - 2 * smw) / 2;
+                if let Some(second_location) = second_location {
+                    // The pages meet in the middle of the screen.
+                    self.chunks.push(RenderChunk { frame, location, position: pt!(middle - frame.width() as i32, dy), scale });
+                    self.load_pixmap(second_location);
+                    self.load_text(second_location);
+                    let Resource { frame, scale, .. } = self.cache[&self.cache_key(second_location)];
+                    let dy = smw + ((self.rect.height() - frame.height()) as i32 - 2 * smw) / 2;
+                    self.chunks.push(RenderChunk { frame, location: second_location, position: pt!(middle, dy), scale });
+                } else {
+                    let dx = middle - frame.width() as i32 / 2;
+                    self.chunks.push(RenderChunk { frame, location, position: pt!(dx, dy), scale });
+                }
+            },
             ZoomMode::FitToPage => {
                 self.load_pixmap(location);
                 self.load_text(location);
-                let Resource { frame, scale, .. } = self.cache[&location];
-                let dx = smw + ((self.rect.width() - frame.width()) as i32 - 2 * smw) / 2;
+                let Resource { frame, scale, .. } = self.cache[&self.cache_key(location)];
+                let width = self.rect.width() - self.notes_width();
+                let dx = smw + ((width - frame.width()) as i32 - 2 * smw) / 2;
                 let dy = smw + ((self.rect.height() - frame.height()) as i32 - 2 * smw) / 2;
                 self.chunks.push(RenderChunk { frame, location, position: pt!(dx, dy), scale });
             },
@@ -919,11 +1870,13 @@ impl Reader {
                 while height < available_height {
                     self.load_pixmap(location);
                     self.load_text(location);
-                    let Resource { mut frame, scale, .. } = self.cache[&location];
+                    let Resource { mut frame, scale, .. } = self.cache[&self.cache_key(location)];
                     if location == self.current_page {
                         frame.min.y += self.view_port.top_offset;
                     }
-                    let position = pt!(smw, smw + height);
+                    let width = (self.rect.width() - self.notes_width()) as i32;
+                    let dx = smw + (width - 2 * smw - frame.width() as i32).max(0) / 2;
+                    let position = pt!(dx, smw + height);
                     self.chunks.push(RenderChunk { frame, location, position, scale });
                     height += frame.height() as i32;
                     if let Ok(mut doc) = self.doc.lock() {
@@ -935,11 +1888,12 @@ impl Reader {
                     }
                 }
                 if height > available_height {
+                    let scale_key = self.cache_key(0).1;
                     if let Some(last_chunk) = self.chunks.last_mut() {
                         last_chunk.frame.max.y -= height - available_height;
                         let mut doc = self.doc.lock().unwrap();
                         if let Some((lines, _)) = doc.lines(Location::Exact(last_chunk.location)) {
-                            let pixmap_frame = self.cache[&last_chunk.location].frame;
+                            let pixmap_frame = self.cache[&(last_chunk.location, scale_key)].frame;
                             if let Some(mut y_pos) = find_cut(&pixmap_frame, last_chunk.frame.max.y, last_chunk.scale, LinearDir::Backward, &lines) {
                                 y_pos = y_pos.max(pixmap_frame.min.y).min(pixmap_frame.max.y - 1);
                                 last_chunk.frame.max.y = y_pos;
@@ -953,39 +1907,109 @@ impl Reader {
                     }
                 }
             },
+            ZoomMode::FitToColumn => {
+                self.load_pixmap(location);
+                self.load_text(location);
+                let available_height = self.rect.height() as i32 - 2 * smw;
+                let Resource { frame, scale, .. } = self.cache[&self.cache_key(location)];
+                let column = self.cache[&self.cache_key(location)].columns
+                                 .get(self.view_port.column_index)
+                                 .cloned().unwrap_or(frame);
+                let mut chunk_frame = column;
+                chunk_frame.min.y += self.view_port.top_offset.min(column.height() as i32 - 1).max(0);
+                if chunk_frame.height() as i32 > available_height {
+                    chunk_frame.max.y = chunk_frame.min.y + available_height;
+                    let mut doc = self.doc.lock().unwrap();
+                    if let Some((lines, _)) = doc.lines(Location::Exact(location)) {
+                        if let Some(y_pos) = find_cut(&column, chunk_frame.max.y, scale, LinearDir::Backward, &lines) {
+                            if y_pos > chunk_frame.min.y {
+                                chunk_frame.max.y = y_pos;
+                            }
+                        }
+                    }
+                }
+                let dx = smw + ((self.rect.width() - chunk_frame.width()) as i32 - 2 * smw) / 2;
+                let dy = smw + (available_height - chunk_frame.height() as i32) / 2;
+                self.chunks.push(RenderChunk { frame: chunk_frame, location, position: pt!(dx, dy), scale });
+            },
+            ZoomMode::Custom => {
+                self.load_pixmap(location);
+                self.load_text(location);
+                let available_width = self.rect.width() as i32 - 2 * smw;
+                let available_height = self.rect.height() as i32 - 2 * smw;
+                let Resource { frame, scale, .. } = self.cache[&self.cache_key(location)];
+                let max_left_offset = (frame.width() as i32 - available_width).max(0);
+                let max_top_offset = (frame.height() as i32 - available_height).max(0);
+                self.view_port.left_offset = self.view_port.left_offset.max(0).min(max_left_offset);
+                self.view_port.top_offset = self.view_port.top_offset.max(0).min(max_top_offset);
+                let mut chunk_frame = frame;
+                chunk_frame.min.x += self.view_port.left_offset;
+                chunk_frame.min.y += self.view_port.top_offset;
+                chunk_frame.max.x = chunk_frame.max.x.min(chunk_frame.min.x + available_width);
+                chunk_frame.max.y = chunk_frame.max.y.min(chunk_frame.min.y + available_height);
+                let dx = smw + (available_width - chunk_frame.width() as i32) / 2;
+                let dy = smw + (available_height - chunk_frame.height() as i32) / 2;
+                self.chunks.push(RenderChunk { frame: chunk_frame, location, position: pt!(dx, dy), scale });
+            },
         }
 
         hub.send(Event::Render(self.rect, update_mode)).ok();
         let first_location = self.chunks.first().map(|c| c.location).unwrap();
         let last_location = self.chunks.last().map(|c| c.location).unwrap();
 
-        while self.cache.len() > 3 {
-            let left_count = self.cache.range(..first_location).count();
-            let right_count = self.cache.range(last_location+1..).count();
-            let extremum = if left_count >= right_count {
+        // Evict the pages farthest from the displayed ones until the cache fits in memory.
+        let mut cache_size = self.cache.values().map(|r| r.pixmap.data.len()).sum::<usize>();
+        // The pages rendered at other zoom factors go first.
+        let scale_key = self.cache_key(first_location).1;
+        while cache_size > MAX_CACHE_SIZE {
+            let left_count = self.cache.range(..(first_location, 0)).count();
+            let right_count = self.cache.range((last_location+1, 0)..).count();
+            let extremum = if let Some(key) = self.cache.keys().find(|k| k.1 != scale_key).cloned() {
+                key
+            } else if left_count == 0 && right_count == 0 {
+                break;
+            } else if left_count >= right_count {
                 self.cache.keys().next().cloned().unwrap()
             } else {
                 self.cache.keys().next_back().cloned().unwrap()
             };
-            self.cache.remove(&extremum);
+            if let Some(resource) = self.cache.remove(&extremum) {
+                cache_size -= resource.pixmap.data.len();
+            }
         }
 
         self.update_annotations();
 
-        let doc2 = self.doc.clone();
+        self.prefetch(Location::Next(last_location), hub);
+        self.prefetch(Location::Previous(first_location), hub);
+    }
+
+    // Renders the page at the given location in a background thread.
+    fn prefetch(&self, loc: Location, hub: &Hub) {
+        let doc2 = Arc::clone(&self.doc);
         let hub2 = hub.clone();
+        let params = self.render_params();
+        let cached = self.cache.keys().cloned().collect::<Vec<(usize, u32)>>();
+        let scale_key = self.cache_key(0).1;
+        let prefetched = Arc::clone(&self.prefetched);
+        let render_generation = Arc::clone(&self.render_generation);
+        let generation = render_generation.load(AtomicOrdering::Relaxed);
         thread::spawn(move || {
             let mut doc = doc2.lock().unwrap();
-            if let Some(next_location) = doc.resolve_location(Location::Next(last_location)) {
-                hub2.send(Event::LoadPixmap(next_location)).ok();
+            // Skip the jobs made stale by a change of the zoom or the cropping.
+            if render_generation.load(AtomicOrdering::Relaxed) != generation {
+                return;
             }
-        });
-        let doc3 = self.doc.clone();
-        let hub3 = hub.clone();
-        thread::spawn(move || {
-            let mut doc = doc3.lock().unwrap();
-            if let Some(previous_location) = doc.resolve_location(Location::Previous(first_location)) {
-                hub3.send(Event::LoadPixmap(previous_location)).ok();
+            if let Some(location) = doc.resolve_location(loc) {
+                if cached.contains(&(location, scale_key)) || prefetched.lock().unwrap().contains_key(&location) {
+                    return;
+                }
+                if let Some(resource) = render_resource(doc.as_mut(), location, &params) {
+                    if render_generation.load(AtomicOrdering::Relaxed) == generation {
+                        prefetched.lock().unwrap().insert(location, (generation, resource));
+                        hub2.send(Event::LoadPixmap(location)).ok();
+                    }
+                }
             }
         });
     }
@@ -1426,6 +2450,7 @@ impl Reader {
                                             self.toc(),
                                             self.current_page,
                                             self.pages_count,
+                                            self.page_name(self.current_page),
                                             &neighbors,
                                             self.synthetic);
             self.children.insert(index, Box::new(bottom_bar) as Box<dyn View>);
@@ -1521,7 +2546,92 @@ impl Reader {
             hub.send(Event::Render(*name_page.rect(), UpdateMode::Gui)).ok();
             hub.send(Event::Focus(Some(ViewId::NamePageInput))).ok();
 
-            self.children.push(Box::new(name_page) as Box<dyn View>);
+            self.children.push(Box::new(name_page) as Box<dyn View>);
+        }
+    }
+
+    fn toggle_unlock(&mut self, enable: Option<bool>, hub: &Hub, context: &mut Context) {
+        if let Some(index) = locate_by_id(self, ViewId::Unlock) {
+            if let Some(true) = enable {
+                return;
+            }
+
+            hub.send(Event::Expose(*self.child(index).rect(), UpdateMode::Gui)).ok();
+            self.children.remove(index);
+
+            if self.focus.map(|focus_id| focus_id == ViewId::UnlockInput).unwrap_or(false) {
+                self.toggle_keyboard(false, None, hub, context);
+            }
+        } else {
+            if let Some(false) = enable {
+                return;
+            }
+
+            let unlock = NamedInput::new("Password".to_string(), ViewId::Unlock, ViewId::UnlockInput, 16, context);
+            hub.send(Event::Render(*unlock.rect(), UpdateMode::Gui)).ok();
+            hub.send(Event::Focus(Some(ViewId::UnlockInput))).ok();
+
+            self.children.push(Box::new(unlock) as Box<dyn View>);
+        }
+    }
+
+    // While the document is locked, only the events related to the password prompt are handled.
+    fn handle_locked_event(&mut self, evt: &Event, hub: &Hub, context: &mut Context) -> bool {
+        match *evt {
+            Event::Submit(ViewId::UnlockInput, ref text) => {
+                let unlocked = self.doc.lock().unwrap().authenticate(text);
+                if unlocked {
+                    self.locked = false;
+                    self.password = Some(text.to_string());
+                    self.toggle_unlock(Some(false), hub, context);
+                    if self.load(hub, context).is_none() {
+                        hub.send(Event::Back).ok();
+                        return true;
+                    }
+                    if context.settings.reader.remember_passwords {
+                        if let Some(ref mut r) = self.info.reader {
+                            r.password = Some(text.to_string());
+                        }
+                    }
+                } else {
+                    let notif = Notification::new(ViewId::MessageNotif,
+                                                  "Incorrect password.".to_string(),
+                                                  hub,
+                                                  context);
+                    self.children.push(Box::new(notif) as Box<dyn View>);
+                    if let Some(index) = locate_by_id(self, ViewId::Unlock) {
+                        if let Some(unlock) = self.children[index].downcast_mut::<NamedInput>() {
+                            unlock.set_text("", hub, context);
+                        }
+                    }
+                    self.retry_unlock = true;
+                }
+                true
+            },
+            Event::Close(ViewId::Unlock) => {
+                if self.retry_unlock {
+                    self.retry_unlock = false;
+                } else {
+                    self.toggle_unlock(Some(false), hub, context);
+                }
+                true
+            },
+            Event::Gesture(GestureEvent::Tap(center)) if self.rect.includes(center) => {
+                self.toggle_unlock(Some(true), hub, context);
+                true
+            },
+            Event::Device(DeviceEvent::Button { code: ButtonCode::Home, status: ButtonStatus::Pressed, .. }) => {
+                hub.send(Event::Back).ok();
+                true
+            },
+            Event::Focus(v) => {
+                self.focus = v;
+                if v.is_some() {
+                    self.toggle_keyboard(true, v, hub, context);
+                }
+                true
+            },
+            _ => false,
         }
     }
 
@@ -1571,6 +2681,11 @@ impl Reader {
 
             let sel = annot.selection;
             let mut entries = Vec::new();
+            let styles = HighlightStyle::all().iter().map(|s|
+                EntryKind::RadioButton(s.to_string(),
+                                       EntryId::SetAnnotationStyle(sel, *s),
+                                       annot.style == *s)
+            ).collect::<Vec<EntryKind>>();
 
             if annot.note.is_empty() {
                 entries.push(EntryKind::Command("Remove Highlight".to_string(), EntryId::RemoveAnnotation(sel)));
@@ -1583,6 +2698,8 @@ impl Reader {
                 entries.push(EntryKind::Command("Remove Note".to_string(), EntryId::RemoveAnnotationNote(sel)));
             }
 
+            entries.push(EntryKind::SubMenu("Style".to_string(), styles));
+
             let selection_menu = Menu::new(rect, ViewId::AnnotationMenu, MenuKind::Contextual, entries, context);
             hub.send(Event::Render(*selection_menu.rect(), UpdateMode::Gui)).ok();
             self.children.push(Box::new(selection_menu) as Box<dyn View>);
@@ -1601,8 +2718,12 @@ impl Reader {
             if let Some(false) = enable {
                 return;
             }
+            let styles = HighlightStyle::all().iter().map(|s|
+                EntryKind::Command(s.to_string(), EntryId::HighlightSelection(*s))
+            ).collect::<Vec<EntryKind>>();
             let mut entries = vec![
-                EntryKind::Command("Highlight".to_string(), EntryId::HighlightSelection),
+                EntryKind::Command("Highlight".to_string(), EntryId::HighlightSelection(HighlightStyle::default())),
+                EntryKind::SubMenu("Highlight As".to_string(), styles),
                 EntryKind::Command("Add Note".to_string(), EntryId::AnnotateSelection)
             ];
 
@@ -1610,7 +2731,7 @@ impl Reader {
             entries.push(EntryKind::Command("Define".to_string(), EntryId::DefineSelection));
             entries.push(EntryKind::Command("Search".to_string(), EntryId::SearchForSelection));
 
-            if self.info.reader.as_ref().map_or(false, |r| !r.page_names.is_empty()) {
+            if !self.page_labels.is_empty() || self.info.reader.as_ref().map_or(false, |r| !r.page_names.is_empty()) {
                 entries.push(EntryKind::Command("Go To".to_string(), EntryId::GoToSelectedPageName));
             }
 
@@ -1636,18 +2757,101 @@ impl Reader {
                 return;
             }
 
+            let mut entries = Vec::new();
+
             if self.reflowable {
-                return;
+                entries.push(EntryKind::CheckBox("Continuous Scroll".to_string(),
+                                                 EntryId::ToggleContinuous,
+                                                 self.view_port.zoom_mode == ZoomMode::FitToWidth));
+                if !self.two_pages {
+                    entries.push(EntryKind::CheckBox("Margin Notes".to_string(),
+                                                     EntryId::ToggleMarginNotes,
+                                                     self.margin_notes()));
+                }
+            }
+
+            if !self.reflowable {
+                let zoom_mode = self.view_port.zoom_mode;
+                entries.push(EntryKind::SubMenu("Zoom Mode".to_string(), vec![
+                                 EntryKind::RadioButton("Fit to Page".to_string(),
+                                                        EntryId::SetZoomMode(ZoomMode::FitToPage),
+                                                        zoom_mode == ZoomMode::FitToPage),
+                                 EntryKind::RadioButton("Fit to Width".to_string(),
+                                                        EntryId::SetZoomMode(ZoomMode::FitToWidth),
+                                                        zoom_mode == ZoomMode::FitToWidth),
+                                 EntryKind::RadioButton("Fit to Column".to_string(),
+                                                        EntryId::SetZoomMode(ZoomMode::FitToColumn),
+                                                        zoom_mode == ZoomMode::FitToColumn)]));
+            }
+
+            if !self.reflowable {
+                let (deskew, despeckle, auto_levels) = self.info.reader.as_ref()
+                                                           .map(|r| (r.deskew == Some(true),
+                                                                     r.despeckle == Some(true),
+                                                                     r.auto_levels == Some(true)))
+                                                           .unwrap_or_default();
+                entries.push(EntryKind::SubMenu("Enhance".to_string(), vec![
+                                 EntryKind::CheckBox("Deskew".to_string(), EntryId::ToggleDeskew, deskew),
+                                 EntryKind::CheckBox("Despeckle".to_string(), EntryId::ToggleDespeckle, despeckle),
+                                 EntryKind::CheckBox("Auto-Levels".to_string(), EntryId::ToggleAutoLevels, auto_levels)]));
+            }
+
+            let dithering = self.info.reader.as_ref().and_then(|r| r.dithering);
+            let mut dithering_entries = vec![EntryKind::RadioButton("None".to_string(),
+                                                                    EntryId::SetDithering(None),
+                                                                    dithering.is_none())];
+            for mode in &[DitherMode::FloydSteinberg, DitherMode::Atkinson, DitherMode::Bayer] {
+                dithering_entries.push(EntryKind::RadioButton(mode.to_string(),
+                                                              EntryId::SetDithering(Some(*mode)),
+                                                              dithering == Some(*mode)));
+            }
+            entries.push(EntryKind::SubMenu("Dithering".to_string(), dithering_entries));
+
+            if self.is_two_up() && !self.reflowable {
+                entries.push(EntryKind::CheckBox("Cover Alone".to_string(),
+                                                 EntryId::ToggleCoverAlone,
+                                                 self.cover_alone()));
+            }
+
+            if self.fixed_doc.is_none() && !self.ephemeral {
+                let mut ink_entries = vec![
+                    EntryKind::CheckBox("Draw".to_string(), EntryId::ToggleInk, self.ink.enabled),
+                    EntryKind::CheckBox("Eraser".to_string(), EntryId::ToggleInkEraser, self.ink.eraser),
+                    EntryKind::CheckBox("Show".to_string(), EntryId::ToggleInkVisibility, self.ink.visible),
+                ];
+                if !self.ink.history.is_empty() {
+                    ink_entries.push(EntryKind::Separator);
+                    ink_entries.push(EntryKind::Command("Undo".to_string(), EntryId::UndoInk));
+                }
+                entries.push(EntryKind::SubMenu("Ink".to_string(), ink_entries));
+            }
+
+            if self.info.file.kind == "pdf" {
+                entries.push(EntryKind::CheckBox("Reflow".to_string(),
+                                                 EntryId::ToggleReflow,
+                                                 self.fixed_doc.is_some() || self.reflow_job.is_some()));
+            }
+
+            if !self.ephemeral {
+                entries.push(EntryKind::Separator);
+                entries.push(EntryKind::Command("Annotations".to_string(), EntryId::ShowAnnotations));
+                entries.push(EntryKind::Command("Bookmarks".to_string(), EntryId::ShowBookmarks));
+                if self.info.reader.as_ref().map_or(false, |r| !r.annotations.is_empty()) {
+                    let export_entries = ExportFormat::all().iter().map(|format| {
+                        EntryKind::Command(format.to_string(), EntryId::ExportAnnotations(*format))
+                    }).collect::<Vec<EntryKind>>();
+                    entries.push(EntryKind::SubMenu("Export Annotations".to_string(), export_entries));
+                }
+            }
+
+            if self.info.file.kind == "pdf" &&
+               self.info.reader.as_ref().map_or(false, |r| !r.annotations.is_empty()) {
+                if self.ephemeral {
+                    entries.push(EntryKind::Separator);
+                }
+                entries.push(EntryKind::Command("Save Annotated PDF".to_string(), EntryId::SaveAnnotatedDocument));
             }
 
-            let zoom_mode = self.view_port.zoom_mode;
-            let entries = vec![EntryKind::SubMenu("Zoom Mode".to_string(), vec![
-                               EntryKind::RadioButton("Fit to Page".to_string(),
-                                                      EntryId::SetZoomMode(ZoomMode::FitToPage),
-                                                      zoom_mode == ZoomMode::FitToPage),
-                               EntryKind::RadioButton("Fit to Width".to_string(),
-                                                      EntryId::SetZoomMode(ZoomMode::FitToWidth),
-                                                      zoom_mode == ZoomMode::FitToWidth)])];
             let title_menu = Menu::new(rect, ViewId::TitleMenu, MenuKind::DropDown, entries, context);
             hub.send(Event::Render(*title_menu.rect(), UpdateMode::Gui)).ok();
             self.children.push(Box::new(title_menu) as Box<dyn View>);
@@ -1911,6 +3115,13 @@ impl Reader {
             let is_applied = self.info.reader.as_ref()
                                  .map(|r| r.cropping_margins.is_some())
                                  .unwrap_or(false);
+            entries.extend_from_slice(&[EntryKind::Separator,
+                                        EntryKind::SubMenu("Auto-Crop".to_string(),
+                                                           vec![EntryKind::Command("Uniform".to_string(),
+                                                                                   EntryId::AutoCropMargins(PageScheme::Any)),
+                                                                EntryKind::Command("Even/Odd".to_string(),
+                                                                                   EntryId::AutoCropMargins(PageScheme::EvenOdd))])]);
+
             if is_applied {
                 entries.extend_from_slice(&[EntryKind::Separator,
                                             EntryKind::Command("Remove".to_string(), EntryId::RemoveCroppings)]);
@@ -1953,11 +3164,14 @@ impl Reader {
             return;
         }
 
+        let anchor = self.first_visible_offset();
+
         if let Some(ref mut r) = self.info.reader {
             r.font_size = Some(font_size);
         }
 
         let (width, height) = context.display.dims;
+        let width = if self.two_pages { width / 2 } else { text_width(width, self.margin_notes()) };
         {
             let mut doc = self.doc.lock().unwrap();
 
@@ -1975,8 +3189,11 @@ impl Reader {
             }
         }
 
-        self.cache.clear();
+        self.clear_cache();
         self.text.clear();
+        if let Some(offset) = anchor {
+            self.scroll_to_offset(offset);
+        }
         self.update(None, hub, context);
         self.update_tool_bar(hub, context);
         self.update_bottom_bar(hub);
@@ -1987,6 +3204,8 @@ impl Reader {
             return;
         }
 
+        let anchor = self.first_visible_offset();
+
         if let Some(ref mut r) = self.info.reader {
             r.text_align = Some(text_align);
         }
@@ -2006,8 +3225,11 @@ impl Reader {
             }
         }
 
-        self.cache.clear();
+        self.clear_cache();
         self.text.clear();
+        if let Some(offset) = anchor {
+            self.scroll_to_offset(offset);
+        }
         self.update(None, hub, context);
         self.update_tool_bar(hub, context);
         self.update_bottom_bar(hub);
@@ -2018,6 +3240,8 @@ impl Reader {
             return;
         }
 
+        let anchor = self.first_visible_offset();
+
         if let Some(ref mut r) = self.info.reader {
             r.font_family = Some(font_family.to_string());
         }
@@ -2043,8 +3267,11 @@ impl Reader {
             }
         }
 
-        self.cache.clear();
+        self.clear_cache();
         self.text.clear();
+        if let Some(offset) = anchor {
+            self.scroll_to_offset(offset);
+        }
         self.update(None, hub, context);
         self.update_tool_bar(hub, context);
         self.update_bottom_bar(hub);
@@ -2055,6 +3282,8 @@ impl Reader {
             return;
         }
 
+        let anchor = self.first_visible_offset();
+
         if let Some(ref mut r) = self.info.reader {
             r.line_height = Some(line_height);
         }
@@ -2074,8 +3303,11 @@ impl Reader {
             }
         }
 
-        self.cache.clear();
+        self.clear_cache();
         self.text.clear();
+        if let Some(offset) = anchor {
+            self.scroll_to_offset(offset);
+        }
         self.update(None, hub, context);
         self.update_tool_bar(hub, context);
         self.update_bottom_bar(hub);
@@ -2086,6 +3318,8 @@ impl Reader {
             return;
         }
 
+        let anchor = self.first_visible_offset();
+
         if let Some(ref mut r) = self.info.reader {
             if self.reflowable {
                 r.margin_width = Some(width);
@@ -2099,6 +3333,7 @@ impl Reader {
         }
 
         if self.reflowable {
+            self.page_margin = mm_to_px(width as f32, CURRENT_DEVICE.dpi).round() as i32;
             let mut doc = self.doc.lock().unwrap();
             doc.set_margin_width(width);
 
@@ -2120,17 +3355,223 @@ impl Reader {
         }
 
         self.text.clear();
-        self.cache.clear();
+        self.clear_cache();
+        if let Some(offset) = anchor {
+            self.scroll_to_offset(offset);
+        }
         self.update(None, hub, context);
         self.update_tool_bar(hub, context);
         self.update_bottom_bar(hub);
     }
 
+    // The strokes of the document, unless it's the reflowed version of a fixed-layout document.
+    fn strokes(&self) -> Option<&BTreeMap<usize, Vec<Stroke>>> {
+        if self.fixed_doc.is_some() {
+            return None;
+        }
+        self.info.reader.as_ref().map(|r| if self.reflowable { &r.word_ink } else { &r.ink })
+    }
+
+    fn strokes_mut(&mut self) -> Option<&mut BTreeMap<usize, Vec<Stroke>>> {
+        if self.fixed_doc.is_some() {
+            return None;
+        }
+        let reflowable = self.reflowable;
+        self.info.reader.as_mut().map(|r| if reflowable { &mut r.word_ink } else { &mut r.ink })
+    }
+
+    // Returns the keys of the strokes shown in the given chunk, with the position,
+    // on the screen, of the origin of their coordinates.
+    fn ink_origins(&self, chunk: &RenderChunk) -> Vec<(usize, Vec2)> {
+        let ink = match self.strokes() {
+            Some(ink) if !ink.is_empty() => ink,
+            _ => return Vec::new(),
+        };
+        let page_origin = Vec2::from(chunk.position - chunk.frame.min);
+
+        if self.reflowable {
+            self.text.get(&chunk.location).map(|words| {
+                words.iter().filter_map(|word| match word.location {
+                    TextLocation::Dynamic(offset) if ink.contains_key(&offset) => {
+                        Some((offset, page_origin + word.rect.min * chunk.scale))
+                    },
+                    _ => None,
+                }).collect()
+            }).unwrap_or_default()
+        } else if ink.contains_key(&chunk.location) {
+            vec![(chunk.location, page_origin)]
+        } else {
+            Vec::new()
+        }
+    }
+
+    fn handle_pen(&mut self, status: FingerStatus, position: Point, time: f64, hub: &Hub, context: &Context) {
+        if status == FingerStatus::Down {
+            self.ink.pen_start = None;
+
+            if self.children.iter().any(|child| child.rect().includes(position)) {
+                return;
+            }
+
+            let chunk = match self.chunks.iter().find(|c| (c.frame - c.frame.min + c.position).includes(position)) {
+                Some(chunk) => chunk.clone(),
+                None => return,
+            };
+
+            self.ink.pen_start = Some(position);
+
+            if self.ink.eraser {
+                self.ink.history.push(InkAction::Erase(Vec::new()));
+                self.erase_ink(position, hub);
+                return;
+            }
+
+            let page_origin = Vec2::from(chunk.position - chunk.frame.min);
+            // The strokes of reflowable documents are anchored to the nearest word.
+            let anchor = if self.reflowable {
+                self.text.get(&chunk.location).and_then(|words| {
+                    words.iter().filter_map(|word| match word.location {
+                        TextLocation::Dynamic(offset) => {
+                            let rect = (word.rect * chunk.scale).to_rect() - chunk.frame.min + chunk.position;
+                            Some((position.rdist2(&rect), offset, page_origin + word.rect.min * chunk.scale))
+                        },
+                        _ => None,
+                    }).min_by_key(|(d, ..)| *d).map(|(_, offset, origin)| (offset, origin))
+                })
+            } else {
+                Some((chunk.location, page_origin))
+            };
+
+            if let Some((key, origin)) = anchor {
+                let pen = &context.settings.sketch.pen;
+                let radius = pen.size as f32 / 2.0;
+                let point = (Vec2::from(position) - origin) / chunk.scale;
+                self.ink.stroke = Some(PenStroke {
+                    key,
+                    origin,
+                    scale: chunk.scale,
+                    clip: chunk.frame - chunk.frame.min + chunk.position,
+                    stroke: Stroke { points: vec![[point.x, point.y, radius / chunk.scale]], color: pen.color },
+                    touch: TouchState::new(position, time, radius),
+                });
+            }
+
+            return;
+        }
+
+        if let Some(ps) = self.ink.stroke.as_mut() {
+            let (start_radius, end_radius) = segment_radii(&ps.touch, position, time, &context.settings.sketch.pen);
+            let point = (Vec2::from(position) - ps.origin) / ps.scale;
+            ps.stroke.points.push([point.x, point.y, end_radius / ps.scale]);
+            let rect = Rectangle::from_segment(ps.touch.pt, position,
+                                               start_radius.ceil() as i32,
+                                               end_radius.ceil() as i32);
+            if let Some(render_rect) = rect.intersection(&ps.clip) {
+                hub.send(Event::RenderNoWaitRegion(render_rect, UpdateMode::FastMono)).ok();
+            }
+            ps.touch.pt = position;
+            ps.touch.time = time;
+            ps.touch.radius = end_radius;
+        } else if self.ink.eraser && self.ink.pen_start.is_some() {
+            self.erase_ink(position, hub);
+        }
+
+        if status == FingerStatus::Up {
+            if let Some(ps) = self.ink.stroke.take() {
+                if let Some(ink) = self.strokes_mut() {
+                    ink.entry(ps.key).or_insert_with(Vec::new).push(ps.stroke);
+                    self.ink.history.push(InkAction::Draw(ps.key));
+                }
+            } else {
+                drop_empty_erase(&mut self.ink.history);
+            }
+        }
+    }
+
+    fn erase_ink(&mut self, position: Point, hub: &Hub) {
+        let distance = mm_to_px(ERASER_RADIUS_MM, CURRENT_DEVICE.dpi);
+        let targets: Vec<(usize, Vec2, f32)> = self.chunks.iter()
+            .filter(|c| (c.frame - c.frame.min + c.position).includes(position))
+            .flat_map(|c| self.ink_origins(c).into_iter().map(move |(key, origin)| (key, origin, c.scale)))
+            .collect();
+        let mut erased = Vec::new();
+        let mut render_rect: Option<Rectangle> = None;
+
+        if let Some(ink) = self.strokes_mut() {
+            for (key, origin, scale) in targets {
+                let p = (Vec2::from(position) - origin) / scale;
+                for entry in ink::erase_ink(ink, key, p, distance / scale) {
+                    if let Some(rect) = stroke_rect(&entry.2, origin, scale) {
+                        render_rect = Some(render_rect.map_or(rect, |mut rr| { rr.absorb(&rect); rr }));
+                    }
+                    erased.push(entry);
+                }
+            }
+        }
+
+        if let Some(InkAction::Erase(ref mut previous)) = self.ink.history.last_mut() {
+            previous.extend(erased);
+        }
+
+        if let Some(rect) = render_rect.and_then(|rect| rect.intersection(&self.rect)) {
+            hub.send(Event::RenderRegion(rect, UpdateMode::Gui)).ok();
+        }
+    }
+
+    fn undo_ink(&mut self, hub: &Hub) {
+        let action = match self.ink.history.pop() {
+            Some(action) => action,
+            None => return,
+        };
+
+        if let Some(ink) = self.strokes_mut() {
+            ink::undo_ink(ink, action);
+        }
+
+        hub.send(Event::RenderRegion(self.rect, UpdateMode::Gui)).ok();
+    }
+
+    // Returns the beginning of the text of the current page.
+    fn page_excerpt(&self) -> String {
+        let mut words_text = WordsText::default();
+        if let Some(words) = self.text.get(&self.current_page) {
+            for word in words {
+                if words_text.text.chars().count() >= BOOKMARK_EXCERPT_LENGTH {
+                    words_text.text.push('…');
+                    break;
+                }
+                words_text.push(&word.text, word.location);
+            }
+        }
+        words_text.text
+    }
+
+    // Returns the bookmark location of the current page.
+    // In reflow mode, bookmarks refer to the pages of the fixed-layout document.
+    fn bookmark_location(&self) -> usize {
+        if self.fixed_doc.is_some() {
+            self.saved_location().0
+        } else {
+            self.current_page
+        }
+    }
+
+    // Returns the location, in the current document, of the page of the given bookmark.
+    fn bookmark_target(&self, location: usize) -> Option<usize> {
+        if self.fixed_doc.is_some() {
+            let mut doc = self.doc.lock().unwrap();
+            doc.resolve_location(Location::Uri(format!("#page-{}", location)))
+               .and_then(|offset| doc.resolve_location(Location::Exact(offset)))
+        } else {
+            Some(location)
+        }
+    }
+
     fn toggle_bookmark(&mut self, hub: &Hub) {
+        let text = self.page_excerpt();
+        let location = self.bookmark_location();
         if let Some(ref mut r) = self.info.reader {
-            if !r.bookmarks.insert(self.current_page) {
-                r.bookmarks.remove(&self.current_page);
-            }
+            r.toggle_bookmark(location, text);
         }
         let dpi = CURRENT_DEVICE.dpi;
         let thickness = scale_by_dpi(3.0, dpi) as u16;
@@ -2141,37 +3582,298 @@ impl Reader {
         hub.send(Event::RenderRegion(rect, UpdateMode::Gui)).ok();
     }
 
-    fn set_contrast_exponent(&mut self, exponent: f32, hub: &Hub, context: &mut Context) {
-        if let Some(ref mut r) = self.info.reader {
-            r.contrast_exponent = Some(exponent);
+    fn set_contrast_exponent(&mut self, exponent: f32, hub: &Hub, context: &mut Context) {
+        if let Some(ref mut r) = self.info.reader {
+            r.contrast_exponent = Some(exponent);
+        }
+        self.contrast.exponent = exponent;
+        self.update(None, hub, context);
+        self.update_tool_bar(hub, context);
+    }
+
+    fn set_contrast_gray(&mut self, gray: f32, hub: &Hub, context: &mut Context) {
+        if let Some(ref mut r) = self.info.reader {
+            r.contrast_gray = Some(gray);
+        }
+        self.contrast.gray = gray;
+        self.update(None, hub, context);
+        self.update_tool_bar(hub, context);
+    }
+
+    fn set_dithering(&mut self, dithering: Option<DitherMode>, hub: &Hub, context: &Context) {
+        if Arc::strong_count(&self.doc) > 1 {
+            return;
+        }
+
+        if let Some(r) = self.info.reader.as_mut() {
+            r.dithering = dithering;
+        }
+
+        let gray_levels = self.gray_levels;
+        self.doc.lock().unwrap().set_dithering(dithering.map(|d| (d, gray_levels)));
+        self.clear_cache();
+        self.update(None, hub, context);
+    }
+
+    fn toggle_enhancement(&mut self, id: EntryId, hub: &Hub, context: &Context) {
+        if let Some(r) = self.info.reader.as_mut() {
+            let value = match id {
+                EntryId::ToggleDeskew => &mut r.deskew,
+                EntryId::ToggleDespeckle => &mut r.despeckle,
+                EntryId::ToggleAutoLevels => &mut r.auto_levels,
+                _ => return,
+            };
+            *value = if *value == Some(true) { None } else { Some(true) };
+        }
+        self.clear_cache();
+        self.update(None, hub, context);
+    }
+
+    // Builds the reflowable version of the document in the background.
+    // The fixed-layout document is shown until it's ready.
+    fn start_reflow(&mut self, hub: &Hub, context: &mut Context) {
+        if self.reflow_job.is_some() {
+            return;
+        }
+
+        let running = Arc::new(AtomicBool::new(true));
+        let result = Arc::new(Mutex::new(None));
+        let running2 = Arc::clone(&running);
+        let result2 = Arc::clone(&result);
+        let doc2 = Arc::clone(&self.doc);
+        let hub2 = hub.clone();
+
+        thread::spawn(move || {
+            if let Some(reflow_doc) = ReflowDocument::new(&doc2, &running2) {
+                *result2.lock().unwrap() = Some(reflow_doc);
+                hub2.send(Event::Reflowed).ok();
+            }
+        });
+
+        self.reflow_job = Some(ReflowJob { running, result });
+
+        let notif = Notification::new(ViewId::MessageNotif,
+                                      "Reflowing the document.".to_string(),
+                                      hub,
+                                      context);
+        self.children.push(Box::new(notif) as Box<dyn View>);
+    }
+
+    fn finish_reflow(&mut self, hub: &Hub, context: &mut Context) {
+        let reflow_doc = self.reflow_job.as_ref()
+                             .and_then(|job| job.result.lock().unwrap().take());
+
+        if let Some(reflow_doc) = reflow_doc {
+            self.reflow_job = None;
+            let (current_page, _) = self.saved_location();
+            let doc = Arc::new(Mutex::new(Box::new(reflow_doc) as Box<dyn Document>));
+            self.fixed_doc = Some(mem::replace(&mut self.doc, doc));
+            // The strokes of the fixed-layout pages can't be drawn on the reflowed ones.
+            self.ink.enabled = false;
+            self.ink.stroke = None;
+            self.ink.history.clear();
+            self.reload(current_page, hub, context);
+        }
+    }
+
+    fn cancel_reflow(&mut self) {
+        if let Some(job) = self.reflow_job.take() {
+            job.running.store(false, AtomicOrdering::Relaxed);
+        }
+    }
+
+    fn toggle_reflow(&mut self, hub: &Hub, context: &mut Context) {
+        if self.reflow_job.is_some() {
+            self.cancel_reflow();
+            if let Some(ref mut r) = self.info.reader {
+                r.reflow = None;
+            }
+            return;
+        }
+
+        if self.fixed_doc.is_none() {
+            if let Some(ref mut r) = self.info.reader {
+                r.reflow = Some(true);
+            }
+            self.start_reflow(hub, context);
+            return;
+        }
+
+        if Arc::strong_count(&self.doc) > 1 {
+            return;
+        }
+
+        let (current_page, _) = self.saved_location();
+
+        if let Some(doc) = self.fixed_doc.take() {
+            self.doc = doc;
+        }
+
+        if let Some(ref mut r) = self.info.reader {
+            r.reflow = None;
+        }
+
+        self.reload(current_page, hub, context);
+    }
+
+    // Loads the document anew, at the given location of the fixed-layout document.
+    fn reload(&mut self, current_page: usize, hub: &Hub, context: &mut Context) {
+        if let Some(ref mut r) = self.info.reader {
+            r.current_page = current_page;
+        }
+
+        self.toggle_bars(Some(false), hub, context);
+        self.clear_cache();
+        self.text.clear();
+        self.selection = None;
+        self.target_annotation = None;
+        self.history.clear();
+
+        if self.load(hub, context).is_some() {
+            self.update(Some(UpdateMode::Full), hub, context);
+        }
+    }
+
+    // Returns the current page and the pages count to save in the reading state.
+    // In reflow mode, they refer to the fixed-layout document.
+    fn saved_location(&self) -> (usize, usize) {
+        if let Some(ref fixed_doc) = self.fixed_doc {
+            let current_page = self.text.get(&self.current_page)
+                                   .and_then(|words| words.iter().find_map(|w| match w.location {
+                                       TextLocation::Static(page, _) => Some(page),
+                                       _ => None,
+                                   }))
+                                   .or_else(|| self.info.reader.as_ref().map(|r| r.current_page))
+                                   .unwrap_or(0);
+            let pages_count = fixed_doc.lock().map(|doc| doc.pages_count()).unwrap_or(self.pages_count);
+            (current_page, pages_count)
+        } else {
+            let current_page = self.first_visible_offset().unwrap_or(self.current_page);
+            (current_page, self.pages_count)
+        }
+    }
+
+    fn set_zoom_mode(&mut self, zoom_mode: ZoomMode, hub: &Hub, context: &Context) {
+        if self.view_port.zoom_mode == zoom_mode {
+            return;
+        }
+        self.view_port.zoom_mode = zoom_mode;
+        self.view_port.top_offset = 0;
+        self.view_port.left_offset = 0;
+        if self.reflowable {
+            if let Some(r) = self.info.reader.as_mut() {
+                r.continuous = if zoom_mode == ZoomMode::FitToWidth { Some(true) } else { None };
+            }
+        }
+        self.view_port.column_index = 0;
+        self.clear_cache();
+        self.update(None, hub, context);
+    }
+
+    fn fit_to_width_scale(&self, location: usize) -> f32 {
+        let cropping_margin = self.info.reader.as_ref()
+                                  .and_then(|r| r.cropping_margins.as_ref()
+                                                 .map(|c| c.margin(location)))
+                                  .cloned().unwrap_or_default();
+        let dims = self.doc.lock().unwrap().dims(location).unwrap();
+        scaling_factor(&self.rect, &cropping_margin, self.view_port.margin_width, dims, ZoomMode::FitToWidth)
+    }
+
+    // Multiplies the current scale by the given ratio, keeping the point
+    // of the page under the given center in place.
+    fn zoom(&mut self, ratio: f32, center: Point, hub: &Hub, context: &Context) {
+        let chunk = match self.chunks.iter().find(|c| (c.frame - c.frame.min + c.position).includes(center))
+                                            .or_else(|| self.chunks.first()).cloned() {
+            Some(chunk) => chunk,
+            None => return,
+        };
+
+        let width_scale = self.fit_to_width_scale(chunk.location);
+        let zoom_factor = ((ratio * chunk.scale / width_scale).max(MIN_ZOOM_FACTOR).min(MAX_ZOOM_FACTOR) * ZOOM_STEPS).round() / ZOOM_STEPS;
+
+        if self.view_port.zoom_mode == ZoomMode::Custom &&
+           (zoom_factor - self.view_port.zoom_factor).abs() < f32::EPSILON {
+            return;
         }
-        self.contrast.exponent = exponent;
+
+        let ratio = zoom_factor * width_scale / chunk.scale;
+        let frame = self.cache[&self.cache_key(chunk.location)].frame;
+        let smw = self.view_port.margin_width;
+        let point = center - chunk.position + chunk.frame.min - frame.min;
+
+        self.view_port.zoom_mode = ZoomMode::Custom;
+        self.view_port.zoom_factor = zoom_factor;
+        self.view_port.left_offset = (point.x as f32 * ratio) as i32 - (center.x - smw);
+        self.view_port.top_offset = (point.y as f32 * ratio) as i32 - (center.y - smw);
+        self.view_port.column_index = 0;
+        self.current_page = chunk.location;
+        // The pages rendered at other zoom factors stay in the cache.
+        self.render_generation.fetch_add(1, AtomicOrdering::Relaxed);
+        self.prefetched.lock().unwrap().clear();
         self.update(None, hub, context);
-        self.update_tool_bar(hub, context);
     }
 
-    fn set_contrast_gray(&mut self, gray: f32, hub: &Hub, context: &mut Context) {
-        if let Some(ref mut r) = self.info.reader {
-            r.contrast_gray = Some(gray);
-        }
-        self.contrast.gray = gray;
+    fn pan(&mut self, delta: Point, hub: &Hub, context: &Context) {
+        self.view_port.left_offset -= delta.x;
+        self.view_port.top_offset -= delta.y;
         self.update(None, hub, context);
-        self.update_tool_bar(hub, context);
     }
 
-    fn set_zoom_mode(&mut self, zoom_mode: ZoomMode, hub: &Hub, context: &Context) {
-        if self.view_port.zoom_mode == zoom_mode {
-            return;
-        }
-        self.view_port.zoom_mode = zoom_mode;
-        self.view_port.top_offset = 0;
-        self.cache.clear();
-        self.update(None, hub, context);
+    fn save_annotated_document(&mut self, hub: &Hub, context: &Context) {
+        let annotations = self.info.reader.as_ref()
+                              .map(|r| r.annotations.clone())
+                              .unwrap_or_default();
+        let password = self.password.clone()
+                           .or_else(|| self.info.reader.as_ref().and_then(|r| r.password.clone()));
+        let path = context.library.home.join(&self.info.file.path);
+        // The library needs to follow the fingerprint of a document saved in place.
+        let in_place = if context.settings.reader.annotate_in_place {
+            context.library.fingerprint(&self.info.file.path)
+                   .map(|fp| (self.info.file.path.clone(), fp))
+        } else {
+            None
+        };
+        let target = if context.settings.reader.annotate_in_place {
+            path.clone()
+        } else {
+            context.library.home
+                   .join(&context.settings.reader.annotated_path)
+                   .join(&self.info.file.path)
+        };
+        let hub2 = hub.clone();
+
+        thread::spawn(move || {
+            let msg = match write_annotations(&path, &target, &annotations, password.as_deref()) {
+                Ok(()) => {
+                    if let Some((path, fp)) = in_place {
+                        hub2.send(Event::UpdateFingerprint(path, fp)).ok();
+                    }
+                    format!("Saved {}.", target.file_name().unwrap_or_default().to_string_lossy())
+                },
+                Err(e) => format!("{}", e),
+            };
+            hub2.send(Event::Notify(msg)).ok();
+        });
+    }
+
+    fn export_annotations(&self, format: ExportFormat, hub: &Hub, context: &Context) {
+        let home = context.library.home.clone();
+        let dir = home.join(&context.settings.export.path);
+        let info = self.info.clone();
+        let hub2 = hub.clone();
+
+        thread::spawn(move || {
+            let msg = match export_annotations(&home, &info, format, &dir) {
+                Ok(target) => format!("Exported {}.", target.file_name().unwrap_or_default().to_string_lossy()),
+                Err(e) => format!("{}", e),
+            };
+            hub2.send(Event::Notify(msg)).ok();
+        });
     }
 
     fn crop_margins(&mut self, index: usize, margin: &Margin, hub: &Hub, context: &Context) {
         if self.view_port.zoom_mode == ZoomMode::FitToWidth {
-            let Resource { pixmap, frame, .. } = self.cache.get(&index).unwrap();
+            let Resource { pixmap, frame, .. } = &self.cache[&self.cache_key(index)];
             let ratio = (frame.min.y + self.view_port.top_offset) as f32 / pixmap.height as f32;
             if ratio >= margin.top && ratio <= (1.0 - margin.bottom) {
                 let dims = {
@@ -2183,6 +3885,11 @@ impl Reader {
             } else {
                 self.view_port.top_offset = 0;
             }
+        } else if self.view_port.zoom_mode == ZoomMode::FitToColumn ||
+                  self.view_port.zoom_mode == ZoomMode::Custom {
+            self.view_port.top_offset = 0;
+            self.view_port.left_offset = 0;
+            self.view_port.column_index = 0;
         }
         if let Some(r) = self.info.reader.as_mut() {
             if r.cropping_margins.is_none() {
@@ -2192,7 +3899,35 @@ impl Reader {
                 *c.margin_mut(index) = margin.clone();
             }
         }
-        self.cache.clear();
+        self.clear_cache();
+        self.update(None, hub, context);
+    }
+
+    // Analyzes, in a background thread, the ink of a sample of pages and crops the margins
+    // that are blank on all of them, for each parity of pages or for all the pages.
+    fn auto_crop_margins(&self, scheme: PageScheme, hub: &Hub) {
+        let hub2 = hub.clone();
+        let doc2 = Arc::clone(&self.doc);
+
+        thread::spawn(move || {
+            if let Some(cropping_margins) = detect_margins(&doc2, scheme) {
+                hub2.send(Event::AutoCroppedMargins(Box::new(cropping_margins))).ok();
+            } else {
+                hub2.send(Event::Notify("No margins were detected.".to_string())).ok();
+            }
+        });
+    }
+
+    fn set_cropping_margins(&mut self, cropping_margins: CroppingMargins, hub: &Hub, context: &mut Context) {
+        if let Some(r) = self.info.reader.as_mut() {
+            r.cropping_margins = Some(cropping_margins);
+        }
+
+        self.toggle_margin_cropper(false, hub, context);
+        self.view_port.top_offset = 0;
+        self.view_port.left_offset = 0;
+        self.view_port.column_index = 0;
+        self.clear_cache();
         self.update(None, hub, context);
     }
 
@@ -2260,9 +3995,21 @@ impl Reader {
             } else {
                 None
             }
+        }).or_else(|| {
+            self.page_labels.iter()
+                .find(|(_, label)| *label == name)
+                .or_else(|| self.page_labels.iter().find(|(_, label)| label.eq_ignore_ascii_case(name)))
+                .map(|(index, _)| *index)
         })
     }
 
+    // User defined names take priority over the page labels of the document.
+    fn page_name(&self, index: usize) -> Option<String> {
+        self.info.reader.as_ref()
+            .and_then(|r| r.page_name(index))
+            .or_else(|| self.page_labels.get(&index).cloned())
+    }
+
     fn text_excerpt(&self, sel: [TextLocation; 2]) -> Option<String> {
         let [start, end] = sel;
         let parts = self.text.values().flatten()
@@ -2287,6 +4034,30 @@ impl Reader {
         Some(text)
     }
 
+    // Returns the text before and after the given selection, within the visible pages.
+    fn text_context(&self, sel: [TextLocation; 2]) -> (String, String) {
+        let [start, end] = sel;
+        let mut before = WordsText::default();
+        let mut after = WordsText::default();
+
+        for bnd in self.chunks.iter().filter_map(|chunk| self.text.get(&chunk.location)).flatten() {
+            if bnd.location < start {
+                before.push(&bnd.text, bnd.location);
+            } else if bnd.location > end {
+                after.push(&bnd.text, bnd.location);
+            }
+        }
+
+        let count = before.text.chars().count();
+        let prefix = before.text.chars().skip(count.saturating_sub(QUOTE_CONTEXT_LENGTH)).collect::<String>();
+        let suffix = after.text.chars().take(QUOTE_CONTEXT_LENGTH).collect::<String>();
+        let separator = |text: &str| if text.is_empty() || text.ends_with('-') { "" } else { " " };
+
+        (format!("{}{}", prefix, separator(&prefix)),
+         format!("{}{}", separator(&self.text_excerpt(sel).unwrap_or_default()), suffix)
+             .trim_end().to_string())
+    }
+
     fn selected_text(&self) -> Option<String> {
         self.selection.as_ref().and_then(|sel| self.text_excerpt([sel.start, sel.end]))
     }
@@ -2366,21 +4137,43 @@ impl Reader {
             s.running.store(false, AtomicOrdering::Relaxed);
         }
 
-        if self.ephemeral {
+        self.cancel_reflow();
+
+        if self.ephemeral || self.locked {
             return;
         }
 
+        if self.reflowable && self.fixed_doc.is_none() {
+            if let Some(path) = context.library.pagination_path(&self.info.file.path) {
+                self.doc.lock().unwrap().save_pagination(&path);
+            }
+        }
+
+        let (current_page, pages_count) = self.saved_location();
+
         if let Some(ref mut r) = self.info.reader {
-            r.current_page = self.current_page;
-            r.pages_count = self.pages_count;
+            r.current_page = current_page;
+            r.pages_count = pages_count;
             r.finished = self.finished;
 
-            if self.view_port.zoom_mode == ZoomMode::FitToPage {
-                r.zoom_mode = None;
-                r.top_offset = None;
-            } else {
-                r.zoom_mode = Some(self.view_port.zoom_mode);
-                r.top_offset = Some(self.view_port.top_offset);
+            // Reflowable documents keep the zoom mode of the fixed-layout document.
+            if !self.reflowable {
+                if self.view_port.zoom_mode == ZoomMode::FitToPage {
+                    r.zoom_mode = None;
+                    r.top_offset = None;
+                    r.left_offset = None;
+                    r.zoom_factor = None;
+                } else {
+                    r.zoom_mode = Some(self.view_port.zoom_mode);
+                    r.top_offset = Some(self.view_port.top_offset);
+                    if self.view_port.zoom_mode == ZoomMode::Custom {
+                        r.left_offset = Some(self.view_port.left_offset);
+                        r.zoom_factor = Some(self.view_port.zoom_factor);
+                    } else {
+                        r.left_offset = None;
+                        r.zoom_factor = None;
+                    }
+                }
             }
 
             r.rotation = Some(CURRENT_DEVICE.to_canonical(context.display.rotation));
@@ -2404,14 +4197,84 @@ impl Reader {
 
 impl View for Reader {
     fn handle_event(&mut self, evt: &Event, hub: &Hub, _bus: &mut Bus, context: &mut Context) -> bool {
+        if self.locked {
+            return self.handle_locked_event(evt, hub, context);
+        }
+
         let prev_current_page = self.current_page;
         let ret = match *evt {
+            Event::Device(DeviceEvent::Finger { id: PEN_SLOT, status, position, time }) if self.ink.enabled &&
+                                                                                            self.fixed_doc.is_none() => {
+                self.handle_pen(status, position, time, hub, context);
+                true
+            },
+            Event::Gesture(GestureEvent::HoldFingerShort(_, PEN_SLOT)) |
+            Event::Gesture(GestureEvent::HoldFingerLong(_, PEN_SLOT)) if self.ink.enabled ||
+                                                                        self.state == State::Selection(PEN_SLOT) => true,
+            Event::Gesture(GestureEvent::Tap(start)) |
+            Event::Gesture(GestureEvent::Swipe { start, .. }) |
+            Event::Gesture(GestureEvent::Arrow { start, .. }) |
+            Event::Gesture(GestureEvent::Corner { start, .. }) if self.ink.pen_start == Some(start) => {
+                self.ink.pen_start = None;
+                true
+            },
+            Event::Device(DeviceEvent::Pen { status: PenStatus::Down, tool: PenTool::Pen, position, time, button, .. })
+                    if !self.ink.enabled && self.state == State::Idle && self.focus.is_none() &&
+                       context.settings.reader.pen_selection_button.map_or(true, |b| button == Some(b)) => {
+                let mut found = None;
+                let mut dmin = u32::MAX;
+                let dmax = (scale_by_dpi(RECT_DIST_JITTER, CURRENT_DEVICE.dpi) as i32).pow(2) as u32;
+
+                for chunk in &self.chunks {
+                    for word in &self.text[&chunk.location] {
+                        let rect = (word.rect * chunk.scale).to_rect() - chunk.frame.min + chunk.position;
+                        let d = position.rdist2(&rect);
+                        if d < dmax && d < dmin {
+                            dmin = d;
+                            found = Some((word.location, rect));
+                        }
+                    }
+                }
+
+                self.pen_press = found.map(|(anchor, rect)| PenPress { anchor, rect, position, time });
+
+                true
+            },
+            // Plain pen taps are left to the gesture handlers.
+            Event::Device(DeviceEvent::Finger { id: PEN_SLOT, status: FingerStatus::Motion, position, .. })
+                    if self.pen_press.map_or(false, |press| (position - press.position).length() >
+                                                            scale_by_dpi(RECT_DIST_JITTER, CURRENT_DEVICE.dpi)) => {
+                if let Some(PenPress { anchor, rect, position, time }) = self.pen_press.take() {
+                    if let Some(rect) = self.selection_rect() {
+                        hub.send(Event::RenderRegion(rect, UpdateMode::Gui)).ok();
+                    }
+                    self.selection = Some(Selection {
+                        start: anchor,
+                        end: anchor,
+                        anchor,
+                        pen: Some((position, time)),
+                    });
+                    self.state = State::Selection(PEN_SLOT);
+                    self.ink.pen_start = Some(position);
+                    hub.send(Event::RenderRegion(rect, UpdateMode::Fast)).ok();
+                }
+                true
+            },
+            Event::Device(DeviceEvent::Finger { id: PEN_SLOT, status: FingerStatus::Up, .. }) if self.pen_press.is_some() => {
+                self.pen_press = None;
+                false
+            },
             Event::Gesture(GestureEvent::Rotate { quarter_turns, .. }) if quarter_turns != 0 => {
                 let (_, dir) = CURRENT_DEVICE.mirroring_scheme();
                 let n = (4 + (context.display.rotation - dir * quarter_turns)) % 4;
                 hub.send(Event::Select(EntryId::Rotate(n))).ok();
                 true
             },
+            Event::Gesture(GestureEvent::Swipe { start, end, .. }) if self.rect.includes(start) &&
+                                                                     self.view_port.zoom_mode == ZoomMode::Custom => {
+                self.pan(end - start, hub, context);
+                true
+            },
             Event::Gesture(GestureEvent::Swipe { dir, start, end, .. }) if self.rect.includes(start) => {
                 match dir {
                     Dir::West => self.go_to_neighbor(CycleDir::Next, hub, context),
@@ -2420,16 +4283,24 @@ impl View for Reader {
                 };
                 true
             },
-            Event::Gesture(GestureEvent::Spread { axis: Axis::Horizontal, starts, .. }) if self.rect.includes(starts[0]) => {
-                if !self.reflowable {
-                    self.set_zoom_mode(ZoomMode::FitToWidth, hub, context);
-                }
+            Event::Gesture(GestureEvent::Spread { axis: Axis::Horizontal, starts, .. }) if self.rect.includes(starts[0]) &&
+                                                                                            self.view_port.zoom_mode != ZoomMode::Custom => {
+                self.set_zoom_mode(ZoomMode::FitToWidth, hub, context);
                 true
 
             },
-            Event::Gesture(GestureEvent::Pinch { axis: Axis::Horizontal, starts, .. }) if self.rect.includes(starts[0]) => {
-                if !self.reflowable {
-                    self.set_zoom_mode(ZoomMode::FitToPage, hub, context);
+            Event::Gesture(GestureEvent::Pinch { axis: Axis::Horizontal, starts, .. }) if self.rect.includes(starts[0]) &&
+                                                                                           self.view_port.zoom_mode != ZoomMode::Custom => {
+                self.set_zoom_mode(ZoomMode::FitToPage, hub, context);
+                true
+            },
+            // Once zoomed in freely, spreading and pinching along both axes change the zoom factor.
+            Event::Gesture(GestureEvent::Spread { starts, ends, .. }) |
+            Event::Gesture(GestureEvent::Pinch { starts, ends, .. }) if self.rect.includes(starts[0]) => {
+                let start_distance = (starts[1] - starts[0]).length();
+                if !self.reflowable && start_distance > 0.0 {
+                    let ratio = (ends[1] - ends[0]).length() / start_distance;
+                    self.zoom(ratio, (ends[0] + ends[1]) / 2, hub, context);
                 }
                 true
             },
@@ -2507,17 +4378,21 @@ impl View for Reader {
                 if !self.held_buttons.remove(&code) {
                     match code {
                         ButtonCode::Backward => {
-                            if self.search.is_none() {
-                                self.go_to_neighbor(CycleDir::Previous, hub, context);
-                            } else {
+                            if self.search.is_some() {
                                 self.go_to_results_neighbor(CycleDir::Previous, hub, context);
+                            } else if self.view_port.zoom_mode == ZoomMode::FitToWidth {
+                                self.button_scroll(CycleDir::Previous, hub, context);
+                            } else {
+                                self.go_to_neighbor(CycleDir::Previous, hub, context);
                             }
                         },
                         ButtonCode::Forward => {
-                            if self.search.is_none() {
-                                self.go_to_neighbor(CycleDir::Next, hub, context);
-                            } else {
+                            if self.search.is_some() {
                                 self.go_to_results_neighbor(CycleDir::Next, hub, context);
+                            } else if self.view_port.zoom_mode == ZoomMode::FitToWidth {
+                                self.button_scroll(CycleDir::Next, hub, context);
+                            } else {
+                                self.go_to_neighbor(CycleDir::Next, hub, context);
                             }
                         },
                         _ => (),
@@ -2616,6 +4491,22 @@ impl View for Reader {
                 }
                 true
             },
+            Event::Device(DeviceEvent::Finger { status: FingerStatus::Up, position, id: PEN_SLOT, time }) if self.state == State::Selection(PEN_SLOT) => {
+                self.state = State::Idle;
+                let min_length = scale_by_dpi(RECT_DIST_JITTER, CURRENT_DEVICE.dpi) as i32;
+                let strike = self.selection.as_ref().and_then(|sel| sel.pen).map_or(false, |(start, start_time)| {
+                    let d = position - start;
+                    time - start_time < STRIKE_MAX_DURATION && d.x.abs() >= min_length &&
+                    d.y.abs() as f32 <= STRIKE_MAX_SLOPE * d.x.abs() as f32
+                });
+                if strike {
+                    hub.send(Event::Select(EntryId::HighlightSelection(HighlightStyle::default()))).ok();
+                } else {
+                    let radius = scale_by_dpi(24.0, CURRENT_DEVICE.dpi) as i32;
+                    self.toggle_selection_menu(Rectangle::from_disk(position, radius), Some(true), hub, context);
+                }
+                true
+            },
             Event::Device(DeviceEvent::Finger { status: FingerStatus::Up, position, id, .. }) if self.state == State::Selection(id) => {
                 self.state = State::Idle;
                 let radius = scale_by_dpi(24.0, CURRENT_DEVICE.dpi) as i32;
@@ -2738,6 +4629,17 @@ impl View for Reader {
                     return true;
                 }
 
+                if let Some(note_box) = self.note_boxes(&mut context.fonts).into_iter()
+                                            .find(|nb| nb.rect.includes(center)) {
+                    if !self.expanded_notes.remove(&note_box.selection) {
+                        self.expanded_notes.insert(note_box.selection);
+                    }
+                    let notes_rect = rect![note_box.rect.min.x, self.rect.min.y,
+                                           self.rect.max.x, self.rect.max.y];
+                    hub.send(Event::RenderRegion(notes_rect, UpdateMode::Gui)).ok();
+                    return true;
+                }
+
                 let mut nearest_link = None;
                 let mut dmin = u32::MAX;
                 let dmax = (scale_by_dpi(RECT_DIST_JITTER, CURRENT_DEVICE.dpi) as i32).pow(2) as u32;
@@ -2891,6 +4793,7 @@ impl View for Reader {
                             start: anchor,
                             end: anchor,
                             anchor,
+                            pen: None,
                         });
                         self.state = State::Selection(id);
                         hub.send(Event::RenderRegion(rect, UpdateMode::Fast)).ok();
@@ -2914,7 +4817,7 @@ impl View for Reader {
                 true
             },
             Event::LoadPixmap(location) => {
-                self.load_pixmap(location);
+                self.load_prefetched_pixmap(location);
                 true
             },
             Event::Submit(ViewId::GoToPageInput, ref text) => {
@@ -2946,6 +4849,9 @@ impl View for Reader {
                                 (number * BYTES_PER_PAGE).max(0.0).round() as usize
                             };
                             self.go_to_page(location, true, hub, context);
+                        } else if let Some(location) = self.page_labels.values().find(|label| *label == text)
+                                                           .and_then(|_| self.find_page_by_name(text)) {
+                            self.go_to_page(location, true, hub, context);
                         }
                     }
                 }
@@ -2962,6 +4868,7 @@ impl View for Reader {
                     if let Some(ref mut r) = self.info.reader {
                         r.page_names.insert(self.current_page, text.to_string());
                     }
+                    self.update_bottom_bar(hub);
                 }
                 self.toggle_keyboard(false, None, hub, context);
                 true
@@ -2971,11 +4878,16 @@ impl View for Reader {
 
                 if let Some(sel) = selection {
                     let text = self.text_excerpt(sel).unwrap();
+                    let (prefix, suffix) = self.text_context(sel);
                     self.info.reader.as_mut().map(|r| {
                         r.annotations.push(Annotation {
                             selection: sel,
                             note: note.to_string(),
                             text,
+                            prefix,
+                            suffix,
+                            style: HighlightStyle::default(),
+                            orphan: false,
                             modified: Local::now(),
                         });
                     });
@@ -3023,6 +4935,12 @@ impl View for Reader {
                 self.go_to_page(location, true, hub, context);
                 true
             },
+            Event::GoToBookmark(location) => {
+                if let Some(location) = self.bookmark_target(location) {
+                    self.go_to_page(location, true, hub, context);
+                }
+                true
+            },
             Event::GoToLocation(ref location) => {
                 let offset_opt = {
                     let mut doc = self.doc.lock().unwrap();
@@ -3244,14 +5162,19 @@ impl View for Reader {
                 self.toggle_edit_note(None, Some(true), hub, context);
                 true
             },
-            Event::Select(EntryId::HighlightSelection) => {
+            Event::Select(EntryId::HighlightSelection(style)) => {
                 if let Some(sel) = self.selection.take() {
                     let text = self.text_excerpt([sel.start, sel.end]).unwrap();
+                    let (prefix, suffix) = self.text_context([sel.start, sel.end]);
                     self.info.reader.as_mut().map(|r| {
                         r.annotations.push(Annotation {
                             selection: [sel.start, sel.end],
                             note: String::new(),
                             text,
+                            prefix,
+                            suffix,
+                            style,
+                            orphan: false,
                             modified: Local::now(),
                         });
                     });
@@ -3331,6 +5254,17 @@ impl View for Reader {
                 }
                 true
             },
+            Event::Select(EntryId::SetAnnotationStyle(sel, style)) => {
+                if let Some(annot) = self.find_annotation_mut(sel) {
+                    annot.style = style;
+                    annot.modified = Local::now();
+                    self.update_annotations();
+                }
+                if let Some(rect) = self.text_rect(sel) {
+                    hub.send(Event::RenderRegion(rect, UpdateMode::Gui)).ok();
+                }
+                true
+            },
             Event::Select(EntryId::RemoveAnnotation(sel)) => {
                 if let Some(annotations) = self.info.reader.as_mut().map(|r| &mut r.annotations) {
                     annotations.retain(|annot| annot.selection[0] != sel[0] || annot.selection[1] != sel[1]); 
@@ -3341,6 +5275,131 @@ impl View for Reader {
                 }
                 true
             },
+            Event::Select(EntryId::SaveAnnotatedDocument) => {
+                self.save_annotated_document(hub, context);
+                true
+            },
+            Event::Select(EntryId::ExportAnnotations(format)) => {
+                self.export_annotations(format, hub, context);
+                true
+            },
+            Event::ReanchorAnnotations(ref results) => {
+                let mut orphans = 0;
+                if let Some(r) = self.info.reader.as_mut() {
+                    for (old, new) in results {
+                        if let Some(annot) = r.annotations.iter_mut().find(|annot| annot.selection == *old) {
+                            if let Some(sel) = new {
+                                annot.selection = *sel;
+                                annot.orphan = false;
+                            } else {
+                                if !annot.orphan {
+                                    orphans += 1;
+                                }
+                                annot.orphan = true;
+                            }
+                        }
+                    }
+                    r.reanchor = false;
+                }
+                self.update_annotations();
+                hub.send(Event::RenderRegion(self.rect, UpdateMode::Gui)).ok();
+                if orphans > 0 {
+                    let msg = if orphans == 1 {
+                        "An annotation couldn't be found in the document.".to_string()
+                    } else {
+                        format!("{} annotations couldn't be found in the document.", orphans)
+                    };
+                    hub.send(Event::Notify(msg)).ok();
+                }
+                true
+            },
+            Event::Select(EntryId::ShowAnnotations) => {
+                hub.send(Event::OpenAnnotations(Box::new(self.info.clone()))).ok();
+                true
+            },
+            Event::Select(EntryId::ShowBookmarks) => {
+                hub.send(Event::OpenBookmarks(Box::new(self.info.clone()))).ok();
+                true
+            },
+            Event::UpdateBookmarks(ref bookmarks) => {
+                if let Some(r) = self.info.reader.as_mut() {
+                    r.bookmarks = bookmarks.clone();
+                }
+                hub.send(Event::RenderRegion(self.rect, UpdateMode::Gui)).ok();
+                true
+            },
+            Event::UpdateAnnotations(ref annotations) => {
+                if let Some(r) = self.info.reader.as_mut() {
+                    r.annotations = annotations.clone();
+                }
+                self.update_annotations();
+                hub.send(Event::RenderRegion(self.rect, UpdateMode::Gui)).ok();
+                true
+            },
+            Event::Select(ref id @ EntryId::ToggleDeskew) |
+            Event::Select(ref id @ EntryId::ToggleDespeckle) |
+            Event::Select(ref id @ EntryId::ToggleAutoLevels) => {
+                self.toggle_enhancement(id.clone(), hub, context);
+                true
+            },
+            Event::Select(EntryId::SetDithering(dithering)) => {
+                self.set_dithering(dithering, hub, context);
+                true
+            },
+            Event::Select(EntryId::ToggleCoverAlone) => {
+                let cover_alone = self.cover_alone();
+                if let Some(r) = self.info.reader.as_mut() {
+                    r.cover_alone = if cover_alone { None } else { Some(true) };
+                }
+                self.update(None, hub, context);
+                true
+            },
+            Event::Select(EntryId::ToggleMarginNotes) => {
+                self.toggle_margin_notes(hub, context);
+                true
+            },
+            Event::Select(EntryId::ToggleContinuous) => {
+                let zoom_mode = if self.view_port.zoom_mode == ZoomMode::FitToWidth {
+                    ZoomMode::FitToPage
+                } else {
+                    ZoomMode::FitToWidth
+                };
+                self.set_zoom_mode(zoom_mode, hub, context);
+                true
+            },
+            Event::Select(EntryId::ToggleInk) => {
+                self.ink.enabled = !self.ink.enabled;
+                self.ink.stroke = None;
+                if self.ink.enabled && !self.ink.visible {
+                    self.ink.visible = true;
+                    hub.send(Event::RenderRegion(self.rect, UpdateMode::Gui)).ok();
+                }
+                true
+            },
+            Event::Select(EntryId::ToggleInkEraser) => {
+                self.ink.eraser = !self.ink.eraser;
+                true
+            },
+            Event::Select(EntryId::ToggleInkVisibility) => {
+                self.ink.visible = !self.ink.visible;
+                if !self.ink.visible {
+                    self.ink.enabled = false;
+                }
+                hub.send(Event::RenderRegion(self.rect, UpdateMode::Gui)).ok();
+                true
+            },
+            Event::Select(EntryId::UndoInk) => {
+                self.undo_ink(hub);
+                true
+            },
+            Event::Select(EntryId::ToggleReflow) => {
+                self.toggle_reflow(hub, context);
+                true
+            },
+            Event::Reflowed => {
+                self.finish_reflow(hub, context);
+                true
+            },
             Event::Select(EntryId::SetZoomMode(zoom_mode)) => {
                 self.set_zoom_mode(zoom_mode, hub, context);
                 true
@@ -3354,11 +5413,19 @@ impl View for Reader {
                 });
                 true
             },
+            Event::Select(EntryId::AutoCropMargins(scheme)) => {
+                self.auto_crop_margins(scheme, hub);
+                true
+            },
+            Event::AutoCroppedMargins(ref cropping_margins) => {
+                self.set_cropping_margins(cropping_margins.as_ref().clone(), hub, context);
+                true
+            },
             Event::Select(EntryId::RemoveCroppings) => {
                 if let Some(r) = self.info.reader.as_mut() {
                     r.cropping_margins = None;
                 }
-                self.cache.clear();
+                self.clear_cache();
                 self.update(None, hub, context);
                 true
             },
@@ -3409,6 +5476,7 @@ impl View for Reader {
                 if let Some(ref mut r) = self.info.reader {
                     r.page_names.remove(&self.current_page);
                 }
+                self.update_bottom_bar(hub);
                 true
             },
             Event::Reseed => {
@@ -3451,20 +5519,33 @@ impl View for Reader {
             },
             _ => false,
         };
+        if self.current_page == prev_current_page {
+            return ret
+        }
+        let (current_page, pages_count) = self.saved_location();
         if let Some(ref mut r) = self.info.reader {
-            if self.current_page == prev_current_page {
-                return ret
-            }
-            r.current_page = self.current_page;
-            r.pages_count = self.pages_count;
+            r.current_page = current_page;
+            r.pages_count = pages_count;
             r.finished = self.finished;
 
-            if self.view_port.zoom_mode == ZoomMode::FitToPage {
-                r.zoom_mode = None;
-                r.top_offset = None;
-            } else {
-                r.zoom_mode = Some(self.view_port.zoom_mode);
-                r.top_offset = Some(self.view_port.top_offset);
+            // Reflowable documents keep the zoom mode of the fixed-layout document.
+            if !self.reflowable {
+                if self.view_port.zoom_mode == ZoomMode::FitToPage {
+                    r.zoom_mode = None;
+                    r.top_offset = None;
+                    r.left_offset = None;
+                    r.zoom_factor = None;
+                } else {
+                    r.zoom_mode = Some(self.view_port.zoom_mode);
+                    r.top_offset = Some(self.view_port.top_offset);
+                    if self.view_port.zoom_mode == ZoomMode::Custom {
+                        r.left_offset = Some(self.view_port.left_offset);
+                        r.zoom_factor = Some(self.view_port.zoom_factor);
+                    } else {
+                        r.left_offset = None;
+                        r.zoom_factor = None;
+                    }
+                }
             }
 
             r.rotation = Some(CURRENT_DEVICE.to_canonical(context.display.rotation));
@@ -3487,11 +5568,11 @@ impl View for Reader {
         ret
     }
 
-    fn render(&self, fb: &mut dyn Framebuffer, rect: Rectangle, _fonts: &mut Fonts) {
+    fn render(&self, fb: &mut dyn Framebuffer, rect: Rectangle, fonts: &mut Fonts) {
         fb.draw_rectangle(&rect, WHITE);
 
         for chunk in &self.chunks {
-            let Resource { ref pixmap, scale, .. } = self.cache[&chunk.location];
+            let Resource { ref pixmap, scale, .. } = self.cache[&self.cache_key(chunk.location)];
             let chunk_rect = chunk.frame - chunk.frame.min + chunk.position;
 
             if let Some(region_rect) = rect.intersection(&chunk_rect) {
@@ -3527,13 +5608,31 @@ impl View for Reader {
                 }
 
                 if let Some(annotations) = self.annotations.get(&chunk.location) {
+                    let thickness = scale_by_dpi(THICKNESS_MEDIUM, CURRENT_DEVICE.dpi) as i32;
                     for annot in annotations {
-                        let drift = if annot.note.is_empty() { HIGHLIGHT_DRIFT } else { ANNOTATION_DRIFT };
+                        let drift = highlight_drift(annot.style).saturating_add(if annot.note.is_empty() {
+                            0
+                        } else {
+                            ANNOTATION_DRIFT - HIGHLIGHT_DRIFT
+                        });
                         let [start, end] = annot.selection;
                         if let Some(text) = self.text.get(&chunk.location) {
                             let mut last_rect: Option<Rectangle> = None;
+                            let mut line_rect: Option<Rectangle> = None;
                             for word in text.iter().filter(|w| w.location >= start && w.location <= end) {
                                 let rect = (word.rect * scale).to_rect() - chunk.frame.min + chunk.position;
+                                match line_rect {
+                                    Some(ref mut lr) if rect.min.y < lr.max.y && lr.min.y < rect.max.y => lr.absorb(&rect),
+                                    _ => {
+                                        if let Some(lr) = line_rect.take() {
+                                            draw_decoration(fb, &lr, annot.style, thickness, &region_rect);
+                                        }
+                                        line_rect = Some(rect);
+                                    },
+                                }
+                                if drift == 0 {
+                                    continue;
+                                }
                                 if let Some(ref sel_rect) = rect.intersection(&region_rect) {
                                     fb.shift_region(sel_rect, drift);
                                 }
@@ -3553,6 +5652,9 @@ impl View for Reader {
                                 }
                                 last_rect = Some(rect);
                             }
+                            if let Some(lr) = line_rect {
+                                draw_decoration(fb, &lr, annot.style, thickness, &region_rect);
+                            }
                         }
                     }
                 }
@@ -3583,10 +5685,47 @@ impl View for Reader {
                         }
                     }
                 }
+
+                if self.ink.visible {
+                    for (key, origin) in self.ink_origins(chunk) {
+                        if let Some(strokes) = self.strokes().and_then(|ink| ink.get(&key)) {
+                            for stroke in strokes {
+                                draw_stroke(fb, stroke, origin, scale, &region_rect);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        if let Some(ps) = self.ink.stroke.as_ref() {
+            if let Some(clip) = rect.intersection(&ps.clip) {
+                draw_stroke(fb, &ps.stroke, ps.origin, ps.scale, &clip);
+            }
+        }
+
+        let note_boxes = self.note_boxes(fonts);
+
+        if !note_boxes.is_empty() {
+            let dpi = CURRENT_DEVICE.dpi;
+            let thickness = scale_by_dpi(THICKNESS_MEDIUM, dpi) as i32;
+            let font = font_from_style(fonts, &MARGIN_NOTE, dpi);
+            let padding = font.em() as i32 / 2;
+            let line_height = font.line_height();
+            let ascender = font.ascender();
+            for note_box in note_boxes.iter().filter(|nb| nb.rect.overlaps(&rect)) {
+                let bar_rect = rect![note_box.rect.min.x + padding / 2, note_box.rect.min.y,
+                                     note_box.rect.min.x + padding / 2 + thickness, note_box.rect.max.y];
+                fb.draw_rectangle(&bar_rect, BLACK);
+                for (i, plan) in note_box.lines.iter().enumerate() {
+                    let pt = pt!(note_box.rect.min.x + padding,
+                                 note_box.rect.min.y + i as i32 * line_height + ascender);
+                    font.render(fb, BLACK, plan, pt);
+                }
             }
         }
 
-        if self.info.reader.as_ref().map_or(false, |r| r.bookmarks.contains(&self.current_page)) {
+        if self.info.reader.as_ref().map_or(false, |r| r.bookmark(self.bookmark_location()).is_some()) {
             let dpi = CURRENT_DEVICE.dpi;
             let thickness = scale_by_dpi(3.0, dpi) as u16;
             let radius = mm_to_px(0.4, dpi) as i32 + thickness as i32;
@@ -3709,12 +5848,22 @@ impl View for Reader {
 
         self.rect = rect;
 
+        if self.locked {
+            hub.send(Event::Render(rect, UpdateMode::Full)).ok();
+            return;
+        }
+
+        self.two_pages = context.settings.reader.two_pages && rect.width() > rect.height();
+
+        let anchor = self.first_visible_offset();
+
         if self.reflowable {
             let font_size = self.info.reader.as_ref()
                                 .and_then(|r| r.font_size)
                                 .unwrap_or(context.settings.reader.font_size);
+            let width = if self.two_pages { rect.width() / 2 } else { text_width(rect.width(), self.margin_notes()) };
             let mut doc = self.doc.lock().unwrap();
-            doc.layout(rect.width(), rect.height(), font_size, CURRENT_DEVICE.dpi);
+            doc.layout(width, rect.height(), font_size, CURRENT_DEVICE.dpi);
             let current_page = self.current_page.min(doc.pages_count() - 1);
             if let Some(location) = doc.resolve_location(Location::Exact(current_page)) {
                 self.current_page = location;
@@ -3722,7 +5871,11 @@ impl View for Reader {
             self.text.clear();
         }
 
-        self.cache.clear();
+        if let Some(offset) = anchor {
+            self.scroll_to_offset(offset);
+        }
+
+        self.clear_cache();
         self.update(Some(UpdateMode::Full), hub, context);
     }
 
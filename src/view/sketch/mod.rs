@@ -8,7 +8,7 @@ use globset::Glob;
 use anyhow::Error;
 use crate::device::CURRENT_DEVICE;
 use crate::geom::{Point, Rectangle, CornerSpec};
-use crate::input::{DeviceEvent, FingerStatus};
+use crate::input::{DeviceEvent, FingerStatus, PenStatus, PenTool, PEN_SLOT};
 use crate::view::icon::Icon;
 use crate::view::notification::Notification;
 use crate::view::menu::{Menu, MenuKind};
@@ -19,22 +19,23 @@ use crate::framebuffer::{Framebuffer, UpdateMode, Pixmap};
 use crate::settings::{ImportSettings, Pen};
 use crate::helpers::IsHidden;
 use crate::font::Fonts;
-use crate::unit::scale_by_dpi;
+use crate::unit::{scale_by_dpi, mm_to_px};
 use crate::color::{BLACK, WHITE};
 use crate::app::Context;
 
 const FILENAME_PATTERN: &str = "sketch-%Y%m%d_%H%M%S.png";
 // https://oeis.org/A000041
 const PEN_SIZES: [i32; 12] = [1, 2, 3, 5, 7, 11, 15, 22, 30, 42, 56, 77];
+pub const ERASER_RADIUS_MM: f32 = 2.0;
 
-struct TouchState {
-    pt: Point,
-    time: f64,
-    radius: f32,
+pub struct TouchState {
+    pub pt: Point,
+    pub time: f64,
+    pub radius: f32,
 }
 
 impl TouchState {
-    fn new(pt: Point, time: f64, radius: f32) -> TouchState {
+    pub fn new(pt: Point, time: f64, radius: f32) -> TouchState {
         TouchState { pt, time, radius }
     }
 }
@@ -105,6 +106,9 @@ impl Sketch {
                 EntryKind::CheckBox("Dynamic".to_string(),
                                     EntryId::TogglePenDynamism,
                                     self.pen.dynamic),
+                EntryKind::CheckBox("Pressure".to_string(),
+                                    EntryId::TogglePenPressure,
+                                    self.pen.pressure),
                 EntryKind::Separator,
             ];
 
@@ -174,6 +178,15 @@ impl Sketch {
         Ok(())
     }
 
+    // Returns the radius of the stylus when it touches the screen.
+    fn stylus_radius(&self, tool: PenTool, pressure: f32) -> f32 {
+        match tool {
+            PenTool::Eraser => mm_to_px(ERASER_RADIUS_MM, CURRENT_DEVICE.dpi),
+            PenTool::Pen if self.pen.pressure => pressure_radius(pressure, &self.pen),
+            PenTool::Pen => self.pen.size as f32 / 2.0,
+        }
+    }
+
     fn quit(&self, context: &mut Context) {
         let import_settings = ImportSettings {
             allowed_kinds: ["png".to_string()].iter().cloned().collect(),
@@ -183,9 +196,11 @@ impl Sketch {
     }
 }
 
+// Returns the radii of the pen at both ends of the segment that goes from the
+// last touch to the given position: the faster the pen, the thicker the line.
 #[inline]
-fn draw_segment(pixmap: &mut Pixmap, ts: &mut TouchState, position: Point, time: f64, pen: &Pen, fb_rect: &Rectangle, hub: &Hub) {
-    let (start_radius, end_radius) = if pen.dynamic {
+pub fn segment_radii(ts: &TouchState, position: Point, time: f64, pen: &Pen) -> (f32, f32) {
+    if pen.dynamic {
         if time > ts.time {
             let d = vec2!((position.x - ts.pt.x) as f32,
                           (position.y - ts.pt.y) as f32).length();
@@ -199,13 +214,24 @@ fn draw_segment(pixmap: &mut Pixmap, ts: &mut TouchState, position: Point, time:
     } else {
         let radius = pen.size as f32 / 2.0;
         (radius, radius)
-    };
+    }
+}
+
+// The harder the stylus is pressed, the thicker the line.
+#[inline]
+fn pressure_radius(pressure: f32, pen: &Pen) -> f32 {
+    pen.size as f32 / 2.0 * (0.5 + 1.5 * pressure.max(0.0).min(1.0))
+}
+
+#[inline]
+fn draw_segment(pixmap: &mut Pixmap, ts: &mut TouchState, position: Point, time: f64, radii: (f32, f32), color: u8, fb_rect: &Rectangle, hub: &Hub) {
+    let (start_radius, end_radius) = radii;
 
     let rect = Rectangle::from_segment(ts.pt, position,
                                        start_radius.ceil() as i32,
                                        end_radius.ceil() as i32);
 
-    pixmap.draw_segment(ts.pt, position, start_radius, end_radius, pen.color);
+    pixmap.draw_segment(ts.pt, position, start_radius, end_radius, color);
 
     if let Some(render_rect) = rect.intersection(fb_rect) {
         hub.send(Event::RenderNoWaitRegion(render_rect, UpdateMode::FastMono)).ok();
@@ -219,9 +245,12 @@ fn draw_segment(pixmap: &mut Pixmap, ts: &mut TouchState, position: Point, time:
 impl View for Sketch {
     fn handle_event(&mut self, evt: &Event, hub: &Hub, _bus: &mut Bus, context: &mut Context) -> bool {
         match *evt {
+            // The stylus is handled through its own events.
+            Event::Device(DeviceEvent::Finger { id: PEN_SLOT, .. }) => true,
             Event::Device(DeviceEvent::Finger { status: FingerStatus::Motion, id, position, time }) => {
                 if let Some(ts) = self.fingers.get_mut(&id) {
-                    draw_segment(&mut self.pixmap, ts, position, time, &self.pen, &self.rect, hub);
+                    let radii = segment_radii(ts, position, time, &self.pen);
+                    draw_segment(&mut self.pixmap, ts, position, time, radii, self.pen.color, &self.rect, hub);
                 }
                 true
             },
@@ -232,11 +261,39 @@ impl View for Sketch {
             },
             Event::Device(DeviceEvent::Finger { status: FingerStatus::Up, id, position, time }) => {
                 if let Some(ts) = self.fingers.get_mut(&id) {
-                    draw_segment(&mut self.pixmap, ts, position, time, &self.pen, &self.rect, hub);
+                    let radii = segment_radii(ts, position, time, &self.pen);
+                    draw_segment(&mut self.pixmap, ts, position, time, radii, self.pen.color, &self.rect, hub);
                 }
                 self.fingers.remove(&id);
                 true
             },
+            Event::Device(DeviceEvent::Pen { status: PenStatus::Down, tool, position, time, pressure, .. }) => {
+                let radius = self.stylus_radius(tool, pressure);
+                self.fingers.insert(PEN_SLOT, TouchState::new(position, time, radius));
+                true
+            },
+            Event::Device(DeviceEvent::Pen { status, tool, position, time, pressure, .. })
+                    if status == PenStatus::Motion || status == PenStatus::Up => {
+                let radius = self.stylus_radius(tool, pressure);
+                let color = if tool == PenTool::Eraser { WHITE } else { self.pen.color };
+                let pen = &self.pen;
+                if let Some(ts) = self.fingers.get_mut(&PEN_SLOT) {
+                    let radii = if tool == PenTool::Pen && !pen.pressure {
+                        segment_radii(ts, position, time, pen)
+                    } else {
+                        (ts.radius, radius)
+                    };
+                    draw_segment(&mut self.pixmap, ts, position, time, radii, color, &self.rect, hub);
+                }
+                if status == PenStatus::Up {
+                    self.fingers.remove(&PEN_SLOT);
+                }
+                true
+            },
+            Event::Device(DeviceEvent::Pen { status: PenStatus::Leave, .. }) => {
+                self.fingers.remove(&PEN_SLOT);
+                true
+            },
             Event::ToggleNear(ViewId::TitleMenu, rect) => {
                 self.toggle_title_menu(rect, None, hub, context);
                 true
@@ -253,6 +310,10 @@ impl View for Sketch {
                 self.pen.dynamic = !self.pen.dynamic;
                 true
             },
+            Event::Select(EntryId::TogglePenPressure) => {
+                self.pen.pressure = !self.pen.pressure;
+                true
+            },
             Event::Select(EntryId::Load(ref name)) => {
                 if let Err(e) = self.load(name) {
                     let msg = format!("Couldn't load sketch: {}).", e);